@@ -0,0 +1,367 @@
+//! Password-based encryption of secret keys, SLIP-0010 derivation and signing.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac_array;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+#[cfg(feature = "trace-crypto")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use zeroize::Zeroizing;
+
+pub const SALT_LENGTH: usize = 32;
+pub const NONCE_LENGTH: usize = 12;
+pub const KEY_LENGTH: usize = 32;
+pub const ITERATIONS: u32 = 100_000;
+// Argon2id defaults follow the OWASP recommendation (19 MiB, 2 passes, 1 lane)
+pub const ARGON2_M_COST: u32 = 19_456;
+pub const ARGON2_T_COST: u32 = 2;
+pub const ARGON2_P_COST: u32 = 1;
+// SLIP-0010 path used by Solana/Phantom-style ed25519 wallets
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/501'/0'/0'";
+const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+// Set by --trace-crypto to dump intermediate crypto values. Only builds with the non-default
+// `trace-crypto` feature have it, so a shipped binary can't be made to print its keys
+#[cfg(feature = "trace-crypto")]
+static TRACE_CRYPTO: AtomicBool = AtomicBool::new(false);
+
+/// Dump intermediate crypto values (keys included!) to stderr. Debugging only.
+#[cfg(feature = "trace-crypto")]
+pub fn set_trace_crypto(enabled: bool) {
+    TRACE_CRYPTO.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(feature = "trace-crypto")]
+fn trace_crypto(label: &str, bytes: &[u8]) {
+    if TRACE_CRYPTO.load(Ordering::Relaxed) {
+        eprintln!("[CRYPTO TRACE] {}: {}", label, hex::encode(bytes));
+    }
+}
+
+#[cfg(not(feature = "trace-crypto"))]
+fn trace_crypto(_label: &str, _bytes: &[u8]) {}
+
+/// Key derivation parameters stored alongside each encrypted secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum KdfParams {
+    Pbkdf2 { iterations: u32 },
+    Argon2id { m_cost: u32, t_cost: u32, p_cost: u32 },
+}
+
+impl KdfParams {
+    /// PBKDF2-HMAC-SHA256 with the default iteration count
+    pub fn pbkdf2() -> Self {
+        KdfParams::Pbkdf2 {
+            iterations: ITERATIONS,
+        }
+    }
+
+    /// A single PBKDF2 iteration, for secrets encrypted with an empty password. No KDF cost
+    /// makes guessing an empty password any slower, so it would only slow down signing
+    pub fn empty_password() -> Self {
+        KdfParams::Pbkdf2 { iterations: 1 }
+    }
+
+    /// Argon2id with the default cost parameters
+    pub fn argon2id() -> Self {
+        KdfParams::Argon2id {
+            m_cost: ARGON2_M_COST,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+        }
+    }
+
+    /// Fail if these parameters can't derive a key, e.g. zero iterations in a tampered store
+    pub fn check(&self) -> Result<()> {
+        match *self {
+            KdfParams::Pbkdf2 { iterations: 0 } => anyhow::bail!("PBKDF2 with 0 iterations"),
+            KdfParams::Argon2id { t_cost: 0, .. } => anyhow::bail!("Argon2id with 0 passes"),
+            // Argon2 needs at least 8 KiB of memory per lane
+            KdfParams::Argon2id { m_cost, p_cost, .. } if p_cost == 0 || m_cost < 8 * p_cost => {
+                anyhow::bail!("Argon2id with {} KiB for {} lane(s)", m_cost, p_cost)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Secrets written before the kdf field existed always used PBKDF2
+    pub(crate) fn legacy() -> Self {
+        Self::pbkdf2()
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        if cfg!(feature = "argon2") {
+            Self::argon2id()
+        } else {
+            Self::pbkdf2()
+        }
+    }
+}
+
+/// AES key derived from a password, together with the parameters that produced it
+pub struct KdfOutput {
+    key: [u8; KEY_LENGTH],
+    pub params: KdfParams,
+}
+
+impl KdfOutput {
+    pub fn as_bytes(&self) -> &[u8; KEY_LENGTH] {
+        &self.key
+    }
+}
+
+/// A secret encrypted with AES-256-GCM under a password-derived key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedSecretKey {
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub encrypted_data: Vec<u8>,
+    #[serde(default = "KdfParams::legacy")]
+    pub kdf: KdfParams,
+    /// Set when encrypted with an empty password (batch keys), so no prompt is needed to decrypt
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub empty_password: bool,
+}
+
+/// Derive the AES-256 key for `password` and `salt`.
+pub fn derive_key(password: &str, salt: &[u8], kdf: KdfParams) -> Result<KdfOutput> {
+    let key = match kdf {
+        KdfParams::Pbkdf2 { iterations } => {
+            pbkdf2_hmac_array::<Sha256, KEY_LENGTH>(password.as_bytes(), salt, iterations)
+        }
+        KdfParams::Argon2id {
+            m_cost,
+            t_cost,
+            p_cost,
+        } => derive_key_argon2id(password, salt, m_cost, t_cost, p_cost)?,
+    };
+    Ok(KdfOutput { key, params: kdf })
+}
+
+#[cfg(feature = "argon2")]
+fn derive_key_argon2id(
+    password: &str,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<[u8; KEY_LENGTH]> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LENGTH))
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2id parameters: {}", e))?;
+    let mut key = [0u8; KEY_LENGTH];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Argon2id key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+#[cfg(not(feature = "argon2"))]
+fn derive_key_argon2id(
+    _password: &str,
+    _salt: &[u8],
+    _m_cost: u32,
+    _t_cost: u32,
+    _p_cost: u32,
+) -> Result<[u8; KEY_LENGTH]> {
+    anyhow::bail!("This secret key uses Argon2id, which is not available in this build. Rebuild with `--features argon2`.")
+}
+
+/// Parse a path like `m/44'/501'/0'/0'` into hardened child indexes.
+pub fn parse_derivation_path(path: &str) -> Result<Vec<u32>> {
+    let mut components = path.split('/');
+    if components.next() != Some("m") {
+        anyhow::bail!("Invalid derivation path '{}': must start with 'm'", path);
+    }
+
+    components
+        .map(|component| {
+            // SLIP-0010 only defines hardened derivation for ed25519
+            let index = component
+                .strip_suffix('\'')
+                .or_else(|| component.strip_suffix('h'))
+                .ok_or_else(|| anyhow::anyhow!("Invalid derivation path '{}': component '{}' must be hardened (e.g. {}')", path, component, component))?;
+            let index: u32 = index
+                .parse()
+                .ok()
+                .filter(|index| *index < HARDENED_OFFSET)
+                .ok_or_else(|| anyhow::anyhow!("Invalid derivation path '{}': bad index '{}'", path, component))?;
+            Ok(index + HARDENED_OFFSET)
+        })
+        .collect()
+}
+
+/// Derive an ed25519 seed from a BIP39 seed along `path` using SLIP-0010.
+pub fn slip10_derive_ed25519(seed: &[u8], path: &str) -> Result<[u8; 32]> {
+    type HmacSha512 = Hmac<Sha512>;
+
+    let mut mac = <HmacSha512 as Mac>::new_from_slice(b"ed25519 seed").expect("HMAC accepts any key length");
+    mac.update(seed);
+    let master = mac.finalize().into_bytes();
+    let (mut key, mut chain_code) = (master[..32].to_vec(), master[32..].to_vec());
+
+    for index in parse_derivation_path(path)? {
+        let mut mac = <HmacSha512 as Mac>::new_from_slice(&chain_code).expect("HMAC accepts any key length");
+        mac.update(&[0u8]);
+        mac.update(&key);
+        mac.update(&index.to_be_bytes());
+        let child = mac.finalize().into_bytes();
+        key = child[..32].to_vec();
+        chain_code = child[32..].to_vec();
+    }
+
+    Ok(key.try_into().unwrap())
+}
+
+/// Encrypt `secret_key` under `password` with a fresh random salt and nonce.
+pub fn encrypt_secret_key(secret_key: &[u8], password: &str, kdf: KdfParams) -> Result<EncryptedSecretKey> {
+    let mut rng = OsRng;
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let derived = derive_key(password, &salt, kdf)?;
+    let key = Key::<Aes256Gcm>::from_slice(derived.as_bytes());
+    let cipher = Aes256Gcm::new(key);
+
+    let encrypted_data = cipher
+        .encrypt(Nonce::from_slice(&nonce), secret_key)
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+    trace_crypto("encrypt salt", &salt);
+    trace_crypto("encrypt nonce", &nonce);
+    trace_crypto("encrypt derived AES key", derived.as_bytes());
+    trace_crypto("encrypt plaintext secret key", secret_key);
+    trace_crypto("encrypt ciphertext", &encrypted_data);
+
+    Ok(EncryptedSecretKey {
+        salt: salt.to_vec(),
+        nonce: nonce.to_vec(),
+        encrypted_data,
+        kdf,
+        empty_password: password.is_empty(),
+    })
+}
+
+/// Decrypt a secret produced by [`encrypt_secret_key`]. Fails on a wrong password.
+///
+/// The plaintext is zeroed when the returned buffer is dropped.
+pub fn decrypt_secret_key(encrypted: &EncryptedSecretKey, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let derived = derive_key(password, &encrypted.salt, encrypted.kdf)?;
+    let key = Key::<Aes256Gcm>::from_slice(derived.as_bytes());
+    let cipher = Aes256Gcm::new(key);
+
+    trace_crypto("decrypt salt", &encrypted.salt);
+    trace_crypto("decrypt nonce", &encrypted.nonce);
+    trace_crypto("decrypt derived AES key", derived.as_bytes());
+    trace_crypto("decrypt ciphertext", &encrypted.encrypted_data);
+
+    let secret_key = cipher
+        .decrypt(
+            Nonce::from_slice(&encrypted.nonce),
+            encrypted.encrypted_data.as_slice(),
+        )
+        .map(Zeroizing::new)
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+
+    trace_crypto("decrypt plaintext secret key", &secret_key);
+    Ok(secret_key)
+}
+
+/// Sign `payload` with a raw 32-byte ed25519 secret key.
+pub fn sign_payload(secret_key: &[u8], payload: &[u8]) -> Result<Signature> {
+    let secret_key_array: [u8; 32] = secret_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
+
+    let signing_key = SigningKey::from_bytes(&secret_key_array);
+    let signature = signing_key.sign(payload);
+
+    trace_crypto("sign payload SHA-256", &Sha256::digest(payload));
+    trace_crypto("sign public key", signing_key.verifying_key().as_bytes());
+    trace_crypto("sign signature", &signature.to_bytes());
+
+    Ok(signature)
+}
+
+/// Check `signature` over `payload` with `verify_strict`, as the server does.
+///
+/// Malformed keys or signatures are errors; a well-formed signature that doesn't verify is `Ok(false)`.
+pub fn verify_signature(public_key: &[u8], signature: &[u8], payload: &[u8]) -> Result<bool> {
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid public key length: expected 32 bytes, got {}", public_key.len()))?;
+    let verifying_key = VerifyingKey::from_bytes(&public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
+
+    Ok(verifying_key.verify_strict(payload, &signature).is_ok())
+}
+
+/// Rough strength estimate for a new password, see [`password_strength`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PasswordStrength {
+    /// Estimated entropy in bits
+    pub bits: f64,
+    /// 0 (very weak) to 4 (very strong)
+    pub score: u8,
+}
+
+impl PasswordStrength {
+    pub fn label(&self) -> &'static str {
+        ["very weak", "weak", "fair", "strong", "very strong"][self.score as usize]
+    }
+}
+
+/// Estimate a password's entropy from its length and the character classes it uses.
+///
+/// Each class present (lowercase, uppercase, digits, anything else) adds to the alphabet an
+/// attacker has to search. Repeated characters count only up to twice the number of distinct
+/// ones, so `aaaaaaaaaaaa` doesn't pass for a long password. This is an upper bound: words and
+/// keyboard patterns make a password weaker than its score.
+pub fn password_strength(password: &str) -> PasswordStrength {
+    let chars: Vec<char> = password.chars().collect();
+    let has = |class: fn(&char) -> bool| chars.iter().any(class);
+    let alphabet = [
+        (has(char::is_ascii_lowercase), 26),
+        (has(char::is_ascii_uppercase), 26),
+        (has(char::is_ascii_digit), 10),
+        (has(|c: &char| !c.is_ascii_alphanumeric()), 33),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum::<u32>();
+
+    let distinct = chars.iter().collect::<std::collections::BTreeSet<_>>().len();
+    let length = chars.len().min(2 * distinct);
+    let bits = if alphabet == 0 { 0.0 } else { length as f64 * f64::from(alphabet).log2() };
+    let score = match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 128.0 => 3,
+        _ => 4,
+    };
+    PasswordStrength { bits, score }
+}
+
+/// SSH-style fingerprint of a public key, handy for eyeballing that two keys match
+pub fn public_key_fingerprint(public_key: &[u8]) -> String {
+    let digest = Sha256::digest(public_key);
+    format!("SHA256:{}", BASE64.encode(digest).trim_end_matches('='))
+}
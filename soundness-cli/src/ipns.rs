@@ -0,0 +1,180 @@
+//! IPNS records (the signed `IpnsEntry` of the IPNS spec) for ed25519 keys, so a key pair
+//! from the key store can publish under its own IPNS name without handing its secret to an
+//! IPFS node.
+
+use std::time::Duration;
+
+const SIGNATURE_V2_PREFIX: &[u8] = b"ipns-signature:";
+// The only validity type there is: the record expires at `validity`
+const VALIDITY_EOL: u64 = 0;
+const BASE36_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// The IPNS name (`k51...`) of an ed25519 public key: a base36 CIDv1 of its libp2p peer ID
+pub fn ipns_name(public_key: &[u8]) -> String {
+    // libp2p PublicKey protobuf: Type = Ed25519 (1), Data = the raw key
+    let mut libp2p_key = vec![0x08, 0x01, 0x12, public_key.len() as u8];
+    libp2p_key.extend_from_slice(public_key);
+    // CIDv1, libp2p-key codec, identity multihash of the protobuf (ed25519 keys are inlined)
+    let mut cid = vec![0x01, 0x72, 0x00, libp2p_key.len() as u8];
+    cid.extend_from_slice(&libp2p_key);
+    format!("k{}", base36(&cid))
+}
+
+fn base36(bytes: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 36) as u8;
+            carry /= 36;
+        }
+        while carry > 0 {
+            digits.push((carry % 36) as u8);
+            carry /= 36;
+        }
+    }
+    let leading_zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n(b'0', leading_zeros)
+        .chain(digits.iter().rev().map(|&digit| BASE36_ALPHABET[digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+/// The fields of an IPNS record, before it is signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpnsRecord {
+    /// Path the name resolves to, e.g. `/ipfs/<CID>`
+    pub value: String,
+    /// Resolvers prefer the highest sequence number, so every update needs a larger one
+    pub sequence: u64,
+    /// RFC 3339 expiry time
+    pub validity: String,
+    /// How long resolvers may cache the record, in nanoseconds
+    pub ttl_ns: u64,
+}
+
+impl IpnsRecord {
+    pub fn new(value: &str, sequence: u64, expires_at: u64, ttl: Duration) -> Self {
+        IpnsRecord {
+            value: value.to_string(),
+            sequence,
+            validity: rfc3339(expires_at),
+            ttl_ns: ttl.as_nanos() as u64,
+        }
+    }
+
+    /// The DAG-CBOR `data` field. Keys are in canonical order: shorter first, then bytewise
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = vec![0xa5];
+        cbor_text(&mut data, "TTL");
+        cbor_head(&mut data, 0, self.ttl_ns);
+        cbor_text(&mut data, "Value");
+        cbor_bytes(&mut data, self.value.as_bytes());
+        cbor_text(&mut data, "Sequence");
+        cbor_head(&mut data, 0, self.sequence);
+        cbor_text(&mut data, "Validity");
+        cbor_bytes(&mut data, self.validity.as_bytes());
+        cbor_text(&mut data, "ValidityType");
+        cbor_head(&mut data, 0, VALIDITY_EOL);
+        data
+    }
+
+    /// The bytes to sign with ed25519 for `signatureV2`
+    pub fn signed_data_v2(&self) -> Vec<u8> {
+        [SIGNATURE_V2_PREFIX, &self.data()].concat()
+    }
+
+    /// The bytes to sign for the legacy `signatureV1`, which older IPFS nodes still check
+    pub fn signed_data_v1(&self) -> Vec<u8> {
+        [self.value.as_bytes(), self.validity.as_bytes(), b"EOL"].concat()
+    }
+
+    /// The protobuf `IpnsEntry`, ready for `routing/put`
+    pub fn encode(&self, signature_v1: &[u8], signature_v2: &[u8]) -> Vec<u8> {
+        let mut entry = Vec::new();
+        protobuf_bytes(&mut entry, 1, self.value.as_bytes());
+        protobuf_bytes(&mut entry, 2, signature_v1);
+        protobuf_varint(&mut entry, 3, VALIDITY_EOL);
+        protobuf_bytes(&mut entry, 4, self.validity.as_bytes());
+        protobuf_varint(&mut entry, 5, self.sequence);
+        protobuf_varint(&mut entry, 6, self.ttl_ns);
+        protobuf_bytes(&mut entry, 8, signature_v2);
+        protobuf_bytes(&mut entry, 9, &self.data());
+        entry
+    }
+}
+
+fn cbor_head(buffer: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => buffer.push(major | value as u8),
+        24..=0xff => buffer.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            buffer.push(major | 25);
+            buffer.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buffer.push(major | 26);
+            buffer.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            buffer.push(major | 27);
+            buffer.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    cbor_head(buffer, 2, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn cbor_text(buffer: &mut Vec<u8>, text: &str) {
+    cbor_head(buffer, 3, text.len() as u64);
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+fn varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn protobuf_varint(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    varint(buffer, field << 3);
+    varint(buffer, value);
+}
+
+fn protobuf_bytes(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(buffer, (field << 3) | 2);
+    varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+/// RFC 3339 with nanoseconds in UTC, as IPFS nodes write and parse `validity`
+pub fn rfc3339(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let seconds = unix_secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000000000Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
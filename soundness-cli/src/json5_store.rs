@@ -0,0 +1,179 @@
+//! JSON5 key stores.
+//!
+//! The data goes through serde like a plain JSON store. Comments have no place in
+//! `KeyStore`, so on save they are read back from the previous file and written out
+//! again next to the same properties and key entries.
+
+use crate::key_store::KeyStore;
+use anyhow::Result;
+use json5format::{Comment, Comments, ParsedDocument, Value};
+use std::collections::HashMap;
+
+pub(crate) fn parse(contents: &str) -> Result<serde_json::Value> {
+    json5::from_str(contents).map_err(|e| anyhow::anyhow!("Invalid JSON5 key store: {}", e))
+}
+
+/// Comments attached to one property: whole lines before it, and one after it on the same line
+#[derive(Default)]
+struct Annotation {
+    before: Vec<String>,
+    end_of_line: Option<String>,
+}
+
+impl Annotation {
+    fn from_comments(comments: &Comments) -> Self {
+        Annotation {
+            before: comment_lines(comments.before_value()),
+            end_of_line: comments.end_of_line().clone(),
+        }
+    }
+}
+
+/// Every comment of an existing JSON5 key store, by the property it belongs to
+#[derive(Default)]
+struct Annotations {
+    header: Vec<String>,
+    fields: HashMap<String, Annotation>,
+    keys: HashMap<String, Annotation>,
+    keys_trailing: Vec<String>,
+    trailing: Vec<String>,
+    footer: Vec<String>,
+}
+
+impl Annotations {
+    fn parse(contents: &str) -> Result<Self> {
+        let document = ParsedDocument::from_str(contents, None)
+            .map_err(|e| anyhow::anyhow!("Invalid JSON5 key store: {}", e))?;
+        let mut annotations = Annotations {
+            footer: comment_lines(document.content.trailing_comments()),
+            ..Default::default()
+        };
+
+        let Some(root) = document.content.items().next() else {
+            return Ok(annotations);
+        };
+        let Value::Object { val: root, comments } = &*root else {
+            return Ok(annotations);
+        };
+        annotations.header = comment_lines(comments.before_value());
+        annotations.trailing = comment_lines(root.trailing_comments());
+
+        for property in root.properties() {
+            let name = unquote(property.name());
+            let value = property.value();
+            if let (true, Value::Object { val: keys, .. }) = (name == "keys", &*value) {
+                annotations.keys_trailing = comment_lines(keys.trailing_comments());
+                for key in keys.properties() {
+                    let key_annotation = Annotation::from_comments(key.value().comments());
+                    annotations.keys.insert(unquote(key.name()), key_annotation);
+                }
+            }
+            annotations.fields.insert(name, Annotation::from_comments(value.comments()));
+        }
+        Ok(annotations)
+    }
+}
+
+// Property names keep their quotes in json5format
+fn unquote(name: &str) -> String {
+    let quoted = name.len() >= 2
+        && (name.starts_with('"') && name.ends_with('"') || name.starts_with('\'') && name.ends_with('\''));
+    if quoted {
+        name[1..name.len() - 1].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn comment_lines(comments: &[Comment]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for comment in comments {
+        match comment {
+            Comment::Line(text) => lines.push(format!("//{}", text)),
+            Comment::Block { lines: block, .. } => {
+                let last = block.len().saturating_sub(1);
+                for (i, line) in block.iter().enumerate() {
+                    let opening = if i == 0 { "/*" } else { "" };
+                    let closing = if i == last { "*/" } else { "" };
+                    lines.push(format!("{}{}{}", opening, line, closing));
+                }
+            }
+            Comment::Break => lines.push(String::new()),
+        }
+    }
+    lines
+}
+
+fn push_lines(output: &mut String, lines: &[String], indent: usize) {
+    for line in lines {
+        if !line.is_empty() {
+            output.push_str(&" ".repeat(indent));
+            output.push_str(line);
+        }
+        output.push('\n');
+    }
+}
+
+// Finish a property with a trailing comma (fine in JSON5) and its end-of-line comment
+fn end_property(output: &mut String, annotation: Option<&Annotation>, indent: usize) {
+    output.push(',');
+    let mut lines = annotation
+        .and_then(|a| a.end_of_line.as_deref())
+        .into_iter()
+        .flat_map(str::lines);
+    if let Some(first) = lines.next() {
+        output.push_str(&format!(" //{}", first));
+    }
+    output.push('\n');
+    for line in lines {
+        output.push_str(&format!("{}//{}\n", " ".repeat(indent), line));
+    }
+}
+
+fn pretty(value: &serde_json::Value, indent: usize) -> Result<String> {
+    let text = serde_json::to_string_pretty(value)?;
+    Ok(text.replace('\n', &format!("\n{}", " ".repeat(indent))))
+}
+
+/// Render `key_store` as JSON5, keeping the comments of `previous`, the file being replaced
+pub(crate) fn render(key_store: &KeyStore, previous: Option<&str>) -> Result<String> {
+    let annotations = previous
+        .and_then(|previous| Annotations::parse(previous).ok())
+        .unwrap_or_default();
+    let root = serde_json::to_value(key_store)?;
+
+    let mut output = String::new();
+    push_lines(&mut output, &annotations.header, 0);
+    output.push_str("{\n");
+    for (field, value) in root.as_object().into_iter().flatten() {
+        let annotation = annotations.fields.get(field);
+        if let Some(annotation) = annotation {
+            push_lines(&mut output, &annotation.before, 2);
+        }
+        output.push_str(&format!("  {}: ", serde_json::to_string(field)?));
+
+        match value.as_object().filter(|_| field == "keys") {
+            Some(keys) => {
+                output.push_str("{\n");
+                let mut names: Vec<&String> = keys.keys().collect();
+                names.sort();
+                for name in names {
+                    let key_annotation = annotations.keys.get(name);
+                    if let Some(key_annotation) = key_annotation {
+                        push_lines(&mut output, &key_annotation.before, 4);
+                    }
+                    output.push_str(&format!("    {}: {}", serde_json::to_string(name)?, pretty(&keys[name], 4)?));
+                    end_property(&mut output, key_annotation, 4);
+                }
+                push_lines(&mut output, &annotations.keys_trailing, 4);
+                output.push_str("  }");
+            }
+            None => output.push_str(&pretty(value, 2)?),
+        }
+        end_property(&mut output, annotation, 2);
+    }
+    push_lines(&mut output, &annotations.trailing, 2);
+    output.push_str("}\n");
+    push_lines(&mut output, &annotations.footer, 0);
+    Ok(output)
+}
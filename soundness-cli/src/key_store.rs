@@ -0,0 +1,819 @@
+//! On-disk key store format and atomic load/save.
+
+use crate::crypto::{
+    decrypt_secret_key, encrypt_secret_key, public_key_fingerprint, EncryptedSecretKey, KdfParams, NONCE_LENGTH,
+    SALT_LENGTH,
+};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A named ed25519 key pair as stored in the key store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyPair {
+    pub public_key: Vec<u8>,
+    pub public_key_string: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted_secret_key: Option<EncryptedSecretKey>,
+    /// Mnemonic entropy for HD-derived keys, whose signing secret can't be turned back into the mnemonic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_mnemonic: Option<EncryptedSecretKey>,
+    #[serde(default, skip_serializing_if = "KeyMetadata::is_empty")]
+    pub metadata: KeyMetadata,
+    /// Whether the HD seed was derived with a BIP39 passphrase, which the mnemonic alone can't restore
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_bip39_passphrase: bool,
+    /// Registry URLs this public key was already submitted to by `sync-public-keys`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registered_with: Vec<String>,
+}
+
+impl KeyPair {
+    /// Record that this entry changed, so incremental backups pick it up
+    pub fn touch(&mut self) {
+        self.metadata.modified_at = Some(unix_timestamp());
+    }
+
+    /// Whether the entry was created or modified at or after `since`. Entries written
+    /// before timestamps were recorded can't tell, so they always count as changed.
+    pub fn changed_since(&self, since: u64) -> bool {
+        self.metadata
+            .created_at
+            .max(self.metadata.modified_at)
+            .is_none_or(|changed_at| changed_at >= since)
+    }
+
+    /// Make sure `secret_key` really belongs to the stored public key. A hand-edited or
+    /// corrupted store could otherwise sign with a key whose advertised public key is wrong.
+    pub fn check_secret_key(&self, secret_key: &[u8]) -> Result<()> {
+        let secret_key: [u8; 32] = secret_key
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
+        let derived = SigningKey::from_bytes(&secret_key).verifying_key();
+        if derived.as_bytes().as_slice() != self.public_key.as_slice() {
+            anyhow::bail!(
+                "stored public key {} does not match the key derived from the secret ({})",
+                public_key_fingerprint(&self.public_key),
+                public_key_fingerprint(derived.as_bytes())
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that need no password: the public key must be a valid ed25519 point, the KDF
+    /// parameters must be able to derive a key, and each ciphertext must be as long as the
+    /// secret it holds plus the AES-GCM tag. Returns one message per problem found.
+    pub fn check_structure(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        // A wrong length is already reported by validate_key_store
+        if let Ok(public_key) = <[u8; 32]>::try_from(self.public_key.as_slice()) {
+            if VerifyingKey::from_bytes(&public_key).is_err() {
+                problems.push("public_key is not a valid ed25519 point".to_string());
+            }
+        }
+        let secrets: [(&str, &Option<EncryptedSecretKey>, &[usize]); 2] = [
+            ("encrypted_secret_key", &self.encrypted_secret_key, &[32]),
+            // BIP39 entropy of 12 to 24 words
+            ("encrypted_mnemonic", &self.encrypted_mnemonic, &[16, 20, 24, 28, 32]),
+        ];
+        for (field, encrypted, secret_lengths) in secrets {
+            let Some(encrypted) = encrypted else { continue };
+            if let Err(e) = encrypted.kdf.check() {
+                problems.push(format!("{}.kdf is unusable: {}", field, e));
+            }
+            let length = encrypted.encrypted_data.len();
+            if length > 0 && !secret_lengths.iter().any(|secret| secret + GCM_TAG_LENGTH == length) {
+                let expected: Vec<String> = secret_lengths.iter().map(|secret| (secret + GCM_TAG_LENGTH).to_string()).collect();
+                problems.push(format!(
+                    "{}.encrypted_data is {} bytes, expected {}",
+                    field,
+                    length,
+                    expected.join(" or ")
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Whether the access list lets this key pair be used for `operation`. Key pairs without
+    /// one allow everything.
+    pub fn allows(&self, operation: KeyOperation) -> bool {
+        self.metadata.acl.as_ref().is_none_or(|acl| acl.contains(&operation))
+    }
+
+    /// Like [`Self::allows`], with an error naming the key pair and what it does allow
+    pub fn check_access(&self, name: &str, operation: KeyOperation) -> Result<()> {
+        if self.allows(operation) {
+            return Ok(());
+        }
+        let allowed: Vec<&str> = self.metadata.acl.iter().flatten().map(|operation| operation.name()).collect();
+        anyhow::bail!(
+            "Key pair '{}' may not be used to {}, its access list allows: {}",
+            name,
+            operation.name(),
+            if allowed.is_empty() { "nothing".to_string() } else { allowed.join(", ") }
+        )
+    }
+
+    /// Decrypt the stored secret with `password` and run [`Self::check_secret_key`] on it.
+    /// Called before a new entry is saved, so a secret that doesn't round-trip is never persisted.
+    pub fn check_encrypted_secret(&self, password: &str) -> Result<()> {
+        let encrypted = self
+            .encrypted_secret_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no encrypted secret key to check"))?;
+        let secret_key = decrypt_secret_key(encrypted, password)
+            .map_err(|e| anyhow::anyhow!("the encrypted secret key does not decrypt: {}", e))?;
+        self.check_secret_key(&secret_key)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hd_standard: Option<HdStandard>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
+    /// Unix time the key pair was generated or imported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// Unix time the entry was last re-encrypted or registered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<u64>,
+    /// Unix time `rotate-key` replaced this key pair. It is kept so old signatures can still be checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated_at: Option<u64>,
+    /// Name of the key pair that replaced this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated_to: Option<String>,
+    /// Operations this key pair may be used for, set with `set-acl`. Without one every operation is allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<Vec<KeyOperation>>,
+}
+
+impl KeyMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.hd_standard.is_none()
+            && self.derivation_path.is_none()
+            && self.created_at.is_none()
+            && self.modified_at.is_none()
+            && self.rotated_at.is_none()
+            && self.rotated_to.is_none()
+            && self.acl.is_none()
+    }
+}
+
+/// Operations a key pair's access list can allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyOperation {
+    /// Sign with the secret key, and change the password protecting it
+    Sign,
+    /// Export the secret as a mnemonic or PEM file
+    Export,
+    /// Show the key pair in `list-keys` and `key-info`, and export its public key
+    List,
+}
+
+impl KeyOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyOperation::Sign => "sign",
+            KeyOperation::Export => "export",
+            KeyOperation::List => "list",
+        }
+    }
+}
+
+/// Current Unix time in seconds, as recorded in `created_at` and `modified_at`
+pub fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Hierarchical deterministic derivation standards for ed25519 keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum HdStandard {
+    /// Ledger-style BIP32-Ed25519 (Khovratovich/Law)
+    Bip32Ed25519,
+    /// SLIP-0010 as used by Trezor and MetaMask
+    Slip0010,
+}
+
+impl HdStandard {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HdStandard::Bip32Ed25519 => "bip32-ed25519",
+            HdStandard::Slip0010 => "slip0010",
+        }
+    }
+}
+
+/// Text encodings for public keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyEncoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+impl KeyEncoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            KeyEncoding::Base64 => BASE64.encode(bytes),
+            KeyEncoding::Hex => hex::encode(bytes),
+        }
+    }
+
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            KeyEncoding::Base64 => BASE64
+                .decode(text.trim())
+                .map_err(|e| anyhow::anyhow!("invalid base64: {}", e)),
+            KeyEncoding::Hex => {
+                hex::decode(text.trim()).map_err(|e| anyhow::anyhow!("invalid hex: {}", e))
+            }
+        }
+    }
+
+    /// Like `decode`, but base64 must be canonical, correctly padded standard base64 with no
+    /// surrounding whitespace. Errors point at the first offending character
+    pub fn decode_strict(self, text: &str) -> Result<Vec<u8>> {
+        if self == KeyEncoding::Hex {
+            return self.decode(text);
+        }
+
+        let padding = text.len() - text.trim_end_matches('=').len();
+        for (position, c) in text.char_indices() {
+            let valid = c.is_ascii_alphanumeric()
+                || c == '+'
+                || c == '/'
+                || (c == '=' && position >= text.len() - padding);
+            if !valid {
+                anyhow::bail!(
+                    "invalid base64: bad character {:?} at position {}",
+                    c,
+                    position
+                );
+            }
+        }
+        if !text.len().is_multiple_of(4) {
+            anyhow::bail!(
+                "invalid base64: length {} is not a multiple of 4",
+                text.len()
+            );
+        }
+        if padding > 2 {
+            anyhow::bail!(
+                "invalid base64: {} padding characters, at most 2 are allowed",
+                padding
+            );
+        }
+        BASE64
+            .decode(text)
+            .map_err(|e| anyhow::anyhow!("invalid base64: not canonical ({})", e))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyStore {
+    #[serde(serialize_with = "serialize_sorted")]
+    pub keys: HashMap<String, KeyPair>,
+    /// Encoding of every `public_key_string` in this store. Stores written before
+    /// this field existed are base64, and stay that way so older CLIs can read them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_encoding: Option<KeyEncoding>,
+}
+
+// HashMap order changes from run to run; sorting by name keeps saved stores stable and diffable
+fn serialize_sorted<S: serde::Serializer>(
+    keys: &HashMap<String, KeyPair>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    keys.iter().collect::<std::collections::BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl KeyStore {
+    /// Encode `public_key` for storing in `public_key_string`. A new, empty store adopts
+    /// `requested`; an existing store keeps the encoding its keys were written with.
+    pub fn encode_public_key(&mut self, public_key: &[u8], requested: KeyEncoding) -> String {
+        if self.display_encoding.is_none() && self.keys.is_empty() {
+            self.display_encoding = Some(requested);
+        }
+        self.display_encoding.unwrap_or_default().encode(public_key)
+    }
+
+    /// SHA-256 of the serialized store, used to notice when it changed underneath us
+    pub fn content_hash(&self) -> String {
+        let serialized = serde_json::to_string(self).unwrap_or_default();
+        format!("{:x}", Sha256::digest(serialized.as_bytes()))
+    }
+}
+
+// AES-GCM appends a 16-byte tag to the plaintext
+const GCM_TAG_LENGTH: usize = 16;
+
+/// One malformed field found by `validate_key_store`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyStoreProblem {
+    pub key_name: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for KeyStoreProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key_name, self.message)
+    }
+}
+
+/// Check every entry of a serialized key store and return all problems found, so a
+/// corrupted store can be diagnosed in one pass. Fails only if the file isn't a key store at all.
+pub fn validate_key_store(contents: &str) -> Result<Vec<KeyStoreProblem>> {
+    let store: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|e| anyhow::anyhow!("Key store is not valid JSON: {}", e))?;
+    let keys = store
+        .get("keys")
+        .and_then(|keys| keys.as_object())
+        .ok_or_else(|| anyhow::anyhow!("Key store has no \"keys\" object"))?;
+    let display_encoding: Option<KeyEncoding> = match store.get("display_encoding") {
+        Some(encoding) => serde_json::from_value(encoding.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid display_encoding: {}", e))?,
+        None => None,
+    };
+
+    let mut names: Vec<&String> = keys.keys().collect();
+    names.sort();
+
+    let problem = |key_name: &str, message: String| KeyStoreProblem {
+        key_name: key_name.to_string(),
+        message,
+    };
+    let mut problems = Vec::new();
+    for name in names {
+        let key_pair: KeyPair = match serde_json::from_value(keys[name].clone()) {
+            Ok(key_pair) => key_pair,
+            Err(e) => {
+                problems.push(problem(name, e.to_string()));
+                continue;
+            }
+        };
+
+        if key_pair.public_key.len() != 32 {
+            problems.push(problem(
+                name,
+                format!(
+                    "public_key is {} bytes, expected 32",
+                    key_pair.public_key.len()
+                ),
+            ));
+        } else if key_pair.public_key_string
+            != display_encoding
+                .unwrap_or_default()
+                .encode(&key_pair.public_key)
+        {
+            problems.push(problem(
+                name,
+                "public_key_string does not match public_key".to_string(),
+            ));
+        }
+
+        let secrets = [
+            ("encrypted_secret_key", &key_pair.encrypted_secret_key),
+            ("encrypted_mnemonic", &key_pair.encrypted_mnemonic),
+        ];
+        for (field, encrypted) in secrets {
+            let Some(encrypted) = encrypted else { continue };
+            if encrypted.salt.len() != SALT_LENGTH {
+                problems.push(problem(
+                    name,
+                    format!(
+                        "{}.salt is {} bytes, expected {}",
+                        field,
+                        encrypted.salt.len(),
+                        SALT_LENGTH
+                    ),
+                ));
+            }
+            if encrypted.nonce.len() != NONCE_LENGTH {
+                problems.push(problem(
+                    name,
+                    format!(
+                        "{}.nonce is {} bytes, expected {}",
+                        field,
+                        encrypted.nonce.len(),
+                        NONCE_LENGTH
+                    ),
+                ));
+            }
+            if encrypted.encrypted_data.is_empty() {
+                problems.push(problem(name, format!("{}.encrypted_data is empty", field)));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// On-disk syntax of a key store file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyStoreFormat {
+    #[default]
+    Json,
+    /// JSON5, which allows comments. Comments are kept when the CLI rewrites the file
+    Json5,
+    /// One JSON object per key pair and line, so a single key can be read without parsing the rest
+    Ndjson,
+}
+
+impl KeyStoreFormat {
+    /// `.json5` files are JSON5, `.ndjson` and `.jsonl` files NDJSON, anything else is plain JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json5") => KeyStoreFormat::Json5,
+            Some(extension) if extension.eq_ignore_ascii_case("ndjson") || extension.eq_ignore_ascii_case("jsonl") => {
+                KeyStoreFormat::Ndjson
+            }
+            _ => KeyStoreFormat::Json,
+        }
+    }
+
+    /// File extension for key stores in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            KeyStoreFormat::Json => "json",
+            KeyStoreFormat::Json5 => "json5",
+            KeyStoreFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Convert key store `contents` in this format to plain JSON
+    pub fn to_json(self, contents: &str) -> Result<String> {
+        match self {
+            KeyStoreFormat::Json => Ok(contents.to_string()),
+            KeyStoreFormat::Json5 => Ok(serde_json::to_string(&parse_json5(contents)?)?),
+            KeyStoreFormat::Ndjson => Ok(serde_json::to_string(&parse_ndjson(contents)?)?),
+        }
+    }
+}
+
+// A key pair line of an NDJSON store. The line without a name, if any, holds the store-wide
+// fields such as `display_encoding`
+#[derive(Serialize, Deserialize)]
+struct NdjsonEntry<K> {
+    name: String,
+    #[serde(flatten)]
+    key_pair: K,
+}
+
+// Into the JSON layout of a key store, without deserializing the key pairs so that
+// validate_key_store still sees malformed fields
+fn parse_ndjson(contents: &str) -> Result<serde_json::Value> {
+    let mut store = serde_json::Map::new();
+    let mut keys = serde_json::Map::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut entry: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(line).with_context(|| format!("Invalid key store line {}", index + 1))?;
+        match entry.remove("name") {
+            // Later lines win, like later duplicate names in a JSON store
+            Some(serde_json::Value::String(name)) => {
+                keys.insert(name, serde_json::Value::Object(entry));
+            }
+            Some(_) => anyhow::bail!("Key store line {} has a name that is not a string", index + 1),
+            None => store.extend(entry),
+        }
+    }
+    store.insert("keys".to_string(), serde_json::Value::Object(keys));
+    Ok(serde_json::Value::Object(store))
+}
+
+fn render_ndjson(key_store: &KeyStore) -> Result<String> {
+    let mut contents = String::new();
+    if let Some(encoding) = key_store.display_encoding {
+        contents += &serde_json::to_string(&serde_json::json!({ "display_encoding": encoding }))?;
+        contents.push('\n');
+    }
+    for (name, key_pair) in key_store.keys.iter().collect::<std::collections::BTreeMap<_, _>>() {
+        contents += &serde_json::to_string(&NdjsonEntry {
+            name: name.clone(),
+            key_pair,
+        })?;
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+/// Look up one key pair in an NDJSON key store, deserializing only its line. Returns `Ok(None)`
+/// if there is no key pair by that name, and fails if the file isn't NDJSON at all.
+pub fn load_ndjson_key_pair(key_store_path: &Path, name: &str) -> Result<Option<KeyPair>> {
+    let file = match fs::File::open(key_store_path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        file => file.with_context(|| format!("Failed to read key store: {}", key_store_path.display()))?,
+    };
+    // Lines without this can't be the key pair. Any that have it are parsed to make sure
+    let needle = format!("\"name\":{}", serde_json::to_string(name)?);
+    let mut found = None;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read key store: {}", key_store_path.display()))?;
+        // A pretty-printed JSON store (or an encrypted one) starts with a line that isn't a whole object
+        if index == 0 && serde_json::from_str::<serde::de::IgnoredAny>(&line).is_err() {
+            anyhow::bail!("Key store {} is not NDJSON", key_store_path.display());
+        }
+        if !line.contains(&needle) {
+            continue;
+        }
+        let entry: NdjsonEntry<KeyPair> = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid key store line {} in {}", index + 1, key_store_path.display()))?;
+        // Keep going, a later line with the same name wins as on a full load
+        if entry.name == name {
+            found = Some(entry.key_pair);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(feature = "json5")]
+fn parse_json5(contents: &str) -> Result<serde_json::Value> {
+    crate::json5_store::parse(contents)
+}
+
+#[cfg(not(feature = "json5"))]
+fn parse_json5(_contents: &str) -> Result<serde_json::Value> {
+    anyhow::bail!("JSON5 key stores are not available in this build. Rebuild with `--features json5`.")
+}
+
+#[cfg(feature = "json5")]
+fn render_json5(key_store: &KeyStore, previous: Option<&str>) -> Result<String> {
+    crate::json5_store::render(key_store, previous)
+}
+
+#[cfg(not(feature = "json5"))]
+fn render_json5(_key_store: &KeyStore, _previous: Option<&str>) -> Result<String> {
+    anyhow::bail!("JSON5 key stores are not available in this build. Rebuild with `--features json5`.")
+}
+
+/// A key store file encrypted as a whole, so not even names and public keys are visible.
+/// The serialized `KeyStore` is sealed with AES-256-GCM under a key derived from the store
+/// password; the secret keys inside keep their own passwords.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyStoreEnvelope {
+    pub version: u32,
+    /// Base64 KDF salt
+    pub salt: String,
+    /// Base64 AES-GCM nonce
+    pub nonce: String,
+    /// Base64 encrypted key store JSON
+    pub ciphertext: String,
+    pub kdf: KdfParams,
+}
+
+impl KeyStoreEnvelope {
+    pub const VERSION: u32 = 1;
+
+    /// Encrypt the key store JSON `plaintext` under `password`
+    pub fn seal(plaintext: &str, password: &str) -> Result<Self> {
+        let encrypted = encrypt_secret_key(plaintext.as_bytes(), password, KdfParams::default())?;
+        Ok(KeyStoreEnvelope {
+            version: Self::VERSION,
+            salt: BASE64.encode(&encrypted.salt),
+            nonce: BASE64.encode(&encrypted.nonce),
+            ciphertext: BASE64.encode(&encrypted.encrypted_data),
+            kdf: encrypted.kdf,
+        })
+    }
+
+    /// Decrypt the key store JSON
+    pub fn open(&self, password: &str) -> Result<String> {
+        if self.version != Self::VERSION {
+            anyhow::bail!("Unsupported encrypted key store version {}", self.version);
+        }
+        let decode = |field: &str, value: &str| {
+            BASE64
+                .decode(value)
+                .map_err(|e| anyhow::anyhow!("Invalid {} in encrypted key store: {}", field, e))
+        };
+        let encrypted = EncryptedSecretKey {
+            salt: decode("salt", &self.salt)?,
+            nonce: decode("nonce", &self.nonce)?,
+            encrypted_data: decode("ciphertext", &self.ciphertext)?,
+            kdf: self.kdf,
+            empty_password: password.is_empty(),
+        };
+        let plaintext = decrypt_secret_key(&encrypted, password)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt the key store, is the store password right?"))?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| anyhow::anyhow!("Decrypted key store is not valid UTF-8"))
+    }
+
+    /// The envelope in key store file `contents`, or `None` for a plain key store
+    pub fn parse(contents: &str) -> Option<Self> {
+        serde_json::from_str(contents).ok()
+    }
+}
+
+/// Whether the key store file at `key_store_path` is encrypted as a whole
+pub fn is_encrypted_key_store(key_store_path: &Path) -> Result<bool> {
+    if !key_store_path.exists() {
+        return Ok(false);
+    }
+    let contents = fs::read_to_string(key_store_path)
+        .with_context(|| format!("Failed to read key store: {}", key_store_path.display()))?;
+    Ok(KeyStoreEnvelope::parse(&contents).is_some())
+}
+
+/// Load a key store written by [`save_encrypted_key_store`]
+pub fn load_encrypted_key_store(key_store_path: &Path, password: &str) -> Result<KeyStore> {
+    let contents = fs::read_to_string(key_store_path)
+        .with_context(|| format!("Failed to read key store: {}", key_store_path.display()))?;
+    let envelope = KeyStoreEnvelope::parse(&contents)
+        .ok_or_else(|| anyhow::anyhow!("Key store {} is not encrypted", key_store_path.display()))?;
+    Ok(serde_json::from_str(&envelope.open(password)?)?)
+}
+
+/// Encrypt the whole key store under `password` and save it atomically
+pub fn save_encrypted_key_store(key_store: &KeyStore, key_store_path: &Path, password: &str) -> Result<()> {
+    let envelope = KeyStoreEnvelope::seal(&serde_json::to_string(key_store)?, password)?;
+    write_atomically(key_store_path, &serde_json::to_string_pretty(&envelope)?)
+}
+
+/// First bytes of a file written by [`encrypt_key_store_backup`]
+pub const BACKUP_MAGIC: &[u8; 8] = b"SNDBKUP1";
+
+/// Encrypt the whole serialized key store under a backup password, for a portable backup file.
+///
+/// The layout is `BACKUP_MAGIC || salt || nonce || ciphertext`, with AES-256-GCM under a
+/// PBKDF2-HMAC-SHA256 key, so a backup can be restored without knowing how it was made.
+pub fn encrypt_key_store_backup(key_store: &KeyStore, password: &str) -> Result<Vec<u8>> {
+    let encrypted = encrypt_secret_key(&serde_json::to_vec(key_store)?, password, KdfParams::pbkdf2())?;
+    let mut backup = Vec::with_capacity(BACKUP_MAGIC.len() + SALT_LENGTH + NONCE_LENGTH + encrypted.encrypted_data.len());
+    backup.extend_from_slice(BACKUP_MAGIC);
+    backup.extend_from_slice(&encrypted.salt);
+    backup.extend_from_slice(&encrypted.nonce);
+    backup.extend_from_slice(&encrypted.encrypted_data);
+    Ok(backup)
+}
+
+/// Decrypt a backup written by [`encrypt_key_store_backup`]
+pub fn decrypt_key_store_backup(backup: &[u8], password: &str) -> Result<KeyStore> {
+    let Some(rest) = backup.strip_prefix(BACKUP_MAGIC) else {
+        anyhow::bail!("Not a key store backup (missing the {} header)", String::from_utf8_lossy(BACKUP_MAGIC));
+    };
+    if rest.len() < SALT_LENGTH + NONCE_LENGTH {
+        anyhow::bail!("Key store backup is truncated");
+    }
+    let (salt, rest) = rest.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let encrypted = EncryptedSecretKey {
+        salt: salt.to_vec(),
+        nonce: nonce.to_vec(),
+        encrypted_data: ciphertext.to_vec(),
+        kdf: KdfParams::pbkdf2(),
+        empty_password: password.is_empty(),
+    };
+    let plaintext = decrypt_secret_key(&encrypted, password)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt the backup, is the backup password right?"))?;
+    serde_json::from_slice(&plaintext).context("Decrypted backup is not a valid key store")
+}
+
+/// Load the key store at `key_store_path`, or an empty one if the file doesn't exist.
+pub fn load_key_store(key_store_path: &Path) -> Result<KeyStore> {
+    load_key_store_as(key_store_path, KeyStoreFormat::Json)
+}
+
+/// Like `load_key_store`, for a file in the given `format`.
+///
+/// A file that doesn't parse is copied to `<path>.corrupt` before the error is returned, so
+/// the bad store is kept even if something later writes over it.
+pub fn load_key_store_as(key_store_path: &Path, format: KeyStoreFormat) -> Result<KeyStore> {
+    if key_store_path.exists() {
+        let contents = fs::read_to_string(key_store_path)
+            .with_context(|| format!("Failed to read key store: {}", key_store_path.display()))?;
+        if KeyStoreEnvelope::parse(&contents).is_some() {
+            anyhow::bail!(
+                "Key store {} is encrypted, load it with its store password",
+                key_store_path.display()
+            );
+        }
+        parse_key_store(&contents, format).or_else(|e| {
+            // A healthy store read in the wrong format isn't corrupt, so it's not backed up
+            let formats = [KeyStoreFormat::Json, KeyStoreFormat::Json5, KeyStoreFormat::Ndjson];
+            if let Some(other) = formats.into_iter().find(|other| parse_key_store(&contents, *other).is_ok()) {
+                return Err(e.context(format!(
+                    "Key store {} is not valid {}, but it is valid {}. Pass --key-store-format {}",
+                    key_store_path.display(),
+                    format.extension(),
+                    other.extension(),
+                    other.extension()
+                )));
+            }
+            let backup_path = corrupt_backup_path(key_store_path);
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&backup_path)
+                .and_then(|mut backup| backup.write_all(contents.as_bytes()))
+                .with_context(|| format!("Failed to back up corrupt key store to {}", backup_path.display()))?;
+            Err(e.context(format!(
+                "Key store {} is corrupt, a copy was saved to {}",
+                key_store_path.display(),
+                backup_path.display()
+            )))
+        })
+    } else {
+        Ok(KeyStore::default())
+    }
+}
+
+/// Write the key store to `key_store_path`, creating parent directories as needed.
+///
+/// The store is written to a temp file next to it and renamed into place, so a crash
+/// mid-write never leaves a truncated key store behind.
+pub fn save_key_store(key_store: &KeyStore, key_store_path: &Path) -> Result<()> {
+    save_key_store_as(key_store, key_store_path, KeyStoreFormat::Json)
+}
+
+/// Like `save_key_store`, in the given `format`
+pub fn save_key_store_as(key_store: &KeyStore, key_store_path: &Path, format: KeyStoreFormat) -> Result<()> {
+    let contents = match format {
+        KeyStoreFormat::Json => serde_json::to_string_pretty(key_store)?,
+        KeyStoreFormat::Json5 => {
+            let previous = fs::read_to_string(key_store_path).ok();
+            render_json5(key_store, previous.as_deref())?
+        }
+        KeyStoreFormat::Ndjson => render_ndjson(key_store)?,
+    };
+    write_atomically(key_store_path, &contents)
+}
+
+/// Where [`load_key_store_as`] copies a key store it can't parse: `<path>.corrupt`, or if an
+/// earlier backup is already there, a new name with the current time so it is never overwritten
+pub fn corrupt_backup_path(key_store_path: &Path) -> PathBuf {
+    let with_suffix = |suffix: &str| {
+        let mut backup_path = key_store_path.as_os_str().to_owned();
+        backup_path.push(suffix);
+        PathBuf::from(backup_path)
+    };
+    let backup_path = with_suffix(".corrupt");
+    if !backup_path.exists() {
+        return backup_path;
+    }
+    let timestamp = unix_timestamp();
+    (0..)
+        .map(|n| match n {
+            0 => with_suffix(&format!(".corrupt.{}", timestamp)),
+            n => with_suffix(&format!(".corrupt.{}-{}", timestamp, n)),
+        })
+        .find(|backup_path| !backup_path.exists())
+        .unwrap()
+}
+
+fn parse_key_store(contents: &str, format: KeyStoreFormat) -> Result<KeyStore> {
+    match format {
+        KeyStoreFormat::Json => serde_json::from_str(contents).map_err(Into::into),
+        KeyStoreFormat::Json5 => parse_json5(contents).and_then(|value| Ok(serde_json::from_value(value)?)),
+        KeyStoreFormat::Ndjson => parse_ndjson(contents).and_then(|value| Ok(serde_json::from_value(value)?)),
+    }
+}
+
+// Write to a temporary file next to `key_store_path`, then rename it over the old store
+fn write_atomically(key_store_path: &Path, contents: &str) -> Result<()> {
+    // Allow pointing --key-store at a directory that doesn't exist yet
+    if let Some(parent) = key_store_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let mut tmp_path = key_store_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = fs::File::create(&tmp_path)
+        .with_context(|| format!("Failed to create file: {}", tmp_path.display()))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write to file: {}", tmp_path.display()))?;
+    file.sync_all()
+        .with_context(|| format!("Failed to sync file: {}", tmp_path.display()))?;
+    drop(file);
+
+    fs::rename(&tmp_path, key_store_path).with_context(|| {
+        format!(
+            "Failed to replace key store {}. The new key store was left at {} for manual recovery",
+            key_store_path.display(),
+            tmp_path.display()
+        )
+    })?;
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! Cryptographic core of the Soundness CLI.
+//!
+//! Everything needed to manage a key store and sign proof submissions without
+//! spawning the `soundness-cli` binary: password-based encryption of secret keys,
+//! SLIP-0010 HD derivation, the key store file format and ed25519 signing.
+
+pub mod crypto;
+pub mod ipns;
+#[cfg(feature = "json5")]
+mod json5_store;
+pub mod key_store;
+pub mod secret_source;
+pub mod sshsig;
+pub mod tee;
+
+pub use crypto::{
+    decrypt_secret_key, derive_key, encrypt_secret_key, password_strength, public_key_fingerprint, sign_payload,
+    verify_signature, EncryptedSecretKey, KdfOutput, KdfParams, PasswordStrength,
+};
+pub use key_store::{
+    corrupt_backup_path, decrypt_key_store_backup, encrypt_key_store_backup, is_encrypted_key_store,
+    load_encrypted_key_store, load_key_store, load_key_store_as, load_ndjson_key_pair, save_encrypted_key_store,
+    save_key_store, save_key_store_as, unix_timestamp, validate_key_store, HdStandard, KeyEncoding, KeyMetadata,
+    KeyOperation, KeyPair, KeyStore, KeyStoreEnvelope, KeyStoreFormat, KeyStoreProblem,
+};
+pub use secret_source::SecretSource;
+pub use zeroize::Zeroizing;
@@ -1,34 +1,89 @@
-use aes_gcm::{
-    aead::{Aead, KeyInit},
-    Aes256Gcm, Key, Nonce,
-};
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
-use bip39;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
 use ed25519_dalek::{Signer, SigningKey};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use once_cell::sync::Lazy;
-use pbkdf2::pbkdf2_hmac_array;
+use rayon::prelude::*;
 use rand::{rngs::OsRng, RngCore};
 use rpassword::prompt_password;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use soundness_layer::ipns::{ipns_name, rfc3339, IpnsRecord};
+use soundness_layer::sshsig;
+use soundness_layer::tee::{self, TeePlatform};
+use soundness_layer::crypto::{parse_derivation_path, slip10_derive_ed25519, DEFAULT_DERIVATION_PATH};
+use soundness_layer::{
+    decrypt_secret_key, encrypt_secret_key, public_key_fingerprint, EncryptedSecretKey, HdStandard,
+    KdfParams, KeyEncoding, KeyMetadata, KeyOperation, KeyPair, KeyStore, KeyStoreEnvelope, KeyStoreFormat,
+    SecretSource, Zeroizing,
+};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::io::Write; // Added for writing to file
-use std::path::PathBuf;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::str::FromStr;
 
-const SALT_LENGTH: usize = 32;
-const NONCE_LENGTH: usize = 12;
-const KEY_LENGTH: usize = 32;
-const ITERATIONS: u32 = 100_000;
+// A password and the hash of the key pair it decrypts. The password is zeroed when replaced
+type CachedPassword = (Zeroizing<String>, String);
+
+// Add a static variable to store the password and key pair hash
+static PASSWORD_CACHE: Lazy<Mutex<Option<CachedPassword>>> = Lazy::new(|| Mutex::new(None));
+
+// Set while `send --batch-file` runs, so rows that share a file read and encode it only once
+static BATCH_FILE_CACHE: Lazy<Mutex<Option<FileCache>>> = Lazy::new(|| Mutex::new(None));
+
+// Set by --json. The command's result fields are collected here and printed as one object at exit
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+static JSON_RESULT: Lazy<Mutex<serde_json::Map<String, serde_json::Value>>> = Lazy::new(Default::default);
+
+// Set by --quiet, or when stdout is not a terminal
+static QUIET_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+fn quiet_output() -> bool {
+    QUIET_OUTPUT.load(Ordering::Relaxed) || json_output()
+}
+
+/// Add a field to the `--json` result of the running command
+fn report_field(key: &str, value: impl Serialize) {
+    if !json_output() {
+        return;
+    }
+    if let Ok(value) = serde_json::to_value(value) {
+        JSON_RESULT.lock().unwrap().insert(key.to_string(), value);
+    }
+}
+
+// Shadows std's println! from here on, for progress and decoration that --quiet drops. With
+// --json stdout carries nothing but the final JSON object, so every human-readable line is dropped
+macro_rules! println {
+    ($($arg:tt)*) => {
+        if !quiet_output() {
+            std::println!($($arg)*);
+        }
+    };
+}
+
+// The command's output proper, such as a signature, a mnemonic or a listing. Kept with --quiet
+macro_rules! outputln {
+    ($($arg:tt)*) => {
+        if !json_output() {
+            std::println!($($arg)*);
+        }
+    };
+}
 
-// Add a static variable to store the password and key store hash
-static PASSWORD_CACHE: Lazy<Mutex<Option<(String, String)>>> = Lazy::new(|| Mutex::new(None));
+// A runaway batch-gen shouldn't leave a store that takes gigabytes of memory to open
+const DEFAULT_MAX_KEY_STORE_SIZE: u64 = 128 * 1024 * 1024;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -37,6 +92,134 @@ struct Args {
     #[arg(short, long, default_value = "http://localhost:3000")]
     endpoint: String,
 
+    /// Total time limit for each HTTP request, in seconds
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 30)]
+    timeout: u64,
+
+    /// Time limit for establishing a connection to the server, in seconds
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 10)]
+    connect_timeout: u64,
+
+    /// Path to the key store file (default: key_store.json)
+    #[arg(
+        long,
+        visible_alias = "keystore",
+        env = "SOUNDNESS_KEYSTORE",
+        global = true,
+        default_value = "key_store.json"
+    )]
+    key_store: PathBuf,
+
+    /// Use the key store of a named profile, kept in the user config directory
+    /// (~/.config/soundness/<NAME>/key_store.json on Linux)
+    #[arg(long, value_name = "NAME", env = "SOUNDNESS_PROFILE", global = true, conflicts_with = "key_store")]
+    profile: Option<String>,
+
+    /// Refuse to load a key store file larger than this, in bytes (default: 128 MiB)
+    #[arg(long, value_name = "BYTES", global = true, default_value_t = DEFAULT_MAX_KEY_STORE_SIZE)]
+    max_key_store_size: u64,
+
+    /// Load the key store whatever its size
+    #[arg(long, global = true, conflicts_with = "max_key_store_size")]
+    ignore_size_limit: bool,
+
+    /// Key store file syntax (default: json5 for `.json5` files, ndjson for `.ndjson` and `.jsonl`, json otherwise)
+    #[arg(long, value_enum, global = true)]
+    key_store_format: Option<KeyStoreFormat>,
+
+    /// Encrypt the whole key store file with a store password, hiding names and public keys too.
+    /// Once encrypted, the store stays encrypted on every save
+    #[arg(long, global = true)]
+    encrypt_store: bool,
+
+    /// Where to read the store password of an encrypted key store from: prompt, stdin, env:VAR, file:PATH or fd:N
+    #[arg(long, value_name = "SOURCE", global = true, default_value = "prompt")]
+    store_password_source: SecretSource,
+
+    /// Keep the key store in memory only; nothing is read from or written to disk
+    /// (with `--key-store -`, the final key store is printed to stdout instead)
+    #[arg(long, global = true)]
+    ephemeral: bool,
+
+    /// Seed the ephemeral key store from the JSON in this environment variable
+    #[arg(long, global = true, value_name = "VAR", requires = "ephemeral")]
+    key_store_env: Option<String>,
+
+    /// Canonical string layout to sign, for servers that expect an older or newer format
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        visible_alias = "canonical-string-template",
+        default_value = "v1"
+    )]
+    canonical_template: CanonicalTemplate,
+
+    /// Where to read key passwords from: prompt, stdin, env:VAR, file:PATH or fd:N
+    /// (default: $SOUNDNESS_PASSWORD if it is set, prompt otherwise)
+    #[arg(long, value_name = "SOURCE", global = true)]
+    password_source: Option<SecretSource>,
+
+    /// Read key passwords from this environment variable. Other processes of the same
+    /// user can read a process environment, and it may leak into logs or crash reports
+    #[arg(long, value_name = "VAR", global = true, conflicts_with_all = ["password_source", "password_file"])]
+    password_env: Option<String>,
+
+    /// Read key passwords from this file, minus a trailing newline. Keep it owner-only
+    /// (chmod 600) and out of backups and shared disks, since it holds the password in plain text
+    #[arg(long, value_name = "PATH", global = true, conflicts_with = "password_source")]
+    password_file: Option<PathBuf>,
+
+    /// Where import-key reads the mnemonic or raw secret from: prompt, stdin, env:VAR, file:PATH or fd:N
+    #[arg(long, value_name = "SOURCE", global = true, default_value = "stdin")]
+    mnemonic_source: SecretSource,
+
+    /// Encoding for displayed public keys and for `public_key_string` in new key stores
+    #[arg(long, value_enum, global = true, visible_alias = "key-format", default_value = "base64")]
+    encoding: KeyEncoding,
+
+    /// Print every intermediate crypto value, including secrets (debugging only, UNSAFE; needs the `trace-crypto` feature)
+    #[arg(long, global = true, hide = cfg!(not(feature = "trace-crypto")))]
+    trace_crypto: bool,
+
+    /// Reject user-supplied base64 unless it is canonical, correctly padded standard base64
+    #[arg(long, global = true)]
+    strict_base64: bool,
+
+    /// Refuse to sign with keys protected by an empty password, instead of only warning
+    #[arg(long, global = true)]
+    fail_on_empty_password: bool,
+
+    /// Answer yes to "are you sure" questions before destructive steps. Passwords are still asked for
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
+
+    /// Reject new key passwords weaker than --min-password-bits, instead of only warning
+    #[arg(long, global = true)]
+    strict_password: bool,
+
+    /// Estimated entropy below which a new key password counts as weak
+    #[arg(long, global = true, value_name = "BITS", default_value_t = DEFAULT_MIN_PASSWORD_BITS)]
+    min_password_bits: u32,
+
+    /// Print one JSON object with the command's result (or error) instead of human-readable output
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Hide progress bars and decorative output, printing only results and errors
+    /// (default: on when stdout is not a terminal; --quiet=false to turn off)
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "SOUNDNESS_QUIET",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    quiet: Option<bool>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -48,563 +231,6151 @@ enum Commands {
         /// Name for the key pair
         #[arg(short, long)]
         name: String,
+
+        /// Key derivation function used to protect the secret (default: argon2id when available)
+        #[arg(long, value_enum)]
+        kdf: Option<Kdf>,
+
+        /// Derive the key from the mnemonic seed using this HD standard
+        #[arg(long, value_enum)]
+        hd_standard: Option<HdStandard>,
+
+        /// HD derivation path, every component must be hardened (default: m/44'/501'/0'/0')
+        #[arg(long, requires = "hd_standard")]
+        derivation_path: Option<String>,
+
+        /// Prompt for a BIP39 passphrase ("25th word") mixed into the HD seed
+        #[arg(long, requires = "hd_standard")]
+        mnemonic_passphrase: bool,
+
+        /// Include the mnemonic in the --json result. Without it the result holds no secrets
+        #[arg(long)]
+        show_mnemonic: bool,
     },
     /// List all saved key pairs
     ListKeys,
+    /// List the profiles in the user config directory, for use with --profile
+    ListProfiles,
+    /// Show everything about one key pair: public key encodings, whether it can sign, and KDF details
+    KeyInfo {
+        /// Name of the key pair
+        name: String,
+    },
+    /// Print a single public key, e.g. to feed it into other tooling
+    ExportPublicKey {
+        /// Name of the key pair
+        #[arg(short, long)]
+        name: String,
+
+        /// Output format (default: the --encoding format)
+        #[arg(short, long, value_enum)]
+        format: Option<PublicKeyFormat>,
+
+        /// File to write the public key to (default: stdout)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Check the key store for malformed entries and report every problem found
+    ValidateKeyStore,
+    /// Look for corruption or tampering without using any key, with a status line per key pair
+    Check {
+        /// List the checks made on each key pair, not only the problems
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Also decrypt every secret with the key password (see --password-source) and make
+        /// sure it belongs to its public key. Keys with a different password are reported
+        #[arg(long)]
+        decrypt: bool,
+    },
+    /// Remove entries that fail validate-key-store, archiving them to a separate file first
+    HealKeyStore {
+        /// Remove every entry with at least one problem
+        #[arg(long)]
+        remove_corrupt: bool,
+
+        /// Only list the entries that would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Rewrite the key store in canonical form: pretty-printed, sorted, without duplicate entries
+    CompactKeyStore,
+    /// Write the key store in another format, e.g. NDJSON so commands load only the key they use
+    ConvertKeyStore {
+        /// Format to write
+        #[arg(long, value_enum)]
+        to: KeyStoreFormat,
+
+        /// Where to write it (default: the key store path with the new format's extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Copy the key store to backup_<timestamp>.json next to it
+    Backup {
+        /// Only back up key pairs created or modified since --since
+        #[arg(long, requires = "since")]
+        incremental: bool,
+
+        /// Unix timestamp of the previous backup, as printed by it
+        #[arg(long, value_name = "TIMESTAMP", requires = "incremental")]
+        since: Option<u64>,
+
+        /// Write a portable backup encrypted with a backup password to this file instead
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Where to read the backup password from (same syntax as --password-source)
+        #[arg(long, value_name = "SOURCE", default_value = "prompt", requires = "output")]
+        backup_password_source: SecretSource,
+    },
+    /// Restore the key store from a backup written by `backup --output`
+    Restore {
+        /// Encrypted backup file
+        input: PathBuf,
+
+        /// Where to read the backup password from (same syntax as --password-source)
+        #[arg(long, value_name = "SOURCE", default_value = "prompt")]
+        backup_password_source: SecretSource,
+
+        /// Replace a key store that already holds key pairs
+        #[arg(long, conflicts_with = "merge")]
+        force: bool,
+
+        /// Add the backed-up key pairs to the current key store instead of replacing it
+        #[arg(long)]
+        merge: bool,
+
+        /// With --merge, let backed-up key pairs replace current ones of the same name
+        #[arg(long, requires = "merge")]
+        overwrite: bool,
+    },
+    /// Partition the key store into shard_0.json ... shard_<N-1>.json, e.g. to spread batch keys over nodes
+    SplitKeyStore {
+        /// Number of shards. Each key pair goes to the shard given by a hash of its name
+        #[arg(long, value_name = "N")]
+        count: u32,
+
+        /// Directory to write the shards to
+        #[arg(long, value_name = "DIR")]
+        output_dir: PathBuf,
+    },
+    /// Add the key pairs from the shard_<N>.json files written by split-key-store to the key store
+    MergeKeyStores {
+        /// Directory holding the shards
+        #[arg(long, value_name = "DIR")]
+        input_dir: PathBuf,
+
+        /// Let key pairs from the shards replace current ones of the same name
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Write every stored public key, one per line, without buffering the whole list
+    ExportPublicKeys {
+        /// File to write the public keys to, or `-` for stdout
+        #[arg(short, long, value_name = "PATH", default_value = "-")]
+        output: PathBuf,
+    },
     /// Export mnemonic for a key pair
     ExportKey {
         /// Name of the key pair to export
         #[arg(short, long)]
         name: String,
+
+        /// Write the mnemonic as a PNG QR code to this file instead of printing it
+        #[arg(long, value_name = "PATH")]
+        qr_file: Option<PathBuf>,
+    },
+    /// Export a key pair as unencrypted PEM files (PKCS#8 private key, SPKI public key) for OpenSSL and other tools
+    ExportPem {
+        /// Name of the key pair to export
+        #[arg(short, long)]
+        name: String,
+
+        /// File for the private key, which must not exist yet; the public key goes next to it
+        /// as <NAME>.pub.pem (default: <name>.pem in the current directory)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Export the mnemonics of many key pairs to one file, as `<name>: <mnemonic>` lines
+    BatchExport {
+        /// File to write the mnemonics to. It must not exist yet
+        #[arg(short, long, value_name = "PATH")]
+        output_file: PathBuf,
+
+        /// Only export key pairs whose name starts with this prefix, e.g. `batch_key_`
+        #[arg(long, value_name = "PREFIX")]
+        filter: Option<String>,
     },
     /// Import a key pair from a mnemonic phrase
     ImportKey {
         /// Name for the imported key pair
         #[arg(short, long)]
         name: String,
+
+        /// Key derivation function used to protect the secret (default: argon2id when available)
+        #[arg(long, value_enum)]
+        kdf: Option<Kdf>,
+
+        /// Derive the key from the mnemonic seed using this HD standard
+        #[arg(long, value_enum)]
+        hd_standard: Option<HdStandard>,
+
+        /// HD derivation path, every component must be hardened (default: m/44'/501'/0'/0')
+        #[arg(long, requires = "hd_standard")]
+        derivation_path: Option<String>,
+
+        /// Prompt for a BIP39 passphrase ("25th word") mixed into the HD seed
+        #[arg(long, requires = "hd_standard")]
+        mnemonic_passphrase: bool,
+
+        /// What is read from stdin: a BIP39 mnemonic, or a raw 32/64-byte secret key in hex or base64
+        #[arg(long, value_enum, default_value = "mnemonic")]
+        format: ImportFormat,
+    },
+    /// Import an unencrypted 32-byte ed25519 secret key from a file
+    ImportRaw {
+        /// Name for the imported key pair
+        #[arg(short, long)]
+        name: String,
+
+        /// File holding the secret key as 32 raw bytes
+        #[arg(long, value_name = "PATH")]
+        key_file: PathBuf,
+
+        /// The file holds the secret key as hex text instead of raw bytes
+        #[arg(long)]
+        hex: bool,
+
+        /// Key derivation function used to protect the secret (default: argon2id when available)
+        #[arg(long, value_enum)]
+        kdf: Option<Kdf>,
+    },
+    /// Re-encrypt a key pair's secret with a new password
+    ChangePassword {
+        /// Name of the key pair
+        #[arg(short, long)]
+        name: String,
+
+        /// Where to read the new password from (same syntax as --password-source)
+        #[arg(long, value_name = "SOURCE", default_value = "prompt")]
+        new_password_source: SecretSource,
+    },
+    /// Rename a key pair without touching its secret
+    RenameKey {
+        /// Current name of the key pair
+        #[arg(short, long, visible_alias = "from")]
+        old_name: String,
+
+        /// New name for the key pair
+        #[arg(short, long, visible_alias = "to")]
+        new_name: String,
+    },
+    /// Restrict which operations a key pair can be used for (asks for its password)
+    SetAcl {
+        /// Name of the key pair
+        #[arg(short, long)]
+        name: String,
+
+        /// Operations to allow, comma separated
+        #[arg(long, value_enum, value_delimiter = ',', required_unless_present = "clear", conflicts_with = "clear")]
+        allow: Vec<KeyOperation>,
+
+        /// Remove the access list so every operation is allowed again
+        #[arg(long)]
+        clear: bool,
     },
     /// Generate multiple key pairs without passwords and output public keys
     BatchGen {
         /// Number of keys to generate
         #[arg(short, long, value_name = "COUNT")]
         count: u32,
-    },
-    /// Send a proof and ELF file to the server
-    Send {
-        /// Path to the proof file
-        #[arg(short, long)]
-        proof_file: PathBuf,
 
-        /// Path to the ELF file
-        #[arg(short = 'l', long)]
-        elf_file: PathBuf,
+        /// Prefix for generated key names
+        #[arg(long, value_name = "STR", default_value = "batch_key_")]
+        prefix: String,
 
-        /// Name of the key pair to use for signing
-        #[arg(short, long)]
-        key_name: String,
+        /// First index to try when naming keys (existing names are skipped)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        start: u32,
 
-        /// Proving system to use (default: sp1)
-        #[arg(short = 's', long, default_value = "sp1")]
-        proving_system: ProvingSystem,
-    },
-}
+        /// File to write the public keys to, or `-` for stdout
+        #[arg(short, long, value_name = "PATH", default_value = "public_keys.txt")]
+        output: PathBuf,
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
-enum ProvingSystem {
-    Sp1,
-    Circom,
-    Risc0,
-    Starknet,
-}
+        /// Append to the output file instead of overwriting it
+        #[arg(long, overrides_with = "no_append")]
+        append: bool,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct KeyPair {
-    public_key: Vec<u8>,
-    public_key_string: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    encrypted_secret_key: Option<EncryptedSecretKey>,
-}
+        /// Overwrite the output file (the default); the last of --append/--no-append wins
+        #[arg(long, overrides_with = "append")]
+        no_append: bool,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EncryptedSecretKey {
-    salt: Vec<u8>,
-    nonce: Vec<u8>,
-    encrypted_data: Vec<u8>,
-}
+        /// Write a JSON report of the run (no secrets) to this file
+        #[arg(long, value_name = "PATH")]
+        report_file: Option<PathBuf>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct KeyStore {
-    keys: HashMap<String, KeyPair>,
-}
+        /// Number of threads generating keys (default: number of CPU cores)
+        #[arg(long, value_name = "N")]
+        parallel: Option<usize>,
 
-fn derive_key(password: &str, salt: &[u8]) -> [u8; KEY_LENGTH] {
-    pbkdf2_hmac_array::<Sha256, KEY_LENGTH>(password.as_bytes(), salt, ITERATIONS)
-}
+        /// Print a JSON event per generated key to stdout (NDJSON) instead of a progress bar
+        #[arg(long)]
+        ndjson: bool,
 
-fn encrypt_secret_key(secret_key: &[u8], password: &str) -> Result<EncryptedSecretKey> {
-    let mut rng = OsRng;
-    let mut salt = [0u8; SALT_LENGTH];
-    let mut nonce = [0u8; NONCE_LENGTH];
-    rng.fill_bytes(&mut salt);
-    rng.fill_bytes(&mut nonce);
-
-    let key_bytes = derive_key(password, &salt);
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-    let cipher = Aes256Gcm::new(key);
-
-    let encrypted_data = cipher
-        .encrypt(Nonce::from_slice(&nonce), secret_key)
-        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
-
-    Ok(EncryptedSecretKey {
-        salt: salt.to_vec(),
-        nonce: nonce.to_vec(),
-        encrypted_data,
-    })
-}
+        /// Only update the key store and don't write the public keys to a file
+        #[arg(long, conflicts_with_all = ["output", "append"])]
+        skip_file_output: bool,
 
-fn decrypt_secret_key(encrypted: &EncryptedSecretKey, password: &str) -> Result<Vec<u8>> {
-    let key_bytes = derive_key(password, &encrypted.salt);
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-    let cipher = Aes256Gcm::new(key);
+        /// Write each public key to its own `<key_name>.pub` file in this directory instead
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "append", "skip_file_output"])]
+        output_dir: Option<PathBuf>,
+    },
+    /// Generate password-less key pairs, printing each public key to stdout as soon as it exists.
+    /// The key store is only saved once the whole batch is done.
+    KeygenBatchStream {
+        /// Number of keys to generate
+        #[arg(short, long, value_name = "COUNT")]
+        count: u32,
 
-    cipher
-        .decrypt(
-            Nonce::from_slice(&encrypted.nonce),
-            encrypted.encrypted_data.as_slice(),
-        )
-        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))
-}
+        /// Prefix for generated key names
+        #[arg(long, value_name = "STR", default_value = "batch_key_")]
+        prefix: String,
 
-fn create_progress_bar(message: &str) -> ProgressBar {
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .unwrap(),
-    );
-    pb.set_message(message.to_string());
-    pb.enable_steady_tick(Duration::from_millis(120));
-    pb
-}
+        /// First index to try when naming keys (existing names are skipped)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        start: u32,
 
-fn load_key_store() -> Result<KeyStore> {
-    let key_store_path = PathBuf::from("key_store.json");
-    if key_store_path.exists() {
-        let contents = fs::read_to_string(&key_store_path)?;
-        let key_store: KeyStore = serde_json::from_str(&contents)?;
-        Ok(key_store)
-    } else {
-        Ok(KeyStore {
-            keys: HashMap::new(),
-        })
-    }
-}
+        /// Number of threads generating keys (default: number of CPU cores)
+        #[arg(long, value_name = "N")]
+        parallel: Option<usize>,
+    },
+    /// Print the canonical string that would be signed for a proof submission
+    CanonicalString {
+        #[command(flatten)]
+        files: ProofFiles,
+    },
+    /// Sign an arbitrary file and output a detached signature
+    Sign {
+        /// Name of the key pair to use for signing
+        #[arg(short, long)]
+        key_name: String,
 
-fn save_key_store(key_store: &KeyStore) -> Result<()> {
-    let key_store_path = PathBuf::from("key_store.json");
-    let contents = serde_json::to_string_pretty(key_store)?;
-    fs::write(key_store_path, contents)?;
-    Ok(())
-}
+        /// File whose bytes are signed
+        #[arg(short, long)]
+        message_file: PathBuf,
 
-fn generate_key_pair(name: &str) -> Result<()> {
-    let mut key_store = load_key_store()?;
+        /// File to write the signature to (default: stdout)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Sign the SHA-256 of a file, such as a release artifact, and write a JSON detached signature
+    SignFile {
+        /// File to sign
+        input: PathBuf,
 
-    if key_store.keys.contains_key(name) {
-        anyhow::bail!("Key pair with name '{}' already exists", name);
-    }
+        /// Where to write the signature (default: <INPUT>.sig)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
 
-    // Generate a new key pair
-    let mut rng = OsRng;
-    let signing_key = SigningKey::generate(&mut rng);
-    let verifying_key = signing_key.verifying_key();
-    let public_key_bytes = verifying_key.to_bytes();
-    let public_key_string = BASE64.encode(&public_key_bytes);
+        /// Name of the key pair to use for signing
+        #[arg(short, long)]
+        key_name: String,
+    },
+    /// Check a file against a JSON detached signature written by sign-file
+    VerifyFile {
+        /// File to check
+        input: PathBuf,
 
-    // Generate mnemonic from secret key
-    let secret_key_bytes = signing_key.to_bytes();
-    let mnemonic = bip39::Mnemonic::from_entropy(&secret_key_bytes)
-        .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic: {}", e))?;
-    let mnemonic_string = mnemonic.to_string();
+        /// Signature file (default: <INPUT>.sig)
+        #[arg(long, value_name = "PATH")]
+        sig: Option<PathBuf>,
 
-    println!("\n📝 IMPORTANT: Save this mnemonic phrase securely for your testnet participation!");
-    println!("⚠️  WARNING: This is the only time you'll see this mnemonic! You'll need it to recover your secret key if the key store is lost!\n");
-    println!("{}", mnemonic_string);
+        /// Expected signer, in the --encoding format
+        #[arg(long)]
+        public_key: String,
+    },
+    /// Sign data in OpenSSH signature format for Git. Also accepts the `-Y sign -n git -f KEY FILE`
+    /// arguments Git passes to `gpg.ssh.program`
+    SignCommit {
+        /// Name of the key pair to sign with (default: the one matching -f)
+        #[arg(short, long)]
+        key_name: Option<String>,
 
-    // Get password for secret key encryption
-    let password = prompt_password("\nEnter password for secret key: ")
-        .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
-    let confirm_password = prompt_password("Confirm password: ")
-        .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
+        /// Read the data from stdin and print the signature to stdout
+        #[arg(long)]
+        stdin: bool,
 
-    if password != confirm_password {
-        anyhow::bail!("Passwords do not match");
-    }
+        /// ssh-keygen operation, only `sign` is supported
+        #[arg(short = 'Y', value_name = "OPERATION", hide = true)]
+        operation: Option<String>,
 
-    // Encrypt the secret key
-    let encrypted_secret = encrypt_secret_key(&secret_key_bytes, &password)?;
+        /// Signature namespace
+        #[arg(short = 'n', long, default_value = "git")]
+        namespace: String,
 
-    // Save the key pair
-    key_store.keys.insert(
-        name.to_string(),
-        KeyPair {
-            public_key: public_key_bytes.to_vec(),
-            public_key_string: public_key_string.clone(),
-            encrypted_secret_key: Some(encrypted_secret),
-        },
-    );
+        /// OpenSSH public key file selecting the key pair, as passed by Git from user.signingkey
+        #[arg(short = 'f', value_name = "PATH")]
+        key_file: Option<PathBuf>,
 
-    save_key_store(&key_store)?;
-    println!("\n✅ Generated new key pair '{}'", name);
-    println!("🔑 Public key: {}", public_key_string);
-    Ok(())
-}
+        /// Passed by Git for literal `key::` signing keys; the key comes from the key store anyway
+        #[arg(short = 'U', hide = true)]
+        literal_key: bool,
 
-fn batch_gen_keys(count: u32) -> Result<()> {
-    if count == 0 {
-        println!("Number of keys to generate must be greater than 0.");
-        return Ok(());
-    }
+        /// File to sign; the signature is written to <FILE>.sig
+        #[arg(conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+    },
+    /// Check the SSH signature of a Git commit made with sign-commit
+    VerifyCommit {
+        /// Commit to check, as accepted by `git cat-file`
+        #[arg(long, value_name = "SHA1")]
+        commit_hash: String,
 
-    let mut key_store = load_key_store()?;
-    let mut public_keys_to_write = Vec::new();
+        /// Expected signer, in the --encoding format or as an `ssh-ed25519 AAAA...` line
+        /// (default: the key pair named after the committer's email)
+        #[arg(long)]
+        public_key: Option<String>,
+    },
+    /// Check an ed25519 signature offline, the same way the server does
+    #[command(visible_alias = "verify-signature")]
+    Verify {
+        /// Public key (as sent in X-Public-Key), in the --encoding format
+        #[arg(long)]
+        public_key: String,
 
-    println!("Generating {} key pair(s)...", count);
-    let pb = ProgressBar::new(count as u64);
-    let pb_style = ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
-        .unwrap_or_else(|_| ProgressStyle::default_bar()) // Fallback style
-        .progress_chars("=> ");
-    pb.set_style(pb_style.clone());
-    pb.set_message("Generating keys");
+        /// Signature (as sent in X-Signature), in the --encoding format
+        #[arg(long)]
+        signature: String,
 
+        /// File containing the signed message bytes
+        #[arg(short, long, visible_alias = "payload-file")]
+        message_file: PathBuf,
+    },
+    /// Send a proof and ELF file to the server
+    ///
+    /// v1 JSON submissions, the default, still need about 3× the base64 size of the files in
+    /// memory: the base64 itself, its copy in the signed canonical string and another in the
+    /// JSON body. For large proofs pass
+    /// `--canonical-template v2` or `--upload multipart`, which sign SHA-256 digests instead.
+    Send {
+        #[command(flatten)]
+        files: SendFiles,
 
-    let mut next_key_idx_base = 0;
-    // Find a starting index for batch_key_X that doesn't exist yet.
-    // This helps avoid immediate collisions if batch-gen is run multiple times.
-    loop {
-        let potential_name = format!("batch_key_{}", next_key_idx_base);
-        if !key_store.keys.contains_key(&potential_name) {
-            break;
-        }
-        next_key_idx_base += 1;
-    }
+        /// Name of the key pair to use for signing
+        #[arg(short, long)]
+        key_name: String,
 
-    for i in 0..count {
-        let mut current_name_candidate_idx = next_key_idx_base + i;
-        let mut final_key_name;
-        // Ensure unique name even if there are gaps or manual additions matching the pattern
-        loop {
-            final_key_name = format!("batch_key_{}", current_name_candidate_idx);
-            if !key_store.keys.contains_key(&final_key_name) {
-                break;
-            }
-            current_name_candidate_idx += 1; // Increment and try next index
-        }
-        
-        // Generate a new key pair
-        let mut rng = OsRng;
-        let signing_key = SigningKey::generate(&mut rng); // Secret key is generated here
-        let verifying_key = signing_key.verifying_key();
-        let public_key_bytes = verifying_key.to_bytes();
-        let public_key_string = BASE64.encode(&public_key_bytes);
-
-        // Encrypt secret key with empty password
-        let secret_key_bytes = signing_key.to_bytes();
-        let empty_password = ""; // 使用空密码
-        let encrypted_secret = encrypt_secret_key(&secret_key_bytes, empty_password)
-            .expect("Failed to encrypt secret key with empty password");
-        
-        // Store the key pair with an encrypted secret key (using empty password)
-        key_store.keys.insert(
-            final_key_name.clone(),
-            KeyPair {
-                public_key: public_key_bytes.to_vec(),
-                public_key_string: public_key_string.clone(),
-                encrypted_secret_key: Some(encrypted_secret), // 使用空密码加密
-            },
-        );
-        public_keys_to_write.push(public_key_string);
-        pb.inc(1);
-    }
+        #[command(flatten)]
+        retry: RetryPolicy,
 
-    pb.finish_with_message(format!("✅ Generated {} key pair(s)", count));
+        #[command(flatten)]
+        wait: WaitPolicy,
 
-    // Save the updated key store
-    save_key_store(&key_store)?;
-    println!("💾 Key store updated with new public keys.");
+        #[command(flatten)]
+        sigstore: SigstoreOptions,
 
-    // Write public keys to public_keys.txt
-    let output_file_path = PathBuf::from("public_keys.txt");
-    let mut file = fs::File::create(&output_file_path)
-        .with_context(|| format!("Failed to create file: {}", output_file_path.display()))?;
-    
-    for pub_key_str in public_keys_to_write {
-        writeln!(file, "{}", pub_key_str)
-            .with_context(|| format!("Failed to write to file: {}", output_file_path.display()))?;
-    }
-    println!("🔑 All public keys written to {}", output_file_path.display());
+        /// Sign and verify the request locally and print it instead of sending it
+        #[arg(long)]
+        dry_run: bool,
 
-    Ok(())
-}
+        /// Stop a --batch-file run at the first submission that fails
+        #[arg(long, requires = "batch_file")]
+        fail_fast: bool,
 
+        /// Verify risc0 receipts against their ELF's image ID before sending (needs the `risc0` feature)
+        #[arg(long)]
+        verify_locally: bool,
 
-fn list_keys() -> Result<()> {
-    let key_store = load_key_store()?;
+        /// Before reading and signing anything, check the server knows the key's public key
+        #[arg(long, env = "SOUNDNESS_REQUIRE_KEY_IN_SERVER")]
+        require_key_in_server: bool,
 
-    if key_store.keys.is_empty() {
-        println!("No key pairs found. Generate one with 'generate-key' command.");
-        return Ok(());
-    }
+        /// Skip the --require-key-in-server check, e.g. when it is turned on through the environment
+        #[arg(long)]
+        skip_key_check: bool,
 
-    println!("Available key pairs:");
-    for (name, key_pair) in key_store.keys {
-        let secret_status = if key_pair.encrypted_secret_key.is_some() {
-            "(secret encrypted)"
-        } else {
-            "(secret not stored/encrypted)"
-        };
-        println!("- {} (Public key: {}) {}", name, key_pair.public_key_string, secret_status);
-    }
-    Ok(())
-}
+        /// Ticket from `notarize` to send in the X-Notarization-Ticket header
+        #[arg(long, value_name = "PATH", conflicts_with = "batch_file")]
+        notarization_ticket: Option<PathBuf>,
 
-// Calculate hash of key store contents
-fn calculate_key_store_hash(key_store: &KeyStore) -> String {
-    let serialized = serde_json::to_string(key_store).unwrap_or_default();
-    format!("{:x}", Sha256::digest(serialized.as_bytes()))
-}
+        /// Attestation from `attest` to send in the X-TEE-Attestation header
+        #[arg(long, value_name = "PATH", conflicts_with = "batch_file")]
+        tee_attestation: Option<PathBuf>,
 
-fn sign_payload(payload: &[u8], key_name: &str) -> Result<Vec<u8>> {
-    let key_store = load_key_store()?;
-    let key_store_hash = calculate_key_store_hash(&key_store);
+        /// PEM client certificate for servers that require mutual TLS (needs --client-key)
+        #[arg(long, value_name = "PATH")]
+        client_cert: Option<PathBuf>,
 
-    let key_pair = key_store
-        .keys
-        .get(key_name)
-        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", key_name))?;
+        /// PEM PKCS#8 private key matching --client-cert
+        #[arg(long, value_name = "PATH")]
+        client_key: Option<PathBuf>,
+    },
+    /// Send a proof only once a threshold of approvers have signed it, with their signatures in X-Approvals
+    ThresholdSend {
+        #[command(flatten)]
+        files: ProofFiles,
 
-    let encrypted_secret = key_pair
-        .encrypted_secret_key
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Keys generated with 'batch-gen' do not store encrypted secrets and cannot be used for signing directly with this method.", key_name))?;
+        /// Key pairs that may approve the submission, asked in this order
+        #[arg(long, value_name = "KEY1,KEY2,...", value_delimiter = ',', required = true)]
+        approvers: Vec<String>,
 
-    // Create a new scope for the password guard to ensure it's dropped properly
-    let password = {
-        let mut password_guard = PASSWORD_CACHE.lock().unwrap();
+        /// Number of approvals needed before the proof is sent
+        #[arg(long, value_name = "T")]
+        threshold: usize,
 
-        if let Some((stored_password, stored_hash)) = password_guard.as_ref() {
-            // Check if key store has changed
-            if stored_hash != &key_store_hash {
+        #[command(flatten)]
+        retry: RetryPolicy,
+
+        #[command(flatten)]
+        wait: WaitPolicy,
+    },
+    /// Sign the proof and ELF hashes, publish them to a transparency log and write a notarization ticket
+    Notarize {
+        /// Path to the proof file
+        #[arg(short, long)]
+        proof_file: PathBuf,
+
+        /// Path to the ELF file
+        #[arg(short = 'l', long)]
+        elf_file: PathBuf,
+
+        /// Name of the key pair to use for signing
+        #[arg(short, long)]
+        key_name: String,
+
+        /// Rekor server to publish to
+        #[arg(long, value_name = "URL", default_value = "https://rekor.sigstore.dev")]
+        rekor_url: String,
+
+        /// Where to write the ticket (default: <PROOF_FILE>.ticket.json)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Get a quote from the TEE this runs in (SGX, TDX or SEV-SNP) binding a proof's SHA-256
+    Attest {
+        /// Path to the proof file
+        #[arg(short, long)]
+        proof_file: PathBuf,
+
+        /// Trusted execution environment to attest with
+        #[arg(long, value_enum)]
+        tee: TeePlatform,
+
+        /// Where to write the attestation (default: <PROOF_FILE>.attestation.json)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Replace a key pair with a newly generated one. The old key pair is kept and marked as rotated
+    RotateKey {
+        /// Name of the key pair to retire
+        old_name: String,
+
+        /// Name for the new key pair
+        new_name: String,
+
+        /// Key derivation function for the new key's password (default: argon2id when available)
+        #[arg(long, value_enum)]
+        kdf: Option<Kdf>,
+
+        /// Also POST the rotation, signed by both keys, to {endpoint}/api/key-rotate
+        #[arg(long)]
+        announce: bool,
+
+        /// Sign the proofs the old key submitted that the server didn't accept (from submissions.log)
+        /// with the new key, and send them again
+        #[arg(long)]
+        resubmit: bool,
+
+        #[command(flatten)]
+        retry: RetryPolicy,
+    },
+    /// Publish an IPFS CID under the IPNS name of a key pair, through an IPFS node's HTTP API.
+    /// The record is signed locally, so the secret key never reaches the node
+    Pin {
+        /// CID to publish, e.g. of the proof after adding it to IPFS
+        #[arg(long, value_name = "CID")]
+        ipfs_cid: String,
+
+        /// Name of the key pair whose IPNS name is updated
+        #[arg(long, value_name = "KEYNAME")]
+        ipns_key: String,
+
+        /// HTTP API of the IPFS node (Kubo RPC)
+        #[arg(long, value_name = "URL", default_value = "http://127.0.0.1:5001")]
+        ipfs_api: String,
+
+        /// How long the record stays valid, in hours
+        #[arg(long, value_name = "HOURS", default_value_t = 48)]
+        lifetime: u64,
+    },
+    /// List the proofs in a registry that were made by a circuit or program
+    Search {
+        /// SHA-256 of the circuit (circom) or program ELF (sp1, risc0), in hex
+        #[arg(long, value_name = "HEX")]
+        circuit_hash: String,
+
+        /// Registry base URL
+        #[arg(long, value_name = "URL", env = "SOUNDNESS_REGISTRY")]
+        registry: String,
+    },
+    /// Publish proofs to a proof registry
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommands,
+    },
+    /// Register every public key not yet known to a key registry
+    SyncPublicKeys {
+        /// Registry base URL (default: the global --endpoint)
+        #[arg(long = "endpoint", value_name = "URL")]
+        registry: Option<String>,
+
+        /// Path on the registry that accepts public keys
+        #[arg(long, default_value = "/api/keys")]
+        path: String,
+    },
+    /// Manage the config file that sets defaults for --endpoint, --key-store, --key-name and --encoding
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Show the log of every proof sent to a server, kept in submissions.log next to the key store
+    ListSubmissions {
+        /// Only show submissions signed with this key pair, including those it approved with `threshold-send`
+        #[arg(long, value_name = "KEYNAME")]
+        filter_key: Option<String>,
+
+        /// Only show submissions from this time on: a Unix timestamp or an RFC 3339 date such as 2025-10-18
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+    },
+    /// Print a shell completion script to stdout, e.g. `soundness-cli completions bash > /etc/bash_completion.d/soundness-cli`
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Submit the same signed proof twice and check the replay is rejected (debug only)
+    #[command(hide = true)]
+    ReplayAttackDemo {
+        #[command(flatten)]
+        files: ProofFiles,
+
+        /// Name of the key pair to use for signing
+        #[arg(short, long)]
+        key_name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Write a commented config template to fill in
+    Init {
+        /// Write .soundness.toml in the current directory instead of the user config file
+        #[arg(long)]
+        local: bool,
+
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+const CONFIG_FILE: &str = "config.toml";
+const LOCAL_CONFIG_FILE: &str = ".soundness.toml";
+
+const CONFIG_TEMPLATE: &str = r#"# soundness-cli configuration. Command-line flags and environment variables take
+# precedence over these values. Uncomment the ones you need.
+
+# Server to send proofs to (--endpoint)
+# endpoint = "http://localhost:3000"
+
+# Key store file, relative to the directory the CLI runs in (--key-store)
+# key_store_path = "key_store.json"
+
+# Key pair for commands that need --key-name
+# default_key = "my-key"
+
+# Encoding for displayed public keys: "base64" or "hex" (--encoding)
+# encoding = "base64"
+
+# Largest key store file to load, in bytes (--max-key-store-size)
+# max_key_store_size_bytes = 134217728
+"#;
+
+/// Defaults for command-line flags, from the user's config file and a `.soundness.toml` in the
+/// current directory, which overrides it field by field
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    endpoint: Option<String>,
+    key_store_path: Option<PathBuf>,
+    /// Key pair for commands that need --key-name
+    default_key: Option<String>,
+    encoding: Option<String>,
+    max_key_store_size_bytes: Option<u64>,
+}
+
+impl Config {
+    // ~/.config/soundness/config.toml on Linux, the platform's config directory elsewhere
+    fn user_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    fn load() -> Result<Self> {
+        let mut config = Config::default();
+        for path in Self::user_path().into_iter().chain([PathBuf::from(LOCAL_CONFIG_FILE)]) {
+            if !path.is_file() {
+                continue;
+            }
+            let contents =
+                fs::read_to_string(&path).with_context(|| format!("Failed to read config file: {}", path.display()))?;
+            let file: Config =
+                toml::from_str(&contents).with_context(|| format!("Invalid config file: {}", path.display()))?;
+            config = Config {
+                endpoint: file.endpoint.or(config.endpoint),
+                key_store_path: file.key_store_path.or(config.key_store_path),
+                default_key: file.default_key.or(config.default_key),
+                encoding: file.encoding.or(config.encoding),
+                max_key_store_size_bytes: file.max_key_store_size_bytes.or(config.max_key_store_size_bytes),
+            };
+        }
+        if let Some(encoding) = &config.encoding {
+            if <KeyEncoding as clap::ValueEnum>::from_str(encoding, true).is_err() {
+                anyhow::bail!("Invalid encoding \"{}\" in the config file, use base64 or hex", encoding);
+            }
+        }
+        Ok(config)
+    }
+
+    // Config values become the defaults of their arguments, so anything given on the command
+    // line or in the environment still wins
+    fn apply(&self, mut command: clap::Command) -> clap::Command {
+        if let Some(endpoint) = &self.endpoint {
+            command = command.mut_arg("endpoint", |arg| arg.default_value(endpoint.clone()));
+        }
+        if let Some(key_store_path) = &self.key_store_path {
+            command = command.mut_arg("key_store", |arg| {
+                arg.default_value(key_store_path.to_string_lossy().into_owned())
+            });
+        }
+        if let Some(encoding) = &self.encoding {
+            command = command.mut_arg("encoding", |arg| arg.default_value(encoding.clone()));
+        }
+        if let Some(max_size) = self.max_key_store_size_bytes {
+            command = command.mut_arg("max_key_store_size", |arg| arg.default_value(max_size.to_string()));
+        }
+        match &self.default_key {
+            Some(default_key) => with_default_key(command, default_key),
+            None => command,
+        }
+    }
+}
+
+// ~/.config/soundness on Linux, the platform's config directory elsewhere. Holds config.toml
+// and one directory per profile
+fn config_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "soundness").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+fn profile_key_store_path(profile: &str) -> Result<PathBuf> {
+    if profile.is_empty() || profile.starts_with('.') || profile.contains(['/', '\\']) {
+        anyhow::bail!("Invalid profile name \"{}\": use a plain name like testnet or mainnet", profile);
+    }
+    let dir = config_dir().ok_or_else(|| anyhow::anyhow!("No home directory to keep profiles in, use --key-store"))?;
+    Ok(dir.join(profile).join("key_store.json"))
+}
+
+fn list_profiles(active: Option<&str>) -> Result<()> {
+    let dir = config_dir().ok_or_else(|| anyhow::anyhow!("No home directory to keep profiles in"))?;
+    let mut profiles: Vec<String> = match fs::read_dir(&dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        entries => entries
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+    };
+    profiles.sort();
+    report_field("profiles", &profiles);
+
+    if profiles.is_empty() {
+        println!("No profiles in {}. Create one with e.g. '--profile testnet generate-key --name my-key'.", dir.display());
+        return Ok(());
+    }
+    println!("Profiles in {}:", dir.display());
+    for profile in &profiles {
+        let marker = if active == Some(profile.as_str()) { " (active)" } else { "" };
+        outputln!("- {}{}", profile, marker);
+    }
+    Ok(())
+}
+
+// Only where --key-name is required: where it's optional, leaving it out means something else
+fn with_default_key(mut command: clap::Command, default_key: &str) -> clap::Command {
+    if command
+        .get_arguments()
+        .any(|arg| arg.get_id() == "key_name" && arg.is_required_set())
+    {
+        command = command.mut_arg("key_name", |arg| arg.default_value(default_key.to_string()).required(false));
+    }
+    let subcommands: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    subcommands.iter().fold(command, |command, name| {
+        command.mut_subcommand(name, |sub| with_default_key(sub, default_key))
+    })
+}
+
+fn init_config(local: bool, force: bool) -> Result<()> {
+    let path = if local {
+        PathBuf::from(LOCAL_CONFIG_FILE)
+    } else {
+        Config::user_path().ok_or_else(|| anyhow::anyhow!("No home directory to put the config file in, use --local"))?
+    };
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists, pass --force to replace it", path.display());
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(&path, CONFIG_TEMPLATE).with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    report_field("config_file", &path);
+    println!("⚙️  Config template written to {}", path.display());
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+enum RegistryCommands {
+    /// Sign a manifest of a proof and its ELF, and publish both under a name and version
+    Push(RegistryPush),
+    /// Download a published proof and its ELF, checking the signed manifest
+    Pull(RegistryPull),
+}
+
+#[derive(clap::Args, Debug)]
+struct RegistryPull {
+    /// Name the proof was published under
+    #[arg(long)]
+    name: String,
+
+    /// Version to download
+    #[arg(long)]
+    version: String,
+
+    /// Directory to write the proof and ELF to, as <name>-<version>.proof and .elf
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Registry base URL
+    #[arg(long, value_name = "URL", env = "SOUNDNESS_REGISTRY")]
+    registry: String,
+
+    /// Only accept a manifest signed by this base64 public key
+    #[arg(long, value_name = "BASE64")]
+    publisher_key: Option<String>,
+
+    /// Also verify the proof against the ELF, for proving systems with a local verifier (risc0)
+    #[arg(long)]
+    verify_local: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct RegistryPush {
+    /// Path to the proof file
+    #[arg(short, long)]
+    proof_file: PathBuf,
+
+    /// Path to the ELF file
+    #[arg(short = 'l', long)]
+    elf_file: PathBuf,
+
+    /// Name to publish the proof under (lowercase letters, digits, `-` and `_`)
+    #[arg(long)]
+    name: String,
+
+    /// Version of this release of the proof, e.g. 1.0.0
+    #[arg(long)]
+    version: String,
+
+    /// Name of the key pair to sign the manifest with
+    #[arg(short, long)]
+    key_name: String,
+
+    /// Registry base URL
+    #[arg(long, value_name = "URL", env = "SOUNDNESS_REGISTRY")]
+    registry: String,
+
+    /// Proving system of the proof (default: detected from the proof file, else sp1)
+    #[arg(short = 's', long)]
+    proving_system: Option<ProvingSystem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ProvingSystem {
+    Sp1,
+    Circom,
+    Risc0,
+    Starknet,
+}
+
+// How much of the proof file is looked at to guess its proving system
+const DETECTION_PREFIX_LEN: usize = 4096;
+
+// Every proving system whose format the proof matches:
+// - circom: snarkjs JSON with a "protocol" field
+// - starknet: JSON with a "stark" key
+// - risc0: a bincode receipt, decoded the same way `--verify-locally` reads it
+// - sp1: a bincode SP1ProofWithPublicValues wrapping a Plonk or Groth16 proof, see `is_sp1_proof`
+fn proving_system_candidates(bytes: &[u8]) -> Vec<ProvingSystem> {
+    let mut candidates = Vec::new();
+    if bytes.trim_ascii_start().starts_with(b"{") {
+        let text = String::from_utf8_lossy(bytes);
+        if text.contains("\"protocol\"") {
+            candidates.push(ProvingSystem::Circom);
+        }
+        if text.contains("\"stark\"") {
+            candidates.push(ProvingSystem::Starknet);
+        }
+    }
+    if is_risc0_receipt(bytes) {
+        candidates.push(ProvingSystem::Risc0);
+    }
+    if is_sp1_proof(bytes) {
+        candidates.push(ProvingSystem::Sp1);
+    }
+    candidates
+}
+
+/// Reads bincode values off the start of a proof, giving None once one doesn't fit
+struct BincodeReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BincodeReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(..len)?;
+        self.bytes = &self.bytes[len..];
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    // Strings and byte vectors are a u64 length followed by the bytes
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        self.take(len)
+    }
+}
+
+// SP1's Plonk and Groth16 proofs are the SP1Proof variant (2 or 3), the two public inputs as
+// decimal strings, the hex encoded and raw gnark proofs and the 32-byte verifying key hash. The
+// public values and the `v…` SDK version follow, and are checked as far as the prefix reaches.
+// Core and compressed proofs start with nothing that tells them from other bincode, so they are
+// left undetected
+fn is_sp1_proof(bytes: &[u8]) -> bool {
+    let mut reader = BincodeReader { bytes };
+    if !matches!(reader.u32(), Some(2 | 3)) {
+        return false;
+    }
+    let decimal = |field: &[u8]| !field.is_empty() && field.len() <= 78 && field.iter().all(u8::is_ascii_digit);
+    let hex = |field: &[u8]| field.len().is_multiple_of(2) && field.iter().all(u8::is_ascii_hexdigit);
+    let header = (|| {
+        let inputs = [reader.bytes()?, reader.bytes()?];
+        let proofs = [reader.bytes()?, reader.bytes()?];
+        reader.take(32)?;
+        Some(inputs.into_iter().all(decimal) && !proofs[0].is_empty() && proofs.into_iter().all(hex))
+    })();
+    if header != Some(true) {
+        return false;
+    }
+    // Large public values push the version past a prefix, and then it can't be checked
+    let cut_off = bytes.len() >= DETECTION_PREFIX_LEN;
+    match reader.bytes().and_then(|_public_values| reader.bytes()) {
+        Some(version) => version.starts_with(b"v"),
+        None => cut_off,
+    }
+}
+
+/// Guess the proving system of a proof from its contents, if exactly one format matches
+fn detect_proving_system(bytes: &[u8]) -> Option<ProvingSystem> {
+    match proving_system_candidates(bytes).as_slice() {
+        [system] => Some(*system),
+        _ => None,
+    }
+}
+
+// Files that match nothing give None (callers fall back to sp1), files that match several need --proving-system
+fn detect_proof_file_system(path: &Path) -> Result<Option<ProvingSystem>> {
+    let mut prefix = Vec::with_capacity(DETECTION_PREFIX_LEN);
+    fs::File::open(path)
+        .and_then(|file| file.take(DETECTION_PREFIX_LEN as u64).read_to_end(&mut prefix))
+        .with_context(|| format!("Failed to read proof file: {}", path.display()))?;
+    // A receipt has no fixed header, so one can only be recognized by decoding all of it. It opens
+    // with one of the four InnerReceipt variants
+    let variant = prefix.get(..4).map(|tag| u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]));
+    if cfg!(feature = "risc0") && variant.is_some_and(|variant| variant <= 3) {
+        prefix = fs::read(path).with_context(|| format!("Failed to read proof file: {}", path.display()))?;
+    }
+    if let Some(detected) = detect_proving_system(&prefix) {
+        eprintln!(
+            "🔍 Detected a {} proof in {} (pass --proving-system to override)",
+            detected.name(),
+            path.display()
+        );
+        return Ok(Some(detected));
+    }
+    let candidates = proving_system_candidates(&prefix);
+    if candidates.len() > 1 {
+        anyhow::bail!(
+            "{} could be a {} proof, pass --proving-system to pick one",
+            path.display(),
+            candidates.iter().map(|system| system.name()).collect::<Vec<_>>().join(" or ")
+        );
+    }
+    eprintln!(
+        "🔍 Could not tell the proving system of {}, using sp1 (pass --proving-system to choose)",
+        path.display()
+    );
+    Ok(None)
+}
+
+/// Output formats for `export-public-key`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum PublicKeyFormat {
+    Base64,
+    Hex,
+    /// The 32 raw key bytes
+    Raw,
+    /// An `ssh-ed25519 AAAA...` line, e.g. for Git's `user.signingkey` and allowed signers file
+    Openssh,
+}
+
+/// Input formats accepted by `import-key`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ImportFormat {
+    Mnemonic,
+    Hex,
+    Base64,
+}
+
+impl ImportFormat {
+    fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+/// Built-in canonical string layouts, so the CLI can talk to servers on different versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CanonicalTemplate {
+    /// Base64 of the full proof and ELF contents (current layout). The base64 is held in memory
+    /// several times over, so prefer v2 for large proofs
+    V1,
+    /// SHA-256 digests of the proof and ELF instead of their full contents
+    V2,
+}
+
+impl CanonicalTemplate {
+    fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+impl ProvingSystem {
+    /// Lowercase name used in the request body and canonical string
+    fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+/// HD standard and path a key is derived with
+struct HdDerivation {
+    standard: HdStandard,
+    path: String,
+    use_passphrase: bool,
+}
+
+impl HdDerivation {
+    fn new(standard: HdStandard, path: Option<String>, use_passphrase: bool) -> Result<Self> {
+        if standard == HdStandard::Bip32Ed25519 {
+            // BIP32-Ed25519 yields extended secret keys rather than 32-byte ed25519 seeds
+            anyhow::bail!("The bip32-ed25519 standard is not supported: it produces extended secret keys that cannot be stored as ed25519 seeds. Use --hd-standard slip0010 instead.");
+        }
+        let path = path.unwrap_or_else(|| DEFAULT_DERIVATION_PATH.to_string());
+        parse_derivation_path(&path)?;
+        Ok(HdDerivation {
+            standard,
+            path,
+            use_passphrase,
+        })
+    }
+
+    // Rebuild the derivation settings recorded for a stored key
+    fn from_key_pair(key_pair: &KeyPair) -> Option<Self> {
+        Some(HdDerivation {
+            standard: key_pair.metadata.hd_standard?,
+            path: key_pair.metadata.derivation_path.clone()?,
+            use_passphrase: key_pair.has_bip39_passphrase,
+        })
+    }
+
+    fn metadata(&self) -> KeyMetadata {
+        KeyMetadata {
+            hd_standard: Some(self.standard),
+            derivation_path: Some(self.path.clone()),
+            ..Default::default()
+        }
+    }
+
+    // Derive the ed25519 signing seed for this path from a BIP39 mnemonic and optional passphrase
+    fn derive(&self, mnemonic: &bip39::Mnemonic, passphrase: &str) -> Result<[u8; 32]> {
+        slip10_derive_ed25519(&mnemonic.to_seed(passphrase), &self.path)
+    }
+}
+
+// Metadata for a key pair created right now
+fn new_key_metadata(hd: Option<&HdDerivation>) -> KeyMetadata {
+    KeyMetadata {
+        created_at: Some(soundness_layer::unix_timestamp()),
+        ..hd.map(HdDerivation::metadata).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Kdf {
+    Pbkdf2,
+    Argon2id,
+}
+
+impl Kdf {
+    fn params(self) -> KdfParams {
+        match self {
+            Kdf::Pbkdf2 => KdfParams::pbkdf2(),
+            Kdf::Argon2id => KdfParams::argon2id(),
+        }
+    }
+}
+
+/// Settings shared by all subcommand handlers
+struct CliContext {
+    endpoint: String,
+    canonical_template: CanonicalTemplate,
+    encoding: KeyEncoding,
+    password_source: SecretSource,
+    mnemonic_source: SecretSource,
+    strict_base64: bool,
+    fail_on_empty_password: bool,
+    /// --yes: confirm destructive steps without asking
+    assume_yes: bool,
+    strict_password: bool,
+    min_password_bits: u32,
+    timeout: Duration,
+    key_store_path: PathBuf,
+    key_store_format: KeyStoreFormat,
+    /// `None` with --ignore-size-limit
+    max_key_store_size: Option<u64>,
+    encrypt_store: bool,
+    store_password_source: SecretSource,
+    /// Store password, read at most once per run
+    store_password: Mutex<Option<String>>,
+    /// In-memory key store used instead of the file when running with `--ephemeral`
+    ephemeral_store: Option<Mutex<KeyStore>>,
+}
+
+impl CliContext {
+    /// Decode user-supplied key material, honouring `--strict-base64`
+    fn decode(&self, encoding: KeyEncoding, text: &str) -> Result<Vec<u8>> {
+        if self.strict_base64 {
+            encoding.decode_strict(text)
+        } else {
+            encoding.decode(text)
+        }
+    }
+
+    fn load_key_store(&self) -> Result<KeyStore> {
+        if self.ephemeral_store.is_none() {
+            self.check_key_store_size()?;
+        }
+        match &self.ephemeral_store {
+            Some(store) => Ok(store.lock().unwrap().clone()),
+            None if soundness_layer::is_encrypted_key_store(&self.key_store_path)? => {
+                soundness_layer::load_encrypted_key_store(&self.key_store_path, &self.store_password(false)?)
+            }
+            None => soundness_layer::load_key_store_as(&self.key_store_path, self.key_store_format),
+        }
+    }
+
+    // Ask before a destructive step. Without a terminal there is nobody to ask, so the step is
+    // refused rather than taken silently unless --yes was given
+    fn confirm(&self, question: &str) -> Result<()> {
+        if self.assume_yes {
+            return Ok(());
+        }
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("{} Pass --yes to confirm without a terminal", question);
+        }
+        eprint!("❓ {} [y/N] ", question);
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).context("Failed to read the answer")?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            anyhow::bail!("Cancelled, nothing was changed");
+        }
+        Ok(())
+    }
+
+    // Just the key pair `name`. An NDJSON store is scanned for its line instead of loaded whole,
+    // so the size limit doesn't apply
+    fn load_key_pair(&self, name: &str) -> Result<KeyPair> {
+        let scan = self.ephemeral_store.is_none() && self.key_store_format == KeyStoreFormat::Ndjson && !self.encrypt_store;
+        let key_pair = match scan.then(|| soundness_layer::load_ndjson_key_pair(&self.key_store_path, name)) {
+            Some(Ok(key_pair)) => key_pair,
+            // An encrypted store is one JSON document whatever the file is called
+            Some(Err(e)) if !soundness_layer::is_encrypted_key_store(&self.key_store_path)? => return Err(e),
+            _ => self.load_key_store()?.keys.remove(name),
+        };
+        key_pair.ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))
+    }
+
+    fn check_key_store_size(&self) -> Result<()> {
+        let (Some(max_size), Ok(metadata)) = (self.max_key_store_size, fs::metadata(&self.key_store_path)) else {
+            return Ok(());
+        };
+        if metadata.len() > max_size {
+            anyhow::bail!(
+                "Key store {} is {}, over the {} limit. If it is meant to be this big, raise --max-key-store-size or pass --ignore-size-limit",
+                self.key_store_path.display(),
+                indicatif::HumanBytes(metadata.len()),
+                indicatif::HumanBytes(max_size)
+            );
+        }
+        Ok(())
+    }
+
+    // Ask for a new password (twice when prompting) only when a store is encrypted for the first time
+    fn store_password(&self, new: bool) -> Result<String> {
+        let mut cached = self.store_password.lock().unwrap();
+        if let Some(password) = cached.as_ref() {
+            return Ok(password.clone());
+        }
+        let password = if new {
+            read_new_password(&self.store_password_source, "Enter new store password: ")?
+        } else {
+            read_password(&self.store_password_source, "Enter store password: ")?
+        };
+        *cached = Some(password.clone());
+        Ok(password)
+    }
+
+    fn is_store_encrypted(&self) -> Result<bool> {
+        Ok(self.encrypt_store || soundness_layer::is_encrypted_key_store(&self.key_store_path)?)
+    }
+
+    // The key store as written to disk: encrypted if the store is, pretty JSON otherwise
+    fn render_key_store(&self, key_store: &KeyStore) -> Result<String> {
+        if !self.is_store_encrypted()? {
+            return Ok(serde_json::to_string_pretty(key_store)?);
+        }
+        let new = !soundness_layer::is_encrypted_key_store(&self.key_store_path)?;
+        let envelope = KeyStoreEnvelope::seal(&serde_json::to_string(key_store)?, &self.store_password(new)?)?;
+        Ok(serde_json::to_string_pretty(&envelope)?)
+    }
+
+    fn save_key_store(&self, key_store: &KeyStore) -> Result<()> {
+        match &self.ephemeral_store {
+            Some(store) => {
+                *store.lock().unwrap() = key_store.clone();
+                // `--key-store -` dumps the ephemeral store so callers can capture it
+                if self.key_store_path == Path::new("-") {
+                    report_field("key_store", key_store);
+                    outputln!("{}", serde_json::to_string_pretty(key_store)?);
+                }
+                Ok(())
+            }
+            None if self.is_store_encrypted()? => {
+                let new = !soundness_layer::is_encrypted_key_store(&self.key_store_path)?;
+                let password = self.store_password(new)?;
+                soundness_layer::save_encrypted_key_store(key_store, &self.key_store_path, &password)
+            }
+            None => soundness_layer::save_key_store_as(key_store, &self.key_store_path, self.key_store_format),
+        }
+    }
+}
+
+fn create_progress_bar(message: &str) -> ProgressBar {
+    if quiet_output() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap(),
+    );
+    pb.set_message(message.to_string());
+    pb.enable_steady_tick(Duration::from_millis(120));
+    pb
+}
+
+// Bar for a run over `len` items, not drawn with --quiet or --json
+fn new_progress_bar(len: u64) -> ProgressBar {
+    if quiet_output() {
+        ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden())
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+// Checks exit with status 1 when they fail; with --json that is reported as an error instead
+fn exit_unless(passed: bool, failure: &str) -> Result<()> {
+    report_field("valid", passed);
+    if passed {
+        return Ok(());
+    }
+    if json_output() {
+        anyhow::bail!("{}", failure);
+    }
+    std::process::exit(1);
+}
+
+fn read_password(source: &SecretSource, prompt: &str) -> Result<String> {
+    source.read(prompt).map_err(|e| {
+        // Prompting only fails like this when there is no terminal, e.g. in CI
+        if source.is_prompt() {
+            anyhow::anyhow!(
+                "Failed to read password: {}. Without a terminal, set SOUNDNESS_PASSWORD or pass --password-env VAR or --password-file PATH",
+                e
+            )
+        } else {
+            anyhow::anyhow!("Failed to read password: {}", e)
+        }
+    })
+}
+
+// Key password used when no password option is given
+const PASSWORD_ENV_VAR: &str = "SOUNDNESS_PASSWORD";
+
+// Read a new password. When prompting, ask twice and make sure both entries match
+fn read_new_password(source: &SecretSource, prompt: &str) -> Result<String> {
+    let password = read_password(source, prompt)?;
+    if !source.is_prompt() {
+        return Ok(password);
+    }
+    let confirm_password = read_password(source, "Confirm password: ")?;
+
+    if password != confirm_password {
+        anyhow::bail!("Passwords do not match");
+    }
+    Ok(password)
+}
+
+// About 10 random characters from letters and digits
+const DEFAULT_MIN_PASSWORD_BITS: u32 = 50;
+
+// Read a new password for a secret key and rate it. A weak one is only a warning unless
+// --strict-password is given, since scripts may rely on short passwords for throwaway keys
+fn read_new_key_password(ctx: &CliContext, source: &SecretSource, prompt: &str) -> Result<String> {
+    let password = read_new_password(source, prompt)?;
+    let strength = soundness_layer::password_strength(&password);
+    println!("🔐 Password strength: {} ({}/4, about {:.0} bits)", strength.label(), strength.score, strength.bits);
+    if strength.bits < f64::from(ctx.min_password_bits) {
+        if ctx.strict_password {
+            anyhow::bail!(
+                "Password is too weak (about {:.0} bits, --min-password-bits is {}). Use a longer one mixing cases, digits and symbols",
+                strength.bits,
+                ctx.min_password_bits
+            );
+        }
+        eprintln!(
+            "⚠️  WARNING: This password is {} (about {:.0} bits, below {}). Pass --strict-password to refuse weak passwords",
+            strength.label(),
+            strength.bits,
+            ctx.min_password_bits
+        );
+    }
+    Ok(password)
+}
+
+// Read a mnemonic or raw secret key from --mnemonic-source. Only stdin shows the prompt
+// as a visible line, the other sources are meant for automation
+fn read_mnemonic(ctx: &CliContext, prompt: &str) -> Result<String> {
+    if ctx.mnemonic_source == SecretSource::Stdin {
+        outputln!("{}", prompt);
+    }
+    ctx.mnemonic_source
+        .read(&format!("{} ", prompt))
+        .map_err(|e| anyhow::anyhow!("Failed to read mnemonic: {}", e))
+}
+
+fn prompt_bip39_passphrase(hd: Option<&HdDerivation>, confirm: bool) -> Result<String> {
+    if !hd.is_some_and(|hd| hd.use_passphrase) {
+        return Ok(String::new());
+    }
+
+    let passphrase = prompt_password("\nEnter BIP39 passphrase: ")
+        .map_err(|e| anyhow::anyhow!("Failed to read passphrase: {}", e))?;
+    if confirm {
+        let confirm_passphrase = prompt_password("Confirm BIP39 passphrase: ")
+            .map_err(|e| anyhow::anyhow!("Failed to read passphrase: {}", e))?;
+        if passphrase != confirm_passphrase {
+            anyhow::bail!("Passphrases do not match");
+        }
+    }
+    Ok(passphrase)
+}
+
+// `show_mnemonic` puts the mnemonic in the --json result, which is otherwise kept free of secrets
+fn generate_key_pair(
+    ctx: &CliContext,
+    name: &str,
+    kdf: KdfParams,
+    hd: Option<HdDerivation>,
+    show_mnemonic: bool,
+) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    let new_key = create_key_pair(ctx, &mut key_store, name, kdf, hd)?;
+    save_new_key_pair(ctx, &mut key_store, name, new_key, show_mnemonic)
+}
+
+/// A key pair that was generated and encrypted, but not saved yet
+struct NewKeyPair {
+    key_pair: KeyPair,
+    signing_key: SigningKey,
+    mnemonic: String,
+}
+
+// Generate a key pair, show its mnemonic and encrypt it with a new password. Nothing is saved,
+// so a caller that fails afterwards leaves the key store untouched
+fn create_key_pair(
+    ctx: &CliContext,
+    key_store: &mut KeyStore,
+    name: &str,
+    kdf: KdfParams,
+    hd: Option<HdDerivation>,
+) -> Result<NewKeyPair> {
+    if key_store.keys.contains_key(name) {
+        anyhow::bail!("Key pair with name '{}' already exists", name);
+    }
+
+    // Generate a new key pair. Without HD derivation the secret key itself is the mnemonic entropy,
+    // otherwise the secret is derived from the seed of a freshly generated mnemonic
+    let mut rng = OsRng;
+    let mut entropy = [0u8; 32];
+    rng.fill_bytes(&mut entropy);
+    let mnemonic = bip39::Mnemonic::from_entropy(&entropy)
+        .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic: {}", e))?;
+    let mnemonic_string = mnemonic.to_string();
+
+    let passphrase = prompt_bip39_passphrase(hd.as_ref(), true)?;
+    let secret_key_bytes = match &hd {
+        Some(hd) => hd.derive(&mnemonic, &passphrase)?,
+        None => entropy,
+    };
+    let signing_key = SigningKey::from_bytes(&secret_key_bytes);
+    let verifying_key = signing_key.verifying_key();
+    let public_key_bytes = verifying_key.to_bytes();
+    let public_key_string = key_store.encode_public_key(&public_key_bytes, ctx.encoding);
+
+    println!("\n📝 IMPORTANT: Save this mnemonic phrase securely for your testnet participation!");
+    println!("⚠️  WARNING: This is the only time you'll see this mnemonic! You'll need it to recover your secret key if the key store is lost!\n");
+    outputln!("{}", mnemonic_string);
+    if !passphrase.is_empty() {
+        println!("\n🧂 This key also needs your BIP39 passphrase. Without it the mnemonic restores a different key!");
+    }
+
+    // Get password for secret key encryption
+    let password = read_new_key_password(ctx, &ctx.password_source, "\nEnter password for secret key: ")?;
+
+    // Encrypt the secret key
+    let encrypted_secret = encrypt_secret_key(&secret_key_bytes, &password, kdf)?;
+    let encrypted_mnemonic = match &hd {
+        Some(_) => Some(encrypt_secret_key(&entropy, &password, kdf)?),
+        None => None,
+    };
+
+    let key_pair = KeyPair {
+        public_key: public_key_bytes.to_vec(),
+        public_key_string: public_key_string.clone(),
+        encrypted_secret_key: Some(encrypted_secret),
+        encrypted_mnemonic,
+        metadata: new_key_metadata(hd.as_ref()),
+        has_bip39_passphrase: hd.as_ref().is_some_and(|hd| hd.use_passphrase),
+        registered_with: Vec::new(),
+    };
+    key_pair
+        .check_encrypted_secret(&password)
+        .with_context(|| format!("Refusing to save key pair '{}'", name))?;
+    Ok(NewKeyPair {
+        key_pair,
+        signing_key,
+        mnemonic: mnemonic_string,
+    })
+}
+
+// Add a key pair from `create_key_pair` to the key store and save it
+fn save_new_key_pair(
+    ctx: &CliContext,
+    key_store: &mut KeyStore,
+    name: &str,
+    new_key: NewKeyPair,
+    show_mnemonic: bool,
+) -> Result<()> {
+    let public_key = ctx.encoding.encode(&new_key.key_pair.public_key);
+    key_store.keys.insert(name.to_string(), new_key.key_pair);
+
+    ctx.save_key_store(key_store)?;
+    println!("\n✅ Generated new key pair '{}'", name);
+    outputln!("🔑 Public key: {}", public_key);
+    report_field("name", name);
+    report_field("public_key", &public_key);
+    if show_mnemonic {
+        report_field("mnemonic", &new_key.mnemonic);
+    } else if json_output() {
+        eprintln!("🔑 The mnemonic is not in the JSON result. Back it up with export-key, or pass --show-mnemonic");
+    }
+    Ok(())
+}
+
+// The sequence number is the current time, so each publish supersedes the previous one
+async fn pin_to_ipns(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    cid: &str,
+    key_name: &str,
+    ipfs_api: &str,
+    lifetime_hours: u64,
+) -> Result<()> {
+    let cid = cid.trim().trim_start_matches("/ipfs/");
+    if cid.is_empty() || cid.contains(|c: char| c == '/' || c.is_whitespace()) {
+        anyhow::bail!("Invalid CID \"{}\"", cid);
+    }
+    let name = ipns_name(&get_public_key(ctx, key_name)?);
+    let now = soundness_layer::unix_timestamp();
+    let record = IpnsRecord::new(
+        &format!("/ipfs/{}", cid),
+        now,
+        now + lifetime_hours * 3600,
+        Duration::from_secs(3600),
+    );
+    let signature_v1 = sign_payload(ctx, &record.signed_data_v1(), key_name)?;
+    let signature_v2 = sign_payload(ctx, &record.signed_data_v2(), key_name)?;
+    let entry = record.encode(&signature_v1, &signature_v2);
+
+    let url = format!("{}/api/v0/routing/put", ipfs_api.trim_end_matches('/'));
+    let form = reqwest::multipart::Form::new().part("file", reqwest::multipart::Part::bytes(entry).file_name("record"));
+    let pb = create_progress_bar("📌 Publishing the IPNS record...");
+    let response = client
+        .post(&url)
+        .query(&[("arg", format!("/ipns/{}", name).as_str()), ("allow-offline", "true")])
+        .multipart(form)
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            pb.abandon_with_message("❌ Publishing failed");
+            return Err(anyhow::Error::new(e).context(format!("Failed to send request to {}", url)));
+        }
+    };
+    if !response.status().is_success() {
+        pb.abandon_with_message("❌ Publishing failed");
+        anyhow::bail!(
+            "IPFS node returned status {}: {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        );
+    }
+    pb.finish_and_clear();
+
+    println!("📌 /ipns/{} now resolves to /ipfs/{}", name, cid);
+    println!("   Sequence {}, valid until {}", record.sequence, record.validity);
+    report_field("ipns_name", &name);
+    report_field("cid", cid);
+    report_field("sequence", record.sequence);
+    report_field("validity", &record.validity);
+    Ok(())
+}
+
+// The statement both keys sign to show the new key was issued by the holder of the old one
+fn key_rotation_message(old_public_key: &[u8], new_public_key: &[u8], rotated_at: u64) -> String {
+    format!(
+        "soundness-key-rotation:1\nold_public_key:{}\nnew_public_key:{}\nrotated_at:{}",
+        BASE64.encode(old_public_key),
+        BASE64.encode(new_public_key),
+        rotated_at
+    )
+}
+
+async fn rotate_key(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    old_name: &str,
+    new_name: &str,
+    kdf: KdfParams,
+    announce: bool,
+) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    let old_key = key_store
+        .keys
+        .get(old_name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", old_name))?;
+    if let Some(rotated_to) = &old_key.metadata.rotated_to {
+        anyhow::bail!("Key pair '{}' was already rotated to '{}'", old_name, rotated_to);
+    }
+    if announce {
+        old_key.check_access(old_name, KeyOperation::Sign)?;
+        if old_key.encrypted_secret_key.is_none() {
+            anyhow::bail!("Key pair '{}' has no secret key, so it can't sign the rotation", old_name);
+        }
+    }
+    let old_public_key = old_key.public_key.clone();
+
+    // The new key pair stays in memory until both signatures are collected, so a wrong
+    // password leaves the key store as it was and the rotation can simply be run again
+    let new_key = create_key_pair(ctx, &mut key_store, new_name, kdf, None)?;
+    let rotated_at = soundness_layer::unix_timestamp();
+
+    let announcement = if announce {
+        let new_public_key = new_key.key_pair.public_key.clone();
+        let message = key_rotation_message(&old_public_key, &new_public_key, rotated_at);
+        println!("\n✍️  Sign the rotation with the old key '{}'", old_name);
+        let old_signature = sign_payload(ctx, message.as_bytes(), old_name)?;
+        let new_signature = new_key.signing_key.sign(message.as_bytes()).to_bytes();
+        Some(serde_json::json!({
+            "old_public_key": BASE64.encode(&old_public_key),
+            "new_public_key": BASE64.encode(&new_public_key),
+            "rotated_at": rotated_at,
+            "message": message,
+            "old_signature": BASE64.encode(old_signature),
+            "new_signature": BASE64.encode(new_signature),
+        }))
+    } else {
+        None
+    };
+
+    let old_key = key_store
+        .keys
+        .get_mut(old_name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", old_name))?;
+    old_key.metadata.rotated_at = Some(rotated_at);
+    old_key.metadata.rotated_to = Some(new_name.to_string());
+    old_key.touch();
+    // The new key pair and the marks on the old one are saved together
+    save_new_key_pair(ctx, &mut key_store, new_name, new_key, false)?;
+    println!("🔁 Rotated '{}' to '{}'. The old key pair is kept for verifying old signatures", old_name, new_name);
+    report_field("old_name", old_name);
+    report_field("new_name", new_name);
+
+    if let Some(announcement) = announcement {
+        let url = format!("{}/api/key-rotate", ctx.endpoint);
+        let pb = create_progress_bar("📡 Announcing the rotation...");
+        let result = client.post(&url).json(&announcement).send().await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                pb.finish_with_message(format!("📡 Rotation announced to {}", url));
+            }
+            Ok(response) => {
+                pb.abandon_with_message("❌ Announcing the rotation failed");
+                anyhow::bail!(
+                    "The key was rotated locally, but {} returned status {}: {}",
+                    url,
+                    response.status(),
+                    response.text().await.unwrap_or_default()
+                );
+            }
+            Err(e) => {
+                pb.abandon_with_message("❌ Announcing the rotation failed");
+                return Err(anyhow::Error::new(e)
+                    .context(format!("The key was rotated locally, but it could not be announced to {}", url)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Machine-readable summary of a batch run, written by `--report-file`.
+/// Bump `schema_version` whenever a field is renamed or removed.
+#[derive(Debug, Serialize)]
+struct BatchReport {
+    schema_version: u32,
+    command: &'static str,
+    started_at: u64,
+    duration_ms: u128,
+    output: Option<String>,
+    items: Vec<BatchReportItem>,
+    totals: BatchReportTotals,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchReportItem {
+    name: String,
+    status: &'static str,
+    public_key: String,
+    fingerprint: String,
+    duration_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchReportTotals {
+    requested: u32,
+    succeeded: u32,
+    failed: u32,
+}
+
+fn write_batch_report(report: &BatchReport, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(report)?;
+    fs::write(path, json)
+        .with_context(|| format!("Failed to write report file: {}", path.display()))
+}
+
+// Open the public key output: `-` is stdout, otherwise a file that is truncated or appended to
+fn open_public_key_writer(output: &Path, append: bool) -> Result<Box<dyn Write>> {
+    if output == Path::new("-") && json_output() {
+        anyhow::bail!("--json needs stdout for its result, write the public keys to a file with --output <PATH>");
+    }
+    Ok(if output == Path::new("-") {
+        Box::new(std::io::stdout().lock())
+    } else if append {
+        Box::new(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output)
+                .with_context(|| format!("Failed to open file: {}", output.display()))?,
+        )
+    } else {
+        Box::new(
+            fs::File::create(output)
+                .with_context(|| format!("Failed to create file: {}", output.display()))?,
+        )
+    })
+}
+
+/// Settings for a `batch-gen` / `keygen-batch-stream` run
+struct BatchGenOptions {
+    count: u32,
+    prefix: String,
+    start: u32,
+    output: PathBuf,
+    append: bool,
+    report_file: Option<PathBuf>,
+    /// Write and flush each public key as soon as it is generated
+    stream: bool,
+    /// Worker threads for key generation, `None` for one per CPU core
+    parallel: Option<usize>,
+    /// Print one JSON event per key to stdout instead of a progress bar
+    ndjson: bool,
+    /// Only update the key store, don't write the public keys anywhere
+    skip_file_output: bool,
+    /// Write one `<key_name>.pub` file per key here instead of a single output file
+    output_dir: Option<PathBuf>,
+}
+
+// Key material for one batch entry, generated on a worker thread
+struct BatchKey {
+    public_key: [u8; 32],
+    encrypted_secret: EncryptedSecretKey,
+    duration_ms: u128,
+}
+
+fn generate_batch_key(pb: &ProgressBar) -> Result<BatchKey> {
+    let key_timer = Instant::now();
+    let mut rng = OsRng;
+    let signing_key = SigningKey::generate(&mut rng); // Secret key is generated here
+
+    // Encrypt secret key with empty password
+    let empty_password = ""; // 使用空密码
+    let encrypted_secret = encrypt_secret_key(&signing_key.to_bytes(), empty_password, KdfParams::empty_password())
+        .context("Failed to encrypt secret key with empty password")?;
+
+    pb.inc(1);
+    Ok(BatchKey {
+        public_key: signing_key.verifying_key().to_bytes(),
+        encrypted_secret,
+        duration_ms: key_timer.elapsed().as_millis(),
+    })
+}
+
+fn batch_gen_keys(ctx: &CliContext, options: &BatchGenOptions) -> Result<()> {
+    let BatchGenOptions {
+        count,
+        ref prefix,
+        start,
+        ref output,
+        append,
+        ref report_file,
+        stream,
+        parallel,
+        ndjson,
+        skip_file_output,
+        ref output_dir,
+    } = *options;
+    // A directory of per-key files replaces the single output file
+    let skip_file_output = skip_file_output || output_dir.is_some();
+    // Key names become file names, so the prefix must not escape the output directory
+    if output_dir.is_some() && prefix.contains(['/', '\\']) {
+        anyhow::bail!("Prefix '{}' can't be used in file names with --output-dir", prefix);
+    }
+
+    if count == 0 {
+        println!("Number of keys to generate must be greater than 0.");
+        return Ok(());
+    }
+
+    // Keep stdout clean for the public keys or NDJSON events when they are piped elsewhere
+    let to_stdout = output == Path::new("-");
+    if to_stdout && ndjson && !skip_file_output {
+        anyhow::bail!("--ndjson writes events to stdout, so public keys need a file: pass --output <PATH>");
+    }
+    if ndjson && json_output() {
+        anyhow::bail!("--ndjson and --json both write to stdout, pick one");
+    }
+    let status = |message: String| {
+        if to_stdout || ndjson {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    };
+
+    let mut key_store = ctx.load_key_store()?;
+    let mut public_keys_to_write = Vec::new();
+    let mut report_items = Vec::new();
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let run_timer = Instant::now();
+
+    status(format!("Generating {} key pair(s)...", count));
+    status("⚠️  WARNING: Batch keys are encrypted with an EMPTY password. Anyone who can read the key store can sign with them!".to_string());
+    let pb = if ndjson {
+        ProgressBar::hidden()
+    } else {
+        new_progress_bar(count as u64)
+    };
+    let pb_style = ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
+        .unwrap_or_else(|_| ProgressStyle::default_bar()) // Fallback style
+        .progress_chars("=> ");
+    pb.set_style(pb_style.clone());
+    pb.set_message("Generating keys");
+
+
+    // In stream mode keys go out as they are generated, otherwise only after the store is saved
+    let mut stream_writer = if stream {
+        Some(open_public_key_writer(output, append)?)
+    } else {
+        None
+    };
+
+    // Pick every name up front so the key material can be generated in parallel
+    let mut names = Vec::with_capacity(count as usize);
+    // None once the index has passed u32::MAX
+    let mut candidate_idx = Some(start);
+    while names.len() < count as usize {
+        let Some(idx) = candidate_idx else {
+            anyhow::bail!(
+                "Only {} of {} key names are free between index {} and {}, use a lower --start or another --prefix",
+                names.len(),
+                count,
+                start,
+                u32::MAX
+            );
+        };
+        // Skip names that already exist, e.g. when batch-gen is run multiple times
+        let name = format!("{}{}", prefix, idx);
+        if !key_store.keys.contains_key(&name) {
+            names.push(name);
+        }
+        candidate_idx = idx.checked_add(1);
+    }
+
+    // 0 threads lets rayon use one per CPU core
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallel.unwrap_or(0))
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to start worker threads: {}", e))?;
+    // Streaming only waits for one key per thread before writing, otherwise generate everything at once
+    let chunk_size = if stream || ndjson {
+        pool.current_num_threads()
+    } else {
+        names.len()
+    };
+
+    for chunk in names.chunks(chunk_size) {
+        let generated = pool.install(|| {
+            chunk
+                .par_iter()
+                .map(|_| generate_batch_key(&pb))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        // Insert sequentially so names, report and output stay in index order
+        for (name, key) in chunk.iter().zip(generated) {
+            let public_key_string = key_store.encode_public_key(&key.public_key, ctx.encoding);
+            let display_key = ctx.encoding.encode(&key.public_key);
+
+            // Store the key pair with an encrypted secret key (using empty password)
+            key_store.keys.insert(
+                name.clone(),
+                KeyPair {
+                    public_key: key.public_key.to_vec(),
+                    public_key_string,
+                    encrypted_secret_key: Some(key.encrypted_secret), // 使用空密码加密
+                    encrypted_mnemonic: None,
+                    metadata: KeyMetadata {
+                        created_at: Some(started_at),
+                        ..Default::default()
+                    },
+                    has_bip39_passphrase: false,
+                    registered_with: Vec::new(),
+                },
+            );
+            let item = BatchReportItem {
+                name: name.clone(),
+                status: "generated",
+                public_key: display_key.clone(),
+                fingerprint: public_key_fingerprint(&key.public_key),
+                duration_ms: key.duration_ms,
+            };
+            if ndjson {
+                let mut event = serde_json::to_value(&item)?;
+                event["event"] = "item".into();
+                event["index"] = report_items.len().into();
+                outputln!("{}", event);
+            }
+            report_items.push(item);
+            match stream_writer.as_mut() {
+                Some(writer) => {
+                    writeln!(writer, "{}", display_key)
+                        .and_then(|_| writer.flush())
+                        .with_context(|| format!("Failed to write to file: {}", output.display()))?;
+                }
+                None => public_keys_to_write.push(display_key),
+            }
+        }
+    }
+
+    pb.finish_with_message(format!("✅ Generated {} key pair(s)", count));
+
+    // Save the updated key store
+    ctx.save_key_store(&key_store)?;
+    status("💾 Key store updated with new public keys.".to_string());
+
+    // Write public keys to the output file (public_keys.txt by default) or stdout
+    if stream_writer.is_none() && !skip_file_output {
+        let mut writer = open_public_key_writer(output, append)?;
+        for pub_key_str in public_keys_to_write {
+            writeln!(writer, "{}", pub_key_str)
+                .with_context(|| format!("Failed to write to file: {}", output.display()))?;
+        }
+        writer.flush()?;
+    }
+
+    if !to_stdout && !skip_file_output {
+        status(format!("🔑 All public keys written to {}", output.display()));
+    }
+
+    if let Some(output_dir) = output_dir {
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create directory: {}", output_dir.display()))?;
+        for item in &report_items {
+            let path = output_dir.join(format!("{}.pub", item.name));
+            fs::write(&path, format!("{}\n", item.public_key))
+                .with_context(|| format!("Failed to write file: {}", path.display()))?;
+        }
+        status(format!("🔑 Public keys written to {}/<key_name>.pub", output_dir.display()));
+    }
+
+    if ndjson {
+        outputln!(
+            "{}",
+            serde_json::json!({
+                "event": "summary",
+                "requested": count,
+                "succeeded": report_items.len(),
+                "failed": count as usize - report_items.len(),
+            })
+        );
+    }
+
+    report_field("keys", &report_items);
+    if let Some(report_file) = report_file {
+        let succeeded = report_items.len() as u32;
+        let report = BatchReport {
+            schema_version: 1,
+            command: if stream { "keygen-batch-stream" } else { "batch-gen" },
+            started_at,
+            duration_ms: run_timer.elapsed().as_millis(),
+            output: match output_dir {
+                Some(output_dir) => Some(output_dir.display().to_string()),
+                None => (!skip_file_output).then(|| output.display().to_string()),
+            },
+            items: report_items,
+            totals: BatchReportTotals {
+                requested: count,
+                succeeded,
+                failed: count - succeeded,
+            },
+        };
+        write_batch_report(&report, report_file)?;
+        status(format!("📝 Report written to {}", report_file.display()));
+    }
+
+    Ok(())
+}
+
+
+/// One entry of `list-keys` in the `--json` result
+#[derive(Debug, Serialize)]
+struct KeyListing {
+    name: String,
+    public_key_string: String,
+    has_secret: bool,
+}
+
+/// One key pair of `check` in the `--json` result
+#[derive(Debug, Serialize)]
+struct KeyCheck {
+    name: String,
+    ok: bool,
+    problems: Vec<String>,
+    checks: Vec<&'static str>,
+}
+
+/// `key-info` in the `--json` result
+#[derive(Debug, Serialize)]
+struct KeyInfo<'a> {
+    name: &'a str,
+    public_key_base64: String,
+    public_key_hex: String,
+    fingerprint: String,
+    /// Whether a secret key is stored, so the key pair can sign
+    signable: bool,
+    empty_password: bool,
+    kdf: Option<KdfParams>,
+    salt_length: Option<usize>,
+    nonce_length: Option<usize>,
+    has_mnemonic: bool,
+    has_bip39_passphrase: bool,
+    metadata: &'a KeyMetadata,
+    registered_with: &'a [String],
+}
+
+fn key_info(ctx: &CliContext, name: &str) -> Result<()> {
+    let key_pair = &ctx.load_key_pair(name)?;
+    key_pair.check_access(name, KeyOperation::List)?;
+    let secret = key_pair.encrypted_secret_key.as_ref();
+    let info = KeyInfo {
+        name,
+        public_key_base64: KeyEncoding::Base64.encode(&key_pair.public_key),
+        public_key_hex: KeyEncoding::Hex.encode(&key_pair.public_key),
+        fingerprint: public_key_fingerprint(&key_pair.public_key),
+        signable: secret.is_some(),
+        empty_password: secret.is_some_and(|secret| secret.empty_password),
+        kdf: secret.map(|secret| secret.kdf),
+        salt_length: secret.map(|secret| secret.salt.len()),
+        nonce_length: secret.map(|secret| secret.nonce.len()),
+        has_mnemonic: key_pair.encrypted_mnemonic.is_some(),
+        has_bip39_passphrase: key_pair.has_bip39_passphrase,
+        metadata: &key_pair.metadata,
+        registered_with: &key_pair.registered_with,
+    };
+
+    if json_output() {
+        report_field("key", &info);
+        return Ok(());
+    }
+
+    outputln!("🔑 Key pair '{}'", info.name);
+    outputln!("   Public key (base64): {}", info.public_key_base64);
+    outputln!("   Public key (hex):    {}", info.public_key_hex);
+    outputln!("   Fingerprint:         {}", info.fingerprint);
+    match secret {
+        Some(secret) => {
+            let protection = if secret.empty_password { "⚠️  empty password" } else { "password protected" };
+            outputln!("   Can sign:            yes ({})", protection);
+            let kdf = match secret.kdf {
+                KdfParams::Pbkdf2 { iterations } => format!("pbkdf2, {} iterations", iterations),
+                KdfParams::Argon2id { m_cost, t_cost, p_cost } => {
+                    format!("argon2id, m_cost {} KiB, t_cost {}, p_cost {}", m_cost, t_cost, p_cost)
+                }
+            };
+            outputln!("   KDF:                 {}", kdf);
+            outputln!("   Salt length:         {} bytes", secret.salt.len());
+            outputln!("   Nonce length:        {} bytes", secret.nonce.len());
+        }
+        None => outputln!("   Can sign:            no (public key only)"),
+    }
+    if let Some(standard) = key_pair.metadata.hd_standard {
+        outputln!(
+            "   HD derivation:       {:?} at {}",
+            standard,
+            key_pair.metadata.derivation_path.as_deref().unwrap_or(DEFAULT_DERIVATION_PATH)
+        );
+    }
+    if let Some(rotated_to) = &key_pair.metadata.rotated_to {
+        outputln!("   Rotated to:          '{}'", rotated_to);
+    }
+    if let Some(acl) = &key_pair.metadata.acl {
+        let allowed: Vec<&str> = acl.iter().map(KeyOperation::name).collect();
+        outputln!("   Access list:         {}", allowed.join(", "));
+    }
+    for url in &key_pair.registered_with {
+        outputln!("   Registered with:     {}", url);
+    }
+    Ok(())
+}
+
+fn list_keys(ctx: &CliContext) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    // Key pairs whose access list leaves out `list` are only usable by name
+    let total = key_store.keys.len();
+    key_store.keys.retain(|_, key_pair| key_pair.allows(KeyOperation::List));
+    let hidden = total - key_store.keys.len();
+
+    if json_output() {
+        let mut listings: Vec<KeyListing> = key_store
+            .keys
+            .iter()
+            .map(|(name, key_pair)| KeyListing {
+                name: name.clone(),
+                public_key_string: ctx.encoding.encode(&key_pair.public_key),
+                has_secret: key_pair.encrypted_secret_key.is_some(),
+            })
+            .collect();
+        listings.sort_by(|a, b| a.name.cmp(&b.name));
+        report_field("keys", &listings);
+        return Ok(());
+    }
+
+    if key_store.keys.is_empty() && hidden == 0 {
+        println!("No key pairs found. Generate one with 'generate-key' command.");
+        return Ok(());
+    }
+    if hidden > 0 {
+        println!("🔒 {} key pair(s) not shown, their access list doesn't allow listing", hidden);
+    }
+
+    println!("Available key pairs:");
+    for (name, key_pair) in key_store.keys {
+        let secret_status = match &key_pair.encrypted_secret_key {
+            Some(encrypted) if encrypted.empty_password => "⚠️  UNPROTECTED (empty password)",
+            Some(_) => "(secret encrypted)",
+            None => "(secret not stored/encrypted)",
+        };
+        let rotated = match &key_pair.metadata.rotated_to {
+            Some(rotated_to) => format!(" 🔁 rotated to '{}'", rotated_to),
+            None => String::new(),
+        };
+        outputln!(
+            "- {} (Public key: {}) {}{}",
+            name,
+            ctx.encoding.encode(&key_pair.public_key),
+            secret_status,
+            rotated
+        );
+    }
+    Ok(())
+}
+
+// The raw JSON, since a corrupt store may not deserialize into a KeyStore
+fn read_raw_key_store(ctx: &CliContext) -> Result<String> {
+    match &ctx.ephemeral_store {
+        Some(_) => Ok(serde_json::to_string(&ctx.load_key_store()?)?),
+        None => {
+            ctx.check_key_store_size()?;
+            let contents = fs::read_to_string(&ctx.key_store_path)
+                .with_context(|| format!("Failed to read key store: {}", ctx.key_store_path.display()))?;
+            match KeyStoreEnvelope::parse(&contents) {
+                Some(envelope) => envelope.open(&ctx.store_password(false)?),
+                None => ctx.key_store_format.to_json(&contents),
+            }
+        }
+    }
+}
+
+// Returns whether the store is valid, so main can set the exit code
+fn validate_key_store(ctx: &CliContext) -> Result<bool> {
+    let contents = read_raw_key_store(ctx)?;
+    let problems = soundness_layer::validate_key_store(&contents)?;
+    if problems.is_empty() {
+        println!("✅ Key store {} is valid", ctx.key_store_path.display());
+        return Ok(true);
+    }
+
+    println!("❌ Found {} problem(s) in {}:", problems.len(), ctx.key_store_path.display());
+    for problem in &problems {
+        outputln!("- {}", problem);
+    }
+    Ok(false)
+}
+
+// validate-key-store's field checks, then the deeper ones from KeyPair::check_structure and,
+// with --decrypt, a full decryption. Returns whether every key pair passed
+fn check_key_store(ctx: &CliContext, verbose: bool, decrypt: bool) -> Result<bool> {
+    let contents = read_raw_key_store(ctx)?;
+    let problems = soundness_layer::validate_key_store(&contents)?;
+    let store: serde_json::Value = serde_json::from_str(&contents)?;
+    let keys = store["keys"].as_object().cloned().unwrap_or_default();
+    let mut names: Vec<&String> = keys.keys().collect();
+    names.sort();
+
+    let mut password = None;
+    let mut results = Vec::new();
+    for name in names {
+        let mut key_problems: Vec<String> =
+            problems.iter().filter(|p| &p.key_name == name).map(|p| p.message.clone()).collect();
+        let mut checks = vec!["fields", "public_key_string", "salt and nonce lengths"];
+        if let Ok(key_pair) = serde_json::from_value::<KeyPair>(keys[name].clone()) {
+            key_problems.extend(key_pair.check_structure());
+            checks.extend(["ed25519 point", "KDF parameters", "ciphertext lengths"]);
+            // Decrypting what is already known to be broken would only add noise
+            let secret = key_pair.encrypted_secret_key.as_ref().filter(|_| decrypt && key_problems.is_empty());
+            if let Some(secret) = secret {
+                if !secret.empty_password && password.is_none() {
+                    let entered = read_password(&ctx.password_source, "Enter password to decrypt the secret keys: ")?;
+                    password = Some(Zeroizing::new(entered));
+                }
+                let password = match &password {
+                    Some(password) if !secret.empty_password => password.as_str(),
+                    _ => "",
+                };
+                if let Err(e) = key_pair.check_encrypted_secret(password) {
+                    key_problems.push(e.to_string());
+                }
+                if let Some(mnemonic) = &key_pair.encrypted_mnemonic {
+                    if decrypt_secret_key(mnemonic, password).is_err() {
+                        key_problems.push("the encrypted mnemonic does not decrypt".to_string());
+                    }
+                }
+                checks.push("decryption");
+            }
+        }
+        results.push(KeyCheck {
+            name: name.clone(),
+            ok: key_problems.is_empty(),
+            problems: key_problems,
+            checks,
+        });
+    }
+
+    let width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max("KEY PAIR".len());
+    println!("🔎 Checking {}\n", ctx.key_store_path.display());
+    println!("{:<width$}  STATUS", "KEY PAIR");
+    for result in &results {
+        match result.problems.split_first() {
+            None => outputln!("{:<width$}  ✅ ok", result.name),
+            Some((first, rest)) => {
+                outputln!("{:<width$}  ❌ {}", result.name, first);
+                for problem in rest {
+                    outputln!("{:<width$}     {}", "", problem);
+                }
+            }
+        }
+        if verbose {
+            println!("{:<width$}     checked: {}", "", result.checks.join(", "));
+        }
+    }
+    let failed = results.iter().filter(|result| !result.ok).count();
+    println!(
+        "\n{} key pair(s) checked: {} ok, {} with problems",
+        results.len(),
+        results.len() - failed,
+        failed
+    );
+    report_field("keys", &results);
+    Ok(failed == 0)
+}
+
+fn heal_key_store(ctx: &CliContext, remove_corrupt: bool, dry_run: bool) -> Result<()> {
+    if !remove_corrupt {
+        anyhow::bail!("Nothing to do. Pass --remove-corrupt to remove the entries reported by validate-key-store");
+    }
+
+    let contents = read_raw_key_store(ctx)?;
+    let problems = soundness_layer::validate_key_store(&contents)?;
+    let corrupt: BTreeSet<&str> = problems.iter().map(|p| p.key_name.as_str()).collect();
+    if corrupt.is_empty() {
+        println!("✅ Key store {} has no corrupt entries", ctx.key_store_path.display());
+        return Ok(());
+    }
+
+    println!("🩹 {} corrupt key pair(s) in {}:", corrupt.len(), ctx.key_store_path.display());
+    for name in &corrupt {
+        println!("- {}", name);
+        for problem in problems.iter().filter(|p| p.key_name == *name) {
+            println!("    {}", problem.message);
+        }
+    }
+    if dry_run {
+        println!("\n🔍 Dry run: nothing was removed");
+        return Ok(());
+    }
+    ctx.confirm(&format!("Remove {} key pair(s) from {}?", corrupt.len(), ctx.key_store_path.display()))?;
+
+    let mut store: serde_json::Value = serde_json::from_str(&contents)?;
+    let keys = store["keys"].as_object_mut().unwrap();
+    let removed: serde_json::Map<String, serde_json::Value> = corrupt
+        .iter()
+        .filter_map(|name| keys.remove(*name).map(|entry| (name.to_string(), entry)))
+        .collect();
+
+    // Archive before touching the store, so nothing is lost if saving fails. The entries
+    // still hold encrypted secrets, so keep the archive owner-only
+    let archive_path = ctx
+        .key_store_path
+        .with_file_name(format!("key_store_corrupt_{}.json", soundness_layer::unix_timestamp()));
+    let mut archive = create_private_file(&archive_path)?;
+    archive
+        .write_all(serde_json::to_string_pretty(&serde_json::json!({ "keys": removed }))?.as_bytes())
+        .with_context(|| format!("Failed to write to file: {}", archive_path.display()))?;
+
+    let healed: KeyStore = serde_json::from_value(store)
+        .map_err(|e| anyhow::anyhow!("Healed key store is still invalid: {}", e))?;
+    ctx.save_key_store(&healed)?;
+
+    println!("\n📦 Archived removed entries to {}", archive_path.display());
+    println!("✅ Removed {} key pair(s), {} remaining", removed.len(), healed.keys.len());
+    Ok(())
+}
+
+fn compact_key_store(ctx: &CliContext) -> Result<()> {
+    // Re-serializing drops whatever doesn't parse, so only compact a store that is fully valid
+    let contents = read_raw_key_store(ctx)?;
+    let problems = soundness_layer::validate_key_store(&contents)?;
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Key store {} has {} problem(s). Run validate-key-store for details and heal-key-store --remove-corrupt before compacting",
+            ctx.key_store_path.display(),
+            problems.len()
+        );
+    }
+
+    // Parsing also collapses duplicate names, keeping the last entry like every other command does
+    let size_before = fs::metadata(&ctx.key_store_path).map(|m| m.len()).ok();
+    let key_store = ctx.load_key_store()?;
+    ctx.save_key_store(&key_store)?;
+    let size_after = fs::metadata(&ctx.key_store_path).map(|m| m.len()).ok();
+
+    println!("🧹 Compacted {} key pair(s) in {}", key_store.keys.len(), ctx.key_store_path.display());
+    if let (Some(before), Some(after)) = (size_before, size_after) {
+        println!("📏 {} → {} bytes", before, after);
+    }
+    Ok(())
+}
+
+fn convert_key_store(ctx: &CliContext, to: KeyStoreFormat, output: Option<&Path>) -> Result<()> {
+    if ctx.ephemeral_store.is_some() {
+        anyhow::bail!("An --ephemeral key store has no file to convert");
+    }
+    if ctx.is_store_encrypted()? {
+        anyhow::bail!("An encrypted key store is always saved as one encrypted document, there is nothing to convert");
+    }
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| ctx.key_store_path.with_extension(to.extension()));
+    if output.exists() && output != ctx.key_store_path {
+        anyhow::bail!("{} already exists, pick another --output", output.display());
+    }
+
+    let key_store = ctx.load_key_store()?;
+    soundness_layer::save_key_store_as(&key_store, &output, to)?;
+    report_field("output", &output);
+    report_field("key_count", key_store.keys.len());
+    outputln!(
+        "🔄 Converted {} key pair(s) to {} in {}",
+        key_store.keys.len(),
+        to.extension(),
+        output.display()
+    );
+    if output != ctx.key_store_path {
+        let format_flag = if KeyStoreFormat::from_path(&output) == to {
+            String::new()
+        } else {
+            format!(" --key-store-format {}", to.extension())
+        };
+        println!("💡 Use it with --key-store {}{}", output.display(), format_flag);
+    }
+    Ok(())
+}
+
+// Create a file that will hold encrypted secrets: owner-only, and never over an existing one
+fn create_private_file(path: &Path) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .with_context(|| format!("Failed to create file: {}", path.display()))
+}
+
+fn backup_key_store(
+    ctx: &CliContext,
+    since: Option<u64>,
+    output: Option<&Path>,
+    backup_password_source: &SecretSource,
+) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+    let timestamp = soundness_layer::unix_timestamp();
+
+    let mut backup = key_store.clone();
+    let file_name = match since {
+        Some(since) => {
+            backup.keys.retain(|_, key_pair| key_pair.changed_since(since));
+            if backup.keys.is_empty() {
+                println!("✅ No key pairs changed since {}, nothing to back up", since);
+                return Ok(());
+            }
+            format!("backup_incremental_{}.json", timestamp)
+        }
+        None => format!("backup_{}.json", timestamp),
+    };
+
+    let (backup_path, contents) = match output {
+        Some(output) => {
+            let password = read_new_password(backup_password_source, "Enter backup password: ")?;
+            let pb = create_progress_bar("🔐 Encrypting backup...");
+            let contents = soundness_layer::encrypt_key_store_backup(&backup, &password)?;
+            pb.finish_and_clear();
+            (output.to_path_buf(), contents)
+        }
+        None => (
+            ctx.key_store_path.with_file_name(file_name),
+            ctx.render_key_store(&backup)?.into_bytes(),
+        ),
+    };
+    let mut file = create_private_file(&backup_path)?;
+    file.write_all(&contents)
+        .with_context(|| format!("Failed to write to file: {}", backup_path.display()))?;
+    report_field("backup_file", &backup_path);
+    report_field("key_pairs", backup.keys.len());
+
+    println!(
+        "📦 Backed up {} of {} key pair(s) to {}",
+        backup.keys.len(),
+        key_store.keys.len(),
+        backup_path.display()
+    );
+    let undated = backup
+        .keys
+        .values()
+        .filter(|key_pair| key_pair.metadata.created_at.is_none() && key_pair.metadata.modified_at.is_none())
+        .count();
+    if since.is_some() && undated > 0 {
+        println!("⚠️  {} key pair(s) predate change tracking and are included every time", undated);
+    }
+    println!("💡 Continue with: backup --incremental --since {}", timestamp);
+    Ok(())
+}
+
+fn restore_key_store(
+    ctx: &CliContext,
+    input: &Path,
+    backup_password_source: &SecretSource,
+    force: bool,
+    merge: bool,
+    overwrite: bool,
+) -> Result<()> {
+    let backup = fs::read(input).with_context(|| format!("Failed to read backup: {}", input.display()))?;
+    // Catch the wrong file before asking for a password it could never match
+    if !backup.starts_with(soundness_layer::key_store::BACKUP_MAGIC) {
+        anyhow::bail!(
+            "{} is not a key store backup, expected a file written by `backup --output`",
+            input.display()
+        );
+    }
+    let mut current = ctx.load_key_store()?;
+    if !current.keys.is_empty() && !force && !merge {
+        anyhow::bail!(
+            "Key store {} already holds {} key pair(s), pass --merge to add to it or --force to replace it",
+            ctx.key_store_path.display(),
+            current.keys.len()
+        );
+    }
+    if !current.keys.is_empty() && !merge {
+        ctx.confirm(&format!(
+            "Replace the {} key pair(s) in {} with the backup?",
+            current.keys.len(),
+            ctx.key_store_path.display()
+        ))?;
+    }
+
+    let password = read_password(backup_password_source, "Enter backup password: ")?;
+    let pb = create_progress_bar("🔓 Decrypting backup...");
+    let key_store = match soundness_layer::decrypt_key_store_backup(&backup, &password) {
+        Ok(key_store) => key_store,
+        Err(e) => {
+            pb.abandon_with_message("❌ Restore failed, the key store was not touched");
+            return Err(e);
+        }
+    };
+    pb.finish_and_clear();
+
+    if !merge {
+        ctx.save_key_store(&key_store)?;
+        report_field("key_pairs", key_store.keys.len());
+        println!(
+            "📦 Restored {} key pair(s) from {} to {}",
+            key_store.keys.len(),
+            input.display(),
+            ctx.key_store_path.display()
+        );
+        return Ok(());
+    }
+
+    let mut conflicts: Vec<&String> = key_store.keys.keys().filter(|name| current.keys.contains_key(*name)).collect();
+    conflicts.sort();
+    let names = conflicts.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+    if !conflicts.is_empty() && !overwrite {
+        anyhow::bail!("Key pair(s) {} already exist, pass --overwrite to replace them with the backed-up ones", names);
+    }
+    if !conflicts.is_empty() {
+        ctx.confirm(&format!("Replace key pair(s) {} with the backed-up ones?", names))?;
+    }
+    let replaced = conflicts.len();
+    let added = key_store.keys.len() - replaced;
+    current.keys.extend(key_store.keys);
+    ctx.save_key_store(&current)?;
+    report_field("added", added);
+    report_field("replaced", replaced);
+
+    println!(
+        "📦 Merged {} key pair(s) from {} into {}: {} added, {} replaced",
+        added + replaced,
+        input.display(),
+        ctx.key_store_path.display(),
+        added,
+        replaced
+    );
+    Ok(())
+}
+
+// SHA-256 of the name mod `count`, so a key pair lands in the same shard on every split
+fn shard_index(name: &str, count: u32) -> usize {
+    let digest = Sha256::digest(name.as_bytes());
+    (u64::from_be_bytes(digest[..8].try_into().unwrap()) % u64::from(count)) as usize
+}
+
+fn shard_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("shard_{}.json", index))
+}
+
+fn split_key_store(ctx: &CliContext, count: u32, output_dir: &Path) -> Result<()> {
+    if count == 0 {
+        anyhow::bail!("--count must be at least 1");
+    }
+    let paths: Vec<PathBuf> = (0..count as usize).map(|index| shard_path(output_dir, index)).collect();
+    if let Some(existing) = paths.iter().find(|path| path.exists()) {
+        anyhow::bail!("{} already exists, split into an empty directory", existing.display());
+    }
+
+    let key_store = ctx.load_key_store()?;
+    let total = key_store.keys.len();
+    let mut shards = vec![
+        KeyStore {
+            display_encoding: key_store.display_encoding,
+            ..KeyStore::default()
+        };
+        count as usize
+    ];
+    for (name, key_pair) in key_store.keys {
+        shards[shard_index(&name, count)].keys.insert(name, key_pair);
+    }
+
+    // Shards of an encrypted store stay encrypted, under the same store password
+    let store_password = if ctx.is_store_encrypted()? { Some(ctx.store_password(false)?) } else { None };
+    let pb = new_progress_bar(u64::from(count));
+    for (shard, path) in shards.iter().zip(&paths) {
+        match &store_password {
+            Some(password) => soundness_layer::save_encrypted_key_store(shard, path, password)?,
+            None => soundness_layer::save_key_store(shard, path)?,
+        }
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    let sizes: Vec<usize> = shards.iter().map(|shard| shard.keys.len()).collect();
+    report_field("shards", &paths);
+    report_field("key_pairs", &sizes);
+    println!(
+        "✂️  Split {} key pair(s) from {} into {} shard(s):",
+        total,
+        ctx.key_store_path.display(),
+        count
+    );
+    for (path, size) in paths.iter().zip(&sizes) {
+        outputln!("- {} ({} key pairs)", path.display(), size);
+    }
+    Ok(())
+}
+
+fn merge_key_stores(ctx: &CliContext, input_dir: &Path, overwrite: bool) -> Result<()> {
+    let mut shards = BTreeMap::new();
+    for entry in fs::read_dir(input_dir).with_context(|| format!("Failed to read directory: {}", input_dir.display()))? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("shard_")?.strip_suffix(".json")?.parse::<usize>().ok());
+        if let Some(index) = index {
+            shards.insert(index, path);
+        }
+    }
+    if shards.is_empty() {
+        anyhow::bail!("No shard_<N>.json files in {}", input_dir.display());
+    }
+    // Shards are numbered from 0 without gaps, so a gap is a shard that didn't make it here
+    if let Some(missing) = (0..shards.len()).find(|index| !shards.contains_key(index)) {
+        anyhow::bail!(
+            "{} is missing, merging without it would leave out its key pairs",
+            shard_path(input_dir, missing).display()
+        );
+    }
+
+    let mut current = ctx.load_key_store()?;
+    let mut conflicts = Vec::new();
+    let mut replaced = Vec::new();
+    let mut added = 0;
+    for path in shards.values() {
+        let shard = if soundness_layer::is_encrypted_key_store(path)? {
+            soundness_layer::load_encrypted_key_store(path, &ctx.store_password(false)?)?
+        } else {
+            soundness_layer::load_key_store(path)?
+        };
+        let encoding = shard.display_encoding.unwrap_or_default();
+        for (name, mut key_pair) in shard.keys {
+            if current.keys.contains_key(&name) {
+                if !overwrite {
+                    conflicts.push(name);
+                    continue;
+                }
+                replaced.push(format!("'{}'", name));
+            } else {
+                added += 1;
+            }
+            // Shards split from another store may display public keys in another encoding
+            key_pair.public_key_string = current.encode_public_key(&key_pair.public_key, encoding);
+            current.keys.insert(name, key_pair);
+        }
+    }
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        anyhow::bail!(
+            "Key pair(s) {} already exist, pass --overwrite to replace them with the ones from the shards",
+            conflicts.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ")
+        );
+    }
+    if !replaced.is_empty() {
+        replaced.sort();
+        ctx.confirm(&format!("Replace key pair(s) {} with the ones from the shards?", replaced.join(", ")))?;
+    }
+    ctx.save_key_store(&current)?;
+    report_field("added", added);
+    report_field("replaced", replaced.len());
+
+    println!(
+        "🧩 Merged {} key pair(s) from {} shard(s) in {} into {}: {} added, {} replaced",
+        added + replaced.len(),
+        shards.len(),
+        input_dir.display(),
+        ctx.key_store_path.display(),
+        added,
+        replaced.len()
+    );
+    Ok(())
+}
+
+fn export_public_key(
+    ctx: &CliContext,
+    name: &str,
+    format: Option<PublicKeyFormat>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let key_pair = ctx.load_key_pair(name)?;
+    key_pair.check_access(name, KeyOperation::List)?;
+    let public_key = key_pair.public_key;
+    let format = format.unwrap_or(match ctx.encoding {
+        KeyEncoding::Base64 => PublicKeyFormat::Base64,
+        KeyEncoding::Hex => PublicKeyFormat::Hex,
+    });
+
+    let bytes = match format {
+        PublicKeyFormat::Base64 => format!("{}\n", KeyEncoding::Base64.encode(&public_key)).into_bytes(),
+        PublicKeyFormat::Hex => format!("{}\n", KeyEncoding::Hex.encode(&public_key)).into_bytes(),
+        PublicKeyFormat::Raw => public_key,
+        PublicKeyFormat::Openssh => format!("{}\n", sshsig::openssh_public_key(&public_key)).into_bytes(),
+    };
+
+    match output {
+        Some(output) => fs::write(output, bytes)
+            .with_context(|| format!("Failed to write file: {}", output.display()))?,
+        None if json_output() => {
+            let text = match format {
+                PublicKeyFormat::Raw => BASE64.encode(&bytes),
+                _ => String::from_utf8_lossy(&bytes).trim_end().to_string(),
+            };
+            report_field("public_key", text);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&bytes)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// Stream public keys straight to a buffered writer, so output memory doesn't grow with the store
+fn export_public_keys(ctx: &CliContext, output: &Path) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    let to_stdout = output == Path::new("-");
+    // Key pairs hidden from `list-keys` are left out here too
+    let total = key_store.keys.len();
+    key_store.keys.retain(|_, key_pair| key_pair.allows(KeyOperation::List));
+    let hidden = total - key_store.keys.len();
+
+    let mut names: Vec<&String> = key_store.keys.keys().collect();
+    names.sort();
+
+    let pb = new_progress_bar(names.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} keys, {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+    );
+
+    let mut writer = std::io::BufWriter::new(open_public_key_writer(output, false)?);
+    let mut bytes_written = 0u64;
+    for (i, name) in names.into_iter().enumerate() {
+        let line = ctx.encoding.encode(&key_store.keys[name].public_key);
+        writeln!(writer, "{}", line)
+            .with_context(|| format!("Failed to write to file: {}", output.display()))?;
+        bytes_written += line.len() as u64 + 1;
+
+        // Redrawing per key would dominate the runtime for huge stores
+        if i % 1024 == 0 {
+            pb.set_position(i as u64);
+            pb.set_message(indicatif::HumanBytes(bytes_written).to_string());
+        }
+    }
+    writer.flush()?;
+    pb.finish_and_clear();
+
+    let mut summary = format!(
+        "🔑 Exported {} public key(s) ({}){}",
+        key_store.keys.len(),
+        indicatif::HumanBytes(bytes_written),
+        if to_stdout { String::new() } else { format!(" to {}", output.display()) }
+    );
+    if hidden > 0 {
+        summary.push_str(&format!(
+            "\n🔒 {} key pair(s) not exported, their access list doesn't allow listing",
+            hidden
+        ));
+    }
+    // Keep stdout clean for the keys themselves
+    if to_stdout {
+        eprintln!("{}", summary);
+    } else {
+        println!("{}", summary);
+    }
+    Ok(())
+}
+
+fn sign_payload(ctx: &CliContext, payload: &[u8], key_name: &str) -> Result<Vec<u8>> {
+    let key_pair = ctx.load_key_pair(key_name)?;
+    key_pair.check_access(key_name, KeyOperation::Sign)?;
+    // Only this key pair is loaded, so the cached password is tied to it rather than the whole store
+    let key_pair_hash = format!("{:x}", Sha256::digest(serde_json::to_vec(&key_pair)?));
+
+    let encrypted_secret = key_pair
+        .encrypted_secret_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Only the public key is stored, so it cannot be used for signing.", key_name))?;
+
+    // Batch keys are encrypted with an empty password, so there is nothing to prompt for
+    let password = if encrypted_secret.empty_password {
+        Zeroizing::new(String::new())
+    } else {
+        // The else block scopes the password guard so it's dropped before signing
+        let mut password_guard = PASSWORD_CACHE.lock().unwrap();
+
+        if let Some((stored_password, stored_hash)) = password_guard.as_ref() {
+            // Check if the key pair has changed
+            if stored_hash != &key_pair_hash {
                 *password_guard = None;
                 drop(password_guard);
-                return sign_payload(payload, key_name);
+                return sign_payload(ctx, payload, key_name);
+            }
+            stored_password.clone()
+        } else {
+            // If no password is stored, prompt for it
+            let new_password =
+                Zeroizing::new(read_password(&ctx.password_source, "Enter password to decrypt the secret key: ")?);
+
+            // Try to decrypt with the password to verify it's correct
+            if let Err(e) = decrypt_secret_key(encrypted_secret, &new_password) {
+                anyhow::bail!("Invalid password: {}", e);
+            }
+
+            // Store the password and key pair hash
+            *password_guard = Some((new_password.clone(), key_pair_hash));
+            new_password
+        }
+    }; // password_guard is dropped here
+
+    if let Some(rotated_to) = &key_pair.metadata.rotated_to {
+        eprintln!(
+            "⚠️  Key pair '{}' was rotated, new signatures should come from '{}'",
+            key_name, rotated_to
+        );
+    }
+
+    // Anyone who can read the key store can sign with such a key, so say so before it's used
+    if password.is_empty() {
+        if ctx.fail_on_empty_password {
+            anyhow::bail!(
+                "Key pair '{}' is protected by an empty password, refusing to sign with --fail-on-empty-password",
+                key_name
+            );
+        }
+        eprintln!(
+            "⚠️  WARNING: Key pair '{}' is protected by an EMPTY password. Anyone who can read the key store can sign with it!",
+            key_name
+        );
+    }
+
+    // Only show the progress bar after we have the password
+    let pb = create_progress_bar("✍️  Signing payload...");
+
+    let secret_key_bytes = decrypt_secret_key(encrypted_secret, &password)?;
+    if let Err(e) = key_pair.check_secret_key(&secret_key_bytes) {
+        pb.abandon();
+        anyhow::bail!("Refusing to sign with '{}': {}", key_name, e);
+    }
+    let signature = soundness_layer::sign_payload(&secret_key_bytes, payload)?;
+    pb.finish_with_message("✍️  Payload signed successfully");
+
+    Ok(signature.to_bytes().to_vec())
+}
+
+fn get_public_key(ctx: &CliContext, key_name: &str) -> Result<Vec<u8>> {
+    Ok(ctx.load_key_pair(key_name)?.public_key)
+}
+
+// Write a detached signature for `message_file`. Without --output only the signature goes
+// to stdout so it can be captured, and everything else is printed to stderr
+fn sign_message(ctx: &CliContext, key_name: &str, message_file: &Path, output: Option<&Path>) -> Result<()> {
+    let message = fs::read(message_file)
+        .with_context(|| format!("Failed to read message file: {}", message_file.display()))?;
+    let signature = ctx.encoding.encode(&sign_payload(ctx, &message, key_name)?);
+    let public_key = ctx.encoding.encode(&get_public_key(ctx, key_name)?);
+    report_field("signature", &signature);
+    report_field("public_key", &public_key);
+
+    match output {
+        Some(output) => {
+            fs::write(output, format!("{}\n", signature))
+                .with_context(|| format!("Failed to write signature file: {}", output.display()))?;
+            println!("🔑 Public key: {}", public_key);
+            println!("✍️  Signature written to {}", output.display());
+        }
+        None => {
+            eprintln!("🔑 Public key: {}", public_key);
+            outputln!("{}", signature);
+        }
+    }
+    Ok(())
+}
+
+/// A detached signature over a file's SHA-256, as written by `sign-file`
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct FileSignature {
+    /// Hex SHA-256 of the file; the signature is over the 32 raw digest bytes
+    file_hash: String,
+    signature: String,
+    public_key: String,
+    algorithm: String,
+}
+
+const FILE_SIGNATURE_ALGORITHM: &str = "ed25519-sha256";
+
+fn default_signature_path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+// Only the digest is signed, so the file is hashed from disk instead of being read into memory
+fn write_file_signature(ctx: &CliContext, key_name: &str, input: &Path, output: Option<&Path>) -> Result<()> {
+    let file_hash = sha256_file(input)?;
+    let digest = hex::decode(&file_hash)?;
+    let public_key = get_public_key(ctx, key_name)?;
+    let signature = FileSignature {
+        file_hash,
+        signature: BASE64.encode(sign_payload(ctx, &digest, key_name)?),
+        public_key: BASE64.encode(&public_key),
+        algorithm: FILE_SIGNATURE_ALGORITHM.to_string(),
+    };
+
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| default_signature_path(input));
+    fs::write(&output, format!("{}\n", serde_json::to_string_pretty(&signature)?))
+        .with_context(|| format!("Failed to write signature file: {}", output.display()))?;
+    println!("🔑 Public key: {}", ctx.encoding.encode(&public_key));
+    println!("✍️  Signature written to {}", output.display());
+    report_field("signature_file", &output);
+    Ok(())
+}
+
+// Returns whether the file matches a signature by `public_key`, so main can set the exit code
+fn verify_file_signature(ctx: &CliContext, input: &Path, sig: Option<&Path>, public_key: &str) -> Result<bool> {
+    let expected_key = ctx
+        .decode(ctx.encoding, public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    let sig = sig.map(Path::to_path_buf).unwrap_or_else(|| default_signature_path(input));
+    let contents =
+        fs::read_to_string(&sig).with_context(|| format!("Failed to read signature file: {}", sig.display()))?;
+    let signature: FileSignature = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid signature file: {}", sig.display()))?;
+    if signature.algorithm != FILE_SIGNATURE_ALGORITHM {
+        anyhow::bail!(
+            "Unsupported signature algorithm {}, expected {}",
+            signature.algorithm,
+            FILE_SIGNATURE_ALGORITHM
+        );
+    }
+    let signature_bytes = BASE64
+        .decode(&signature.signature)
+        .map_err(|e| anyhow::anyhow!("Invalid signature in {}: {}", sig.display(), e))?;
+
+    let file_hash = sha256_file(input)?;
+    let valid = if !file_hash.eq_ignore_ascii_case(&signature.file_hash) {
+        println!("❌ {} does not match the signed hash", input.display());
+        false
+    } else if BASE64.decode(&signature.public_key).ok().as_deref() != Some(&expected_key[..]) {
+        println!("❌ Signed by a different key than --public-key");
+        false
+    } else if soundness_layer::verify_signature(&expected_key, &signature_bytes, &hex::decode(&file_hash)?)? {
+        println!("✅ Signature valid for {}", input.display());
+        true
+    } else {
+        println!("❌ Signature invalid");
+        false
+    };
+    println!("🔏 Public key fingerprint: {}", public_key_fingerprint(&expected_key));
+    Ok(valid)
+}
+
+// The key pair whose public key is in an OpenSSH public key file, as Git passes with -f
+fn key_name_for_openssh_key(ctx: &CliContext, key_file: &Path) -> Result<String> {
+    let line = fs::read_to_string(key_file)
+        .with_context(|| format!("Failed to read SSH public key file: {}", key_file.display()))?;
+    let line = line.trim().strip_prefix("key::").unwrap_or(line.trim());
+    let public_key = sshsig::parse_openssh_public_key(line)?;
+    let key_store = ctx.load_key_store()?;
+    let mut names: Vec<&String> = key_store
+        .keys
+        .iter()
+        .filter(|(_, key_pair)| key_pair.public_key == public_key)
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names
+        .first()
+        .map(|name| name.to_string())
+        .ok_or_else(|| anyhow::anyhow!("No key pair in the key store matches {}", line))
+}
+
+// Sign like `ssh-keygen -Y sign`: from stdin to stdout, or FILE to FILE.sig
+fn sign_commit(
+    ctx: &CliContext,
+    key_name: Option<&str>,
+    key_file: Option<&Path>,
+    namespace: &str,
+    file: Option<&Path>,
+) -> Result<()> {
+    let key_name = match (key_name, key_file) {
+        (Some(key_name), _) => key_name.to_string(),
+        (None, Some(key_file)) => key_name_for_openssh_key(ctx, key_file)?,
+        (None, None) => anyhow::bail!("Pass --key-name, or -f with the OpenSSH public key of the key pair"),
+    };
+
+    let data = match file {
+        Some(file) => fs::read(file).with_context(|| format!("Failed to read file: {}", file.display()))?,
+        None => {
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut data).context("Failed to read stdin")?;
+            data
+        }
+    };
+
+    let signature = sign_payload(ctx, &sshsig::signed_data(namespace, &data), &key_name)?;
+    let armored = sshsig::armor(&get_public_key(ctx, &key_name)?, namespace, &signature);
+    match file {
+        Some(file) => {
+            let mut signature_path = file.as_os_str().to_owned();
+            signature_path.push(".sig");
+            let signature_path = PathBuf::from(signature_path);
+            fs::write(&signature_path, armored)
+                .with_context(|| format!("Failed to write signature file: {}", signature_path.display()))?;
+        }
+        None if json_output() => report_field("signature", &armored),
+        None => print!("{}", armored),
+    }
+    Ok(())
+}
+
+// PKCS#8 (RFC 8410, with the public key included) and SPKI are what `openssl pkey` and most PEM loaders read
+fn export_pem(ctx: &CliContext, name: &str, output: Option<&Path>) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+    let key_pair = key_store
+        .keys
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))?;
+    key_pair.check_access(name, KeyOperation::Export)?;
+    let encrypted_secret = key_pair
+        .encrypted_secret_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Cannot export it as PEM.", name))?;
+
+    let password = if encrypted_secret.empty_password {
+        String::new()
+    } else {
+        read_password(&ctx.password_source, "Enter password to decrypt the secret key: ")?
+    };
+    let secret_key = decrypt_secret_key(encrypted_secret, &password)
+        .map_err(|_| anyhow::anyhow!("Invalid password for key pair '{}'", name))?;
+    key_pair
+        .check_secret_key(&secret_key)
+        .with_context(|| format!("Refusing to export '{}'", name))?;
+    let secret_key: [u8; 32] = secret_key
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
+    let signing_key = SigningKey::from_bytes(&secret_key);
+    let private_pem = signing_key
+        .to_pkcs8_pem(LineEnding::LF)
+        .map_err(|e| anyhow::anyhow!("Failed to encode the private key as PKCS#8: {}", e))?;
+    let public_pem = signing_key
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)
+        .map_err(|e| anyhow::anyhow!("Failed to encode the public key as PEM: {}", e))?;
+
+    let private_path = output.map_or_else(|| PathBuf::from(format!("{}.pem", name)), Path::to_path_buf);
+    let public_path = private_path.with_extension("pub.pem");
+    if public_path.exists() {
+        anyhow::bail!("{} already exists", public_path.display());
+    }
+    create_private_file(&private_path)?
+        .write_all(private_pem.as_bytes())
+        .with_context(|| format!("Failed to write to file: {}", private_path.display()))?;
+    fs::write(&public_path, public_pem)
+        .with_context(|| format!("Failed to write to file: {}", public_path.display()))?;
+
+    report_field("private_key_file", &private_path);
+    report_field("public_key_file", &public_path);
+    println!("🔐 Private key of '{}' written to {} (PKCS#8)", name, private_path.display());
+    println!("🔑 Public key written to {}", public_path.display());
+    println!("\n⚠️  WARNING: The private key file is NOT encrypted! Anyone who can read it can sign as this key.");
+    Ok(())
+}
+
+fn export_key(ctx: &CliContext, name: &str, qr_file: Option<&Path>) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+    let key_pair = key_store
+        .keys
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))?;
+    key_pair.check_access(name, KeyOperation::Export)?;
+
+    let encrypted_secret = key_pair
+        .encrypted_secret_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Cannot export mnemonic.", name))?;
+
+    // Prompt for password
+    let password = read_password(&ctx.password_source, "Enter password to decrypt the secret key: ")?;
+
+    // HD-derived keys keep the mnemonic entropy separately from the derived secret
+    let encrypted_entropy = key_pair.encrypted_mnemonic.as_ref().unwrap_or(encrypted_secret);
+
+    // An error rather than a printed message, so it reaches stderr and the exit code under --quiet
+    let entropy = decrypt_secret_key(encrypted_entropy, &password).map_err(|_| {
+        anyhow::anyhow!("Invalid password for key pair '{}'. Please try again with the correct password.", name)
+    })?;
+
+    // Generate mnemonic from secret key
+    let mnemonic = bip39::Mnemonic::from_entropy(&entropy)
+        .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic: {}", e))?;
+    let mnemonic_string = mnemonic.to_string();
+
+    // The mnemonic alone doesn't restore a passphrase-protected key, so check the user still knows it
+    let hd = HdDerivation::from_key_pair(key_pair);
+    if let Some(hd) = hd.as_ref().filter(|hd| hd.use_passphrase) {
+        println!("\n🧂 This key was derived with a BIP39 passphrase, which you'll need alongside the mnemonic.");
+        let passphrase = prompt_bip39_passphrase(Some(hd), false)?;
+        let signing_key = SigningKey::from_bytes(&hd.derive(&mnemonic, &passphrase)?);
+        if signing_key.verifying_key().as_bytes().as_slice() == key_pair.public_key.as_slice() {
+            println!("✅ Passphrase verified");
+        } else {
+            println!("⚠️  WARNING: This passphrase does not reproduce the stored public key! Make sure you have the right one before relying on this backup.");
+        }
+    }
+
+    if let Some(qr_file) = qr_file {
+        write_qr_png(qr_file, &mnemonic_string)?;
+        println!("\n🖼️  Mnemonic QR code for key pair '{}' written to {}", name, qr_file.display());
+        println!("\n⚠️  WARNING: This image contains your full secret! Store it offline and never share it with anyone!");
+        return Ok(());
+    }
+
+    println!("\n🔑 Mnemonic for key pair '{}':\n", name);
+    outputln!("{}", mnemonic_string);
+    report_field("mnemonic", &mnemonic_string);
+    if let (Some(standard), Some(path)) = (
+        key_pair.metadata.hd_standard,
+        key_pair.metadata.derivation_path.as_deref(),
+    ) {
+        println!(
+            "\n🧭 This key is derived with {} at path {}; use the same settings when importing it",
+            standard.name(),
+            path
+        );
+    }
+    println!("\n⚠️  WARNING: Keep this mnemonic secure and never share it with anyone!");
+    Ok(())
+}
+
+fn batch_export(ctx: &CliContext, output_file: &Path, filter: Option<&str>) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+    let mut names: Vec<&String> = key_store
+        .keys
+        .keys()
+        .filter(|name| filter.is_none_or(|prefix| name.starts_with(prefix)))
+        .collect();
+    names.sort();
+    if names.is_empty() {
+        anyhow::bail!("No key pairs match the filter");
+    }
+
+    // One password unlocks every protected key; batch keys need none
+    let needs_password = names.iter().any(|name| {
+        let key_pair = &key_store.keys[*name];
+        key_pair.allows(KeyOperation::Export)
+            && key_pair
+                .encrypted_secret_key
+                .as_ref()
+                .is_some_and(|encrypted| !encrypted.empty_password)
+    });
+    let password = if needs_password {
+        read_password(&ctx.password_source, "Enter password to decrypt the secret keys: ")?
+    } else {
+        String::new()
+    };
+
+    let mut lines = String::new();
+    let mut exported = 0;
+    let mut skipped = Vec::new();
+    for name in names {
+        let key_pair = &key_store.keys[name];
+        if !key_pair.allows(KeyOperation::Export) {
+            skipped.push(format!("{} (not allowed by its access list)", name));
+            continue;
+        }
+        let Some(encrypted_secret) = key_pair.encrypted_secret_key.as_ref() else {
+            skipped.push(format!("{} (public key only)", name));
+            continue;
+        };
+        let encrypted_entropy = key_pair.encrypted_mnemonic.as_ref().unwrap_or(encrypted_secret);
+        let password = if encrypted_secret.empty_password { "" } else { password.as_str() };
+        let Ok(entropy) = decrypt_secret_key(encrypted_entropy, password) else {
+            skipped.push(format!("{} (wrong password)", name));
+            continue;
+        };
+        let mnemonic = bip39::Mnemonic::from_entropy(&entropy)
+            .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic for '{}': {}", name, e))?;
+        lines.push_str(&format!("{}: {}\n", name, mnemonic));
+        exported += 1;
+
+        if key_pair.has_bip39_passphrase {
+            println!("🧂 '{}' also needs its BIP39 passphrase, which is not exported", name);
+        } else if let Some(path) = key_pair.metadata.derivation_path.as_deref() {
+            println!("🧭 '{}' is derived at path {}; use the same settings when importing it", name, path);
+        }
+    }
+
+    if exported > 0 {
+        let mut file = create_private_file(output_file)?;
+        file.write_all(lines.as_bytes())
+            .with_context(|| format!("Failed to write to file: {}", output_file.display()))?;
+        println!("\n🔑 Exported {} mnemonic(s) to {}", exported, output_file.display());
+        println!("\n⚠️  WARNING: This file contains your full secrets! Store it offline or encrypted and never share it with anyone!");
+    }
+    if !skipped.is_empty() {
+        anyhow::bail!("{} key pair(s) could not be exported: {}", skipped.len(), skipped.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "qr-image")]
+fn write_qr_png(path: &Path, data: &str) -> Result<()> {
+    use image::{ImageFormat, Luma};
+    use qrcode::QrCode;
+    use std::io::Cursor;
+
+    let code = QrCode::new(data.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Failed to encode QR code: {}", e))?;
+    let image = code.render::<Luma<u8>>().min_dimensions(400, 400).build();
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| anyhow::anyhow!("Failed to render QR code: {}", e))?;
+
+    // The image holds the full secret, so never overwrite an existing file and keep it owner-only
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    file.write_all(&png)
+        .with_context(|| format!("Failed to write to file: {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "qr-image"))]
+fn write_qr_png(_path: &Path, _data: &str) -> Result<()> {
+    anyhow::bail!("QR code export is not available in this build. Rebuild with `--features qr-image`.")
+}
+
+// Decode a raw ed25519 secret: either the 32-byte seed, or the 64-byte seed + public key
+// layout used by libsodium and OpenSSL exports
+fn parse_raw_secret_key(ctx: &CliContext, input: &str, encoding: KeyEncoding) -> Result<[u8; 32]> {
+    let bytes = ctx
+        .decode(encoding, input)
+        .map_err(|e| anyhow::anyhow!("Invalid secret key: {}", e))?;
+    match bytes.len() {
+        32 => Ok(bytes.try_into().unwrap()),
+        64 => {
+            let keypair_bytes: [u8; 64] = bytes.try_into().unwrap();
+            let signing_key = SigningKey::from_keypair_bytes(&keypair_bytes)
+                .map_err(|_| anyhow::anyhow!("Invalid secret key: the public key half does not match the secret half"))?;
+            Ok(signing_key.to_bytes())
+        }
+        len => anyhow::bail!("Invalid secret key length: expected 32 or 64 bytes, got {}", len),
+    }
+}
+
+fn import_key(
+    ctx: &CliContext,
+    name: &str,
+    kdf: KdfParams,
+    hd: Option<HdDerivation>,
+    format: ImportFormat,
+) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+
+    if key_store.keys.contains_key(name) {
+        anyhow::bail!("Key pair with name '{}' already exists", name);
+    }
+    if hd.is_some() && format != ImportFormat::Mnemonic {
+        anyhow::bail!("HD derivation needs a mnemonic, it can't be used with --format {}", format.name());
+    }
+
+    // Raw secret keys are used as-is; mnemonics are converted to a secret key either directly
+    // from their entropy or via HD derivation
+    let (secret_key_array, entropy): ([u8; 32], Vec<u8>) = match format {
+        ImportFormat::Hex | ImportFormat::Base64 => {
+            let secret_input = read_mnemonic(ctx, &format!("\nEnter your secret key ({}):", format.name()))?;
+            let encoding = if format == ImportFormat::Hex {
+                KeyEncoding::Hex
+            } else {
+                KeyEncoding::Base64
+            };
+            let secret_key_array = parse_raw_secret_key(ctx, &secret_input, encoding)?;
+            (secret_key_array, secret_key_array.to_vec())
+        }
+        ImportFormat::Mnemonic => {
+            // Prompt for mnemonic
+            let mnemonic_input = read_mnemonic(ctx, "\nEnter your mnemonic phrase (12 or 24 words):")?;
+            let mnemonic_input = mnemonic_input.trim();
+
+            // Parse mnemonic
+            let mnemonic = bip39::Mnemonic::from_str(mnemonic_input)
+                .map_err(|e| anyhow::anyhow!("Invalid mnemonic phrase: {}", e))?;
+
+            let entropy = mnemonic.to_entropy();
+            let passphrase = prompt_bip39_passphrase(hd.as_ref(), false)?;
+            let secret_key_array: [u8; 32] = match &hd {
+                Some(hd) => hd.derive(&mnemonic, &passphrase)?,
+                None => entropy
+                    .clone()
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?,
+            };
+            (secret_key_array, entropy)
+        }
+    };
+    save_imported_key(ctx, key_store, name, secret_key_array, &entropy, kdf, hd.as_ref())
+}
+
+fn import_raw(ctx: &CliContext, name: &str, key_file: &Path, hex: bool, kdf: KdfParams) -> Result<()> {
+    let key_store = ctx.load_key_store()?;
+    if key_store.keys.contains_key(name) {
+        anyhow::bail!("Key pair with name '{}' already exists", name);
+    }
+
+    let contents =
+        fs::read(key_file).with_context(|| format!("Failed to read key file: {}", key_file.display()))?;
+    let secret_key_bytes = if hex {
+        let text = String::from_utf8(contents).map_err(|_| anyhow::anyhow!("Invalid secret key: not hex text"))?;
+        KeyEncoding::Hex
+            .decode(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid secret key: {}", e))?
+    } else {
+        contents
+    };
+    let secret_key_array: [u8; 32] = secret_key_bytes.try_into().map_err(|bytes: Vec<u8>| {
+        anyhow::anyhow!("Invalid secret key length: expected 32 bytes, got {}", bytes.len())
+    })?;
+
+    save_imported_key(ctx, key_store, name, secret_key_array, &secret_key_array, kdf, None)
+}
+
+// Encrypt an imported secret and add it to the key store. `entropy` is only kept for HD keys,
+// whose secret can't be turned back into the mnemonic
+fn save_imported_key(
+    ctx: &CliContext,
+    mut key_store: KeyStore,
+    name: &str,
+    secret_key_array: [u8; 32],
+    entropy: &[u8],
+    kdf: KdfParams,
+    hd: Option<&HdDerivation>,
+) -> Result<()> {
+    let secret_key_bytes = secret_key_array.to_vec();
+
+    // Create signing key and get public key
+    let signing_key = SigningKey::from_bytes(&secret_key_array);
+    let verifying_key = signing_key.verifying_key();
+    let public_key_bytes = verifying_key.to_bytes();
+    let public_key_string = key_store.encode_public_key(&public_key_bytes, ctx.encoding);
+
+    // Get password for secret key encryption
+    let password = read_new_key_password(ctx, &ctx.password_source, "\nEnter password to encrypt the secret key: ")?;
+
+    // Encrypt the secret key
+    let encrypted_secret = encrypt_secret_key(&secret_key_bytes, &password, kdf)?;
+    let encrypted_mnemonic = match hd {
+        Some(_) => Some(encrypt_secret_key(entropy, &password, kdf)?),
+        None => None,
+    };
+
+    // Save the key pair
+    let key_pair = KeyPair {
+        public_key: public_key_bytes.to_vec(),
+        public_key_string: public_key_string.clone(),
+        encrypted_secret_key: Some(encrypted_secret),
+        encrypted_mnemonic,
+        metadata: new_key_metadata(hd),
+        has_bip39_passphrase: hd.is_some_and(|hd| hd.use_passphrase),
+        registered_with: Vec::new(),
+    };
+    key_pair
+        .check_encrypted_secret(&password)
+        .with_context(|| format!("Refusing to save key pair '{}'", name))?;
+    key_store.keys.insert(name.to_string(), key_pair);
+
+    ctx.save_key_store(&key_store)?;
+    println!("\n✅ Successfully imported key pair '{}'", name);
+    println!("🔑 Public key: {}", ctx.encoding.encode(&public_key_bytes));
+    report_field("name", name);
+    report_field("public_key", ctx.encoding.encode(&public_key_bytes));
+    Ok(())
+}
+
+fn change_password(ctx: &CliContext, name: &str, new_password_source: &SecretSource) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    let key_pair = key_store
+        .keys
+        .get_mut(name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))?;
+    // Re-keying unlocks the secret just like signing does, so a key that may not sign can't be re-keyed
+    key_pair.check_access(name, KeyOperation::Sign)?;
+
+    let encrypted_secret = key_pair
+        .encrypted_secret_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Cannot change password.", name))?;
+
+    let current_password = read_password(&ctx.password_source, "Enter current password to decrypt the secret key: ")?;
+
+    let secret_key_bytes = decrypt_secret_key(encrypted_secret, &current_password).map_err(|_| {
+        anyhow::anyhow!("Invalid password for key pair '{}'. Please try again with the correct password.", name)
+    })?;
+
+    let new_password = read_new_key_password(ctx, new_password_source, "\nEnter new password for secret key: ")?;
+
+    // Re-encrypting with fresh salt and nonce also moves the key to the default KDF;
+    // the public key is left untouched
+    key_pair.encrypted_secret_key = Some(encrypt_secret_key(
+        &secret_key_bytes,
+        &new_password,
+        KdfParams::default(),
+    )?);
+    if let Some(encrypted_mnemonic) = key_pair.encrypted_mnemonic.take() {
+        let entropy = decrypt_secret_key(&encrypted_mnemonic, &current_password)?;
+        key_pair.encrypted_mnemonic = Some(encrypt_secret_key(
+            &entropy,
+            &new_password,
+            KdfParams::default(),
+        )?);
+    }
+    key_pair.touch();
+
+    ctx.save_key_store(&key_store)?;
+
+    // The cached password is no longer valid for this key
+    *PASSWORD_CACHE.lock().unwrap() = None;
+
+    println!("\n✅ Password changed for key pair '{}'", name);
+    report_field("name", name);
+    Ok(())
+}
+
+fn rename_key(ctx: &CliContext, old_name: &str, new_name: &str) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+
+    if !key_store.keys.contains_key(old_name) {
+        anyhow::bail!("Key pair '{}' not found", old_name);
+    }
+
+    if key_store.keys.contains_key(new_name) {
+        anyhow::bail!("Key pair with name '{}' already exists", new_name);
+    }
+
+    // Move the entry as-is so the encrypted secret and public key stay byte-for-byte identical
+    let key_pair = key_store.keys.remove(old_name).unwrap();
+    let public_key_string = ctx.encoding.encode(&key_pair.public_key);
+    let fingerprint = public_key_fingerprint(&key_pair.public_key);
+    key_store.keys.insert(new_name.to_string(), key_pair);
+
+    ctx.save_key_store(&key_store)?;
+
+    // The cached password stays valid: it is keyed by the key pair's contents, which renaming doesn't touch
+    println!("\n✅ Renamed key pair '{}' to '{}'", old_name, new_name);
+    println!("🔑 Public key: {}", public_key_string);
+    report_field("name", new_name);
+    report_field("public_key", &public_key_string);
+    println!("🔏 Fingerprint: {}", fingerprint);
+    Ok(())
+}
+
+// `acl` of None removes the access list. Only whoever knows the key's password may change it
+fn set_acl(ctx: &CliContext, name: &str, acl: Option<Vec<KeyOperation>>) -> Result<()> {
+    let mut key_store = ctx.load_key_store()?;
+    let key_pair = key_store
+        .keys
+        .get_mut(name)
+        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))?;
+
+    let encrypted_secret = key_pair
+        .encrypted_secret_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Cannot change its access list.", name))?;
+    let password = if encrypted_secret.empty_password {
+        String::new()
+    } else {
+        read_password(&ctx.password_source, "Enter password to decrypt the secret key: ")?
+    };
+    decrypt_secret_key(encrypted_secret, &password).map_err(|_| {
+        anyhow::anyhow!("Invalid password for key pair '{}'. Please try again with the correct password.", name)
+    })?;
+
+    let acl = acl.map(|mut acl| {
+        acl.sort();
+        acl.dedup();
+        acl
+    });
+    let allowed: Vec<&str> = acl.iter().flatten().map(KeyOperation::name).collect();
+    key_pair.metadata.acl = acl;
+    key_pair.touch();
+    ctx.save_key_store(&key_store)?;
+
+    report_field("acl", &allowed);
+    if allowed.is_empty() {
+        println!("✅ Removed the access list of '{}', it can be used for anything again", name);
+    } else {
+        println!("✅ Key pair '{}' may now only be used to: {}", name, allowed.join(", "));
+    }
+    Ok(())
+}
+
+/// The files of a proof submission, and the names signed for them
+#[derive(clap::Args, Debug, Clone)]
+struct ProofFiles {
+    /// Path to the proof file
+    #[arg(short, long)]
+    proof_file: PathBuf,
+
+    /// Path to the ELF file
+    #[arg(short = 'l', long)]
+    elf_file: PathBuf,
+
+    #[command(flatten)]
+    options: SubmissionOptions,
+}
+
+/// What `send` submits: the files of one submission, or a batch of them
+#[derive(clap::Args, Debug, Clone)]
+struct SendFiles {
+    /// Path to the proof file
+    #[arg(short, long, required_unless_present = "batch_file")]
+    proof_file: Option<PathBuf>,
+
+    /// Path to the ELF file
+    #[arg(short = 'l', long, required_unless_present = "batch_file")]
+    elf_file: Option<PathBuf>,
+
+    /// File with one `proof_path,elf_path` pair per line, submitted one after another
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["proof_file", "elf_file", "proof_name", "elf_name"])]
+    batch_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    options: SubmissionOptions,
+}
+
+/// How a proof submission is named, encoded and uploaded
+#[derive(clap::Args, Debug, Clone)]
+struct SubmissionOptions {
+    /// Proving system to use (default: detected from the proof file, else sp1)
+    #[arg(short = 's', long)]
+    proving_system: Option<ProvingSystem>,
+
+    /// Name to sign and send for the proof file (default: its file name)
+    #[arg(long, value_name = "NAME")]
+    proof_name: Option<String>,
+
+    /// Name to sign and send for the ELF file (default: its file name)
+    #[arg(long, value_name = "NAME")]
+    elf_name: Option<String>,
+
+    /// How the files are uploaded: base64 inside a JSON body, or streamed from disk as multipart
+    #[arg(long, value_enum, default_value = "json")]
+    upload: UploadFormat,
+
+    /// Gzip the files before base64-encoding them and mark the body with `"encoding": "gzip"`.
+    /// The signature covers the compressed bytes. Only for servers that support it
+    #[arg(long)]
+    compress: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum UploadFormat {
+    Json,
+    Multipart,
+}
+
+// Multipart form field names, which are part of the signed canonical string
+const PROOF_FIELD: &str = "proof";
+const ELF_FIELD: &str = "elf";
+
+/// A proof submission ready to be signed and sent
+enum ProofRequest {
+    Json(serde_json::Value),
+    Multipart(MultipartProof),
+}
+
+/// A proof request and the signatures that go in its headers
+struct SignedRequest {
+    request: ProofRequest,
+    signature: Vec<u8>,
+    public_key: Vec<u8>,
+    /// JSON array for X-Approvals, from `threshold-send`
+    approvals: Option<String>,
+}
+
+/// Everything needed to rebuild the multipart form, which is consumed by every attempt
+struct MultipartProof {
+    proof_file: PathBuf,
+    elf_file: PathBuf,
+    proof_filename: String,
+    elf_filename: String,
+    proving_system: ProvingSystem,
+    canonical_string: String,
+}
+
+impl ProofRequest {
+    fn canonical_string(&self) -> &str {
+        match self {
+            ProofRequest::Json(body) => body["canonical_string"].as_str().unwrap(),
+            ProofRequest::Multipart(multipart) => &multipart.canonical_string,
+        }
+    }
+}
+
+impl MultipartProof {
+    // The files are streamed from disk, so large proofs are never held in memory
+    async fn form(&self) -> Result<reqwest::multipart::Form> {
+        Ok(reqwest::multipart::Form::new()
+            .text("proof_filename", self.proof_filename.clone())
+            .text("elf_filename", self.elf_filename.clone())
+            .text("proving_system", self.proving_system.name())
+            .text("canonical_string", self.canonical_string.clone())
+            .part(PROOF_FIELD, file_part(&self.proof_file, &self.proof_filename).await?)
+            .part(ELF_FIELD, file_part(&self.elf_file, &self.elf_filename).await?))
+    }
+}
+
+// A multipart part streamed from disk, so large files are never held in memory
+async fn file_part(path: &Path, file_name: &str) -> Result<reqwest::multipart::Part> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let length = file.metadata().await?.len();
+    let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file));
+    Ok(reqwest::multipart::Part::stream_with_length(body, length)
+        .file_name(file_name.to_string())
+        .mime_str("application/octet-stream")?)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    FileCache::sha256(path, || {
+        let mut file = fs::File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+}
+
+/// A file's identity and version without reading it: rewriting the file changes its size or
+/// modification time, so a file edited during a batch is read again
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct FileKey {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+impl FileKey {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileKey {
+            path: fs::canonicalize(path).ok()?,
+            len: metadata.len(),
+            modified: metadata.modified().ok()?,
+        })
+    }
+}
+
+/// Digests of every file and encodings of the ELFs seen in one batch. Proofs are rarely shared,
+/// so their encodings aren't kept
+#[derive(Default)]
+struct FileCache {
+    digests: HashMap<FileKey, String>,
+    /// Keyed by file and whether it was gzipped with --compress
+    encoded: HashMap<(FileKey, bool), EncodedFile>,
+}
+
+impl FileCache {
+    // The cache is only consulted while a batch runs, and `compute` runs on a miss
+    fn sha256(path: &Path, compute: impl FnOnce() -> Result<String>) -> Result<String> {
+        let Some(key) = Self::key(path) else { return compute() };
+        if let Some(digest) = BATCH_FILE_CACHE.lock().unwrap().as_ref().and_then(|cache| cache.digests.get(&key)) {
+            return Ok(digest.clone());
+        }
+        let digest = compute()?;
+        if let Some(cache) = BATCH_FILE_CACHE.lock().unwrap().as_mut() {
+            cache.digests.insert(key, digest.clone());
+        }
+        Ok(digest)
+    }
+
+    fn encoded(path: &Path, compress: bool, compute: impl FnOnce() -> Result<EncodedFile>) -> Result<EncodedFile> {
+        let Some(key) = Self::key(path).map(|key| (key, compress)) else { return compute() };
+        if let Some(encoded) = BATCH_FILE_CACHE.lock().unwrap().as_ref().and_then(|cache| cache.encoded.get(&key)) {
+            return Ok(encoded.clone());
+        }
+        let encoded = compute()?;
+        if let Some(cache) = BATCH_FILE_CACHE.lock().unwrap().as_mut() {
+            cache.encoded.insert(key, encoded.clone());
+        }
+        Ok(encoded)
+    }
+
+    // Skips the metadata lookups when no batch is running
+    fn key(path: &Path) -> Option<FileKey> {
+        BATCH_FILE_CACHE.lock().unwrap().is_some().then(|| FileKey::of(path)).flatten()
+    }
+}
+
+// Multipart uploads sign the form field names and file digests rather than the file contents
+fn build_multipart_canonical_string(
+    proof_sha256: &str,
+    elf_sha256: &str,
+    proof_filename: &str,
+    elf_filename: &str,
+    proving_system: ProvingSystem,
+) -> String {
+    format!(
+        "version:multipart-1\nproof_field:{}\nproof_sha256:{}\nproof_filename:{}\nelf_field:{}\nelf_sha256:{}\nelf_filename:{}\nproving_system:{}",
+        PROOF_FIELD,
+        proof_sha256,
+        proof_filename,
+        ELF_FIELD,
+        elf_sha256,
+        elf_filename,
+        proving_system.name()
+    )
+}
+
+impl SendFiles {
+    // The submission given by --proof-file and --elf-file, or None with --batch-file
+    fn single(&self) -> Option<ProofFiles> {
+        Some(ProofFiles {
+            proof_file: self.proof_file.clone()?,
+            elf_file: self.elf_file.clone()?,
+            options: self.options.clone(),
+        })
+    }
+
+    // One submission per `proof_path,elf_path` line of --batch-file, sharing every other option
+    fn read_batch(&self) -> Result<Vec<ProofFiles>> {
+        let Some(batch_file) = &self.batch_file else {
+            anyhow::bail!("--proof-file and --elf-file are required without --batch-file");
+        };
+        let contents = fs::read_to_string(batch_file)
+            .with_context(|| format!("Failed to read batch file: {}", batch_file.display()))?;
+        let mut batch = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((proof_file, elf_file)) = line.split_once(',') else {
+                anyhow::bail!(
+                    "{}:{}: expected `proof_path,elf_path`, got \"{}\"",
+                    batch_file.display(),
+                    i + 1,
+                    line
+                );
+            };
+            let files = ProofFiles {
+                proof_file: PathBuf::from(proof_file.trim()),
+                elf_file: PathBuf::from(elf_file.trim()),
+                options: self.options.clone(),
+            };
+            batch.push(files.with_detected_proving_system()?);
+        }
+        if batch.is_empty() {
+            anyhow::bail!("Batch file {} lists no submissions", batch_file.display());
+        }
+        Ok(batch)
+    }
+}
+
+impl ProofFiles {
+    fn proof_file(&self) -> &Path {
+        &self.proof_file
+    }
+
+    fn elf_file(&self) -> &Path {
+        &self.elf_file
+    }
+
+    fn proving_system(&self) -> ProvingSystem {
+        self.options.proving_system.unwrap_or(ProvingSystem::Sp1)
+    }
+
+    // Fills in --proving-system from the proof file's first bytes when it wasn't given
+    fn with_detected_proving_system(mut self) -> Result<Self> {
+        if self.options.proving_system.is_none() {
+            self.options.proving_system = detect_proof_file_system(&self.proof_file)?;
+        }
+        Ok(self)
+    }
+
+    // Names end up in the signed canonical string, so a real submission never falls back to a placeholder
+    fn resolve_names(&self) -> Result<(String, String)> {
+        Ok((
+            submission_file_name(self.proof_file(), self.options.proof_name.as_deref(), "proof-name")?,
+            submission_file_name(self.elf_file(), self.options.elf_name.as_deref(), "elf-name")?,
+        ))
+    }
+
+    // For previews: show what would be signed even if a real submission would refuse the names
+    fn preview_names(&self) -> (String, String) {
+        let preview_name = |path: &Path, name: &Option<String>, flag| {
+            submission_file_name(path, name.as_deref(), flag).unwrap_or_else(|e| {
+                eprintln!("⚠️  {}. Using \"unknown\" in this preview only, `send` will refuse it", e);
+                "unknown".to_string()
+            })
+        };
+        (
+            preview_name(self.proof_file(), &self.options.proof_name, "proof-name"),
+            preview_name(self.elf_file(), &self.options.elf_name, "elf-name"),
+        )
+    }
+
+    // Large files are encoded straight from disk, so the raw contents are never held in memory.
+    // Within a batch, an ELF shared by several rows is only encoded for the first
+    fn encode(&self) -> Result<(EncodedFile, EncodedFile)> {
+        let size = |path: &Path, what: &str| {
+            fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .with_context(|| format!("Failed to read {} file: {}", what, path.display()))
+        };
+        let total = size(self.proof_file(), "proof")? + size(self.elf_file(), "ELF")?;
+        let stream = !self.options.compress && total >= STREAMING_THRESHOLD;
+        let proof = self.encode_file(self.proof_file(), "proof", stream)?;
+        let elf = FileCache::encoded(self.elf_file(), self.options.compress, || {
+            self.encode_file(self.elf_file(), "ELF", stream)
+        })?;
+        Ok((proof, elf))
+    }
+
+    // The contents as they are uploaded and signed, compressed with --compress
+    fn encode_file(&self, path: &Path, what: &str, stream: bool) -> Result<EncodedFile> {
+        if stream {
+            return EncodedFile::stream(path, what);
+        }
+        let content = fs::read(path).with_context(|| format!("Failed to read {} file: {}", what, path.display()))?;
+        if self.options.compress {
+            return Ok(EncodedFile::new(&gzip(&content)?));
+        }
+        Ok(EncodedFile::new(&content))
+    }
+}
+
+// Proof and ELF files at least this large together are base64-encoded and hashed in one streaming pass
+const STREAMING_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// A file as it goes into a JSON submission: its base64 for the body and v1 template,
+/// and its SHA-256 for the v2 template
+#[derive(Clone)]
+struct EncodedFile {
+    base64: String,
+    sha256: String,
+}
+
+impl EncodedFile {
+    fn new(content: &[u8]) -> Self {
+        EncodedFile {
+            base64: BASE64.encode(content),
+            sha256: format!("{:x}", Sha256::digest(content)),
+        }
+    }
+
+    fn stream(path: &Path, what: &str) -> Result<Self> {
+        let read_error = || format!("Failed to read {} file: {}", what, path.display());
+        let file = fs::File::open(path).with_context(read_error)?;
+        let length = file.metadata().with_context(read_error)?.len() as usize;
+        let mut reader = std::io::BufReader::with_capacity(1024 * 1024, file);
+        let mut hasher = Sha256::new();
+        let mut encoder = base64::write::EncoderStringWriter::from_consumer(
+            String::with_capacity(length.div_ceil(3) * 4),
+            &BASE64,
+        );
+        loop {
+            let chunk = reader.fill_buf().with_context(read_error)?;
+            if chunk.is_empty() {
+                break;
+            }
+            hasher.update(chunk);
+            encoder.write_all(chunk)?;
+            let consumed = chunk.len();
+            reader.consume(consumed);
+        }
+        Ok(EncodedFile {
+            base64: encoder.into_inner(),
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+// No file name or timestamp goes into the header, so the same input always signs the same
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn submission_file_name(path: &Path, name: Option<&str>, flag: &str) -> Result<String> {
+    if let Some(name) = name {
+        if name.is_empty() || name.contains(['\n', '\r']) {
+            anyhow::bail!("--{} must be a non-empty, single-line name", flag);
+        }
+        return Ok(name.to_string());
+    }
+
+    // Paths like `dir/..` have no basename of their own, so look at the resolved path instead
+    let base_name = match path.file_name() {
+        Some(name) => Some(name.to_os_string()),
+        None => fs::canonicalize(path)
+            .ok()
+            .and_then(|path| path.file_name().map(|name| name.to_os_string())),
+    };
+    match base_name.and_then(|name| name.into_string().ok()) {
+        Some(name) => Ok(name),
+        None => anyhow::bail!(
+            "Cannot derive a UTF-8 file name from {}, pass --{} to name it explicitly",
+            path.display(),
+            flag
+        ),
+    }
+}
+
+// Build the exact string that gets signed for a proof submission. Every template
+// layout is signature-critical and pinned by tests/canonical_string_test.rs
+fn build_canonical_string(
+    template: CanonicalTemplate,
+    proof: &EncodedFile,
+    elf: &EncodedFile,
+    proof_filename: &str,
+    elf_filename: &str,
+    proving_system: ProvingSystem,
+) -> String {
+    match template {
+        CanonicalTemplate::V1 => format!(
+            "proof:{}\nelf:{}\nproof_filename:{}\nelf_filename:{}\nproving_system:{}",
+            proof.base64,
+            elf.base64,
+            proof_filename,
+            elf_filename,
+            proving_system.name()
+        ),
+        CanonicalTemplate::V2 => format!(
+            "version:2\nproof_sha256:{}\nelf_sha256:{}\nproof_filename:{}\nelf_filename:{}\nproving_system:{}",
+            proof.sha256,
+            elf.sha256,
+            proof_filename,
+            elf_filename,
+            proving_system.name()
+        ),
+    }
+}
+
+fn print_canonical_string(ctx: &CliContext, files: &ProofFiles) -> Result<()> {
+    let (proof_filename, elf_filename) = files.preview_names();
+    let canonical_string = match files.options.upload {
+        UploadFormat::Json => {
+            let (proof, elf) = files.encode()?;
+            build_canonical_string(
+                ctx.canonical_template,
+                &proof,
+                &elf,
+                &proof_filename,
+                &elf_filename,
+                files.proving_system(),
+            )
+        }
+        UploadFormat::Multipart => build_multipart_canonical_string(
+            &sha256_file(files.proof_file())?,
+            &sha256_file(files.elf_file())?,
+            &proof_filename,
+            &elf_filename,
+            files.proving_system(),
+        ),
+    };
+    let bytes = canonical_string.as_bytes();
+
+    report_field("canonical_string", &canonical_string);
+    report_field("hex", hex::encode(bytes));
+    println!("🧾 Canonical string ({} bytes)", bytes.len());
+    outputln!("\nHex:\n{}", hex::encode(bytes));
+
+    // Filenames end up in the string verbatim, so only echo it when it is safe to print
+    if canonical_string.chars().all(|c| c == '\n' || !c.is_control()) {
+        outputln!("\nUTF-8:\n{}", canonical_string);
+    } else {
+        println!("\n⚠️  Canonical string contains non-printable characters, UTF-8 output omitted");
+    }
+    Ok(())
+}
+
+// Returns whether the signature is valid, so main can set the exit code
+fn verify_signature(ctx: &CliContext, public_key: &str, signature: &str, message_file: &Path) -> Result<bool> {
+    let public_key = ctx
+        .decode(ctx.encoding, public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    let signature = ctx
+        .decode(ctx.encoding, signature)
+        .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
+    let message = fs::read(message_file)
+        .with_context(|| format!("Failed to read message file: {}", message_file.display()))?;
+
+    let valid = soundness_layer::verify_signature(&public_key, &signature, &message)?;
+    if valid {
+        println!("✅ Signature valid");
+    } else {
+        println!("❌ Signature invalid");
+    }
+    println!("🔏 Public key fingerprint: {}", public_key_fingerprint(&public_key));
+    Ok(valid)
+}
+
+/// A commit object split into what Git signed and the signature it stored
+struct SignedCommit {
+    payload: String,
+    signature: String,
+    committer_email: Option<String>,
+}
+
+// Git signs the commit object without its `gpgsig` header, which continues on lines
+// starting with a space
+fn split_signed_commit(object: &str) -> Result<SignedCommit> {
+    let (headers, message) = object
+        .split_once("\n\n")
+        .ok_or_else(|| anyhow::anyhow!("Not a commit object"))?;
+    let mut payload = String::new();
+    let mut signature: Option<String> = None;
+    let mut committer_email = None;
+    let mut in_signature = false;
+    for line in headers.split('\n') {
+        if in_signature {
+            if let (Some(continued), Some(signature)) = (line.strip_prefix(' '), signature.as_mut()) {
+                signature.push_str(continued);
+                signature.push('\n');
+                continue;
             }
-            stored_password.clone()
+            in_signature = false;
+        }
+        if let Some(first) = line.strip_prefix("gpgsig ") {
+            signature = Some(format!("{}\n", first));
+            in_signature = true;
+            continue;
+        }
+        if let Some(committer) = line.strip_prefix("committer ") {
+            committer_email = committer
+                .split_once('<')
+                .and_then(|(_, rest)| rest.split_once('>'))
+                .map(|(email, _)| email.to_string());
+        }
+        payload.push_str(line);
+        payload.push('\n');
+    }
+    payload.push('\n');
+    payload.push_str(message);
+
+    Ok(SignedCommit {
+        payload,
+        signature: signature.ok_or_else(|| anyhow::anyhow!("Commit is not signed"))?,
+        committer_email,
+    })
+}
+
+fn verify_commit(ctx: &CliContext, commit_hash: &str, public_key: Option<&str>) -> Result<bool> {
+    let output = std::process::Command::new("git")
+        .args(["cat-file", "commit", commit_hash])
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git cat-file failed for {}: {}",
+            commit_hash,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let object = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow::anyhow!("Commit {} is not valid UTF-8", commit_hash))?;
+    let commit = split_signed_commit(&object)?;
+    let signature = sshsig::SshSignature::parse(&commit.signature)?;
+
+    let expected_key = match public_key {
+        Some(key) if key.starts_with("ssh-") => sshsig::parse_openssh_public_key(key)?,
+        Some(key) => ctx
+            .decode(ctx.encoding, key)
+            .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?,
+        None => {
+            let email = commit
+                .committer_email
+                .ok_or_else(|| anyhow::anyhow!("Commit {} has no committer email", commit_hash))?;
+            get_public_key(ctx, &email).map_err(|_| {
+                anyhow::anyhow!(
+                    "No key pair named '{}' in the key store. Pass --public-key, or name the committer's key pair after their email",
+                    email
+                )
+            })?
+        }
+    };
+
+    let valid = if signature.namespace != "git" {
+        println!("❌ Signature is for namespace '{}', not git", signature.namespace);
+        false
+    } else if signature.public_key != expected_key {
+        println!(
+            "❌ Commit is signed by a different key ({})",
+            public_key_fingerprint(&signature.public_key)
+        );
+        false
+    } else if signature.verify(commit.payload.as_bytes())? {
+        println!("✅ Good signature on commit {}", commit_hash);
+        true
+    } else {
+        println!("❌ Bad signature on commit {}", commit_hash);
+        false
+    };
+    println!("🔏 Public key fingerprint: {}", public_key_fingerprint(&expected_key));
+    Ok(valid)
+}
+
+// Read the proof and ELF files and build the JSON request body, including the canonical string
+// A dry run only previews the request, so it warns about names a real submission would refuse
+fn build_proof_request(ctx: &CliContext, files: &ProofFiles, dry_run: bool) -> Result<ProofRequest> {
+    let (proof_filename, elf_filename) = if dry_run {
+        files.preview_names()
+    } else {
+        files.resolve_names()?
+    };
+    let proving_system = files.proving_system();
+
+    if files.options.upload == UploadFormat::Multipart {
+        if files.options.compress {
+            anyhow::bail!("--compress only applies to --upload json");
+        }
+        let hashing_pb = create_progress_bar("📂 Hashing files...");
+        let canonical_string = build_multipart_canonical_string(
+            &sha256_file(files.proof_file())?,
+            &sha256_file(files.elf_file())?,
+            &proof_filename,
+            &elf_filename,
+            proving_system,
+        );
+        hashing_pb.finish_with_message("📂 Files hashed successfully");
+        return Ok(ProofRequest::Multipart(MultipartProof {
+            proof_file: files.proof_file().to_path_buf(),
+            elf_file: files.elf_file().to_path_buf(),
+            proof_filename,
+            elf_filename,
+            proving_system,
+            canonical_string,
+        }));
+    }
+
+    // Create progress bars
+    let reading_pb = create_progress_bar("📂 Reading files...");
+
+    // Read and encode the files
+    let (proof, elf) = files.encode()?;
+
+    reading_pb.finish_with_message("📂 Files read successfully");
+
+    // Create the request body with canonical string
+    let canonical_string = build_canonical_string(
+        ctx.canonical_template,
+        &proof,
+        &elf,
+        &proof_filename,
+        &elf_filename,
+        proving_system,
+    );
+    let mut request_body = serde_json::json!({
+        "proof": proof.base64,
+        "elf": elf.base64,
+        "proof_filename": proof_filename,
+        "elf_filename": elf_filename,
+        "proving_system": proving_system.name(),
+        "canonical_string": canonical_string,
+    });
+
+    // v1 servers predate templates, so only tag the body for newer layouts
+    if ctx.canonical_template != CanonicalTemplate::V1 {
+        request_body["canonical_template"] = serde_json::json!(ctx.canonical_template.name());
+    }
+    if files.options.compress {
+        request_body["encoding"] = serde_json::json!("gzip");
+    }
+    Ok(ProofRequest::Json(request_body))
+}
+
+// The signature headers are still sent with mTLS, so the server can tie both identities together
+fn load_client_identity(cert: Option<&Path>, key: Option<&Path>) -> Result<Option<reqwest::Identity>> {
+    let (cert, key) = match (cert, key) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("Mutual TLS needs both --client-cert and --client-key"),
+    };
+
+    let cert_pem =
+        fs::read(cert).with_context(|| format!("Failed to read client certificate: {}", cert.display()))?;
+    let key_pem = fs::read(key).with_context(|| format!("Failed to read client key: {}", key.display()))?;
+    let identity = reqwest::Identity::from_pkcs8_pem(&cert_pem, &key_pem)
+        .map_err(|e| anyhow::anyhow!("Invalid client certificate or key: {}", e))?;
+    Ok(Some(identity))
+}
+
+async fn sync_public_keys(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    registry: Option<&str>,
+    path: &str,
+) -> Result<()> {
+    let registry = registry.unwrap_or(&ctx.endpoint);
+    let url = format!("{}/{}", registry.trim_end_matches('/'), path.trim_start_matches('/'));
+
+    let mut key_store = ctx.load_key_store()?;
+    let mut pending: Vec<String> = key_store
+        .keys
+        .iter()
+        .filter(|(_, key_pair)| !key_pair.registered_with.contains(&url))
+        .map(|(name, _)| name.clone())
+        .collect();
+    pending.sort();
+    let already_registered = key_store.keys.len() - pending.len();
+
+    let pb = create_progress_bar("📡 Registering public keys...");
+    let mut registered = 0;
+    let mut failures = Vec::new();
+    for (i, name) in pending.iter().enumerate() {
+        pb.set_message(format!("📡 Registering '{}' ({}/{})...", name, i + 1, pending.len()));
+        let key_pair = key_store.keys.get_mut(name).unwrap();
+        let body = serde_json::json!({
+            "name": name,
+            "public_key": BASE64.encode(&key_pair.public_key),
+        });
+        match client.post(&url).json(&body).send().await {
+            Ok(response) if response.status().is_success() => {
+                key_pair.registered_with.push(url.clone());
+                key_pair.touch();
+                registered += 1;
+            }
+            Ok(response) => failures.push(format!("{}: server returned {}", name, response.status())),
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    pb.finish_and_clear();
+
+    report_field("registered", registered);
+    // Save even after failures, so the keys that did get through aren't sent again
+    if registered > 0 {
+        ctx.save_key_store(&key_store)?;
+    }
+
+    println!(
+        "✅ Registered {} new public key(s) with {} ({} already registered)",
+        registered, url, already_registered
+    );
+    if !failures.is_empty() {
+        for failure in &failures {
+            println!("❌ {}", failure);
+        }
+        anyhow::bail!("{} public key(s) failed to register", failures.len());
+    }
+    Ok(())
+}
+
+/// What `registry push` signs: who publishes which files under which name and version
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct RegistryManifest {
+    name: String,
+    version: String,
+    proving_system: String,
+    proof_sha256: String,
+    elf_sha256: String,
+    proof_size: u64,
+    elf_size: u64,
+    /// Base64 public key of the publisher
+    public_key: String,
+    published_at: u64,
+}
+
+fn check_registry_name(name: &str) -> Result<()> {
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+    if name.is_empty() || name.len() > 64 || !name.chars().all(valid_char) || !name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        anyhow::bail!(
+            "Invalid registry name \"{}\": use up to 64 lowercase letters, digits, `-` and `_`, starting with a letter or digit",
+            name
+        );
+    }
+    Ok(())
+}
+
+fn check_registry_version(version: &str) -> Result<()> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+');
+    if version.is_empty() || !version.chars().all(valid_char) {
+        anyhow::bail!("Invalid version \"{}\": use letters, digits, `.`, `-` and `+`", version);
+    }
+    Ok(())
+}
+
+// The manifest goes up as signed JSON next to the files, so the registry can check the
+// hashes and the publisher's signature before listing the proof
+async fn registry_push(ctx: &CliContext, client: &reqwest::Client, push: &RegistryPush) -> Result<()> {
+    let RegistryPush {
+        proof_file,
+        elf_file,
+        name,
+        version,
+        key_name,
+        registry,
+        proving_system,
+    } = push;
+    check_registry_name(name)?;
+    check_registry_version(version)?;
+    let proving_system = match *proving_system {
+        Some(proving_system) => proving_system,
+        None => detect_proof_file_system(proof_file)?.unwrap_or(ProvingSystem::Sp1),
+    };
+    let file_size = |path: &Path| {
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .with_context(|| format!("Failed to read file: {}", path.display()))
+    };
+    let manifest = RegistryManifest {
+        name: name.to_string(),
+        version: version.to_string(),
+        proving_system: proving_system.name(),
+        proof_sha256: sha256_file(proof_file)?,
+        elf_sha256: sha256_file(elf_file)?,
+        proof_size: file_size(proof_file)?,
+        elf_size: file_size(elf_file)?,
+        public_key: BASE64.encode(get_public_key(ctx, key_name)?),
+        published_at: soundness_layer::unix_timestamp(),
+    };
+    let manifest_json = serde_json::to_string(&manifest)?;
+    let signature = sign_payload(ctx, manifest_json.as_bytes(), key_name)?;
+
+    let file_name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let form = reqwest::multipart::Form::new()
+        .text("manifest", manifest_json)
+        .text("signature", BASE64.encode(&signature))
+        .part(PROOF_FIELD, file_part(proof_file, &file_name(proof_file)).await?)
+        .part(ELF_FIELD, file_part(elf_file, &file_name(elf_file)).await?);
+
+    let url = format!("{}/api/v1/proofs", registry.trim_end_matches('/'));
+    let pb = create_progress_bar(&format!("📚 Publishing {}@{}...", name, version));
+    let response = match client.post(&url).multipart(form).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            pb.abandon_with_message("❌ Publishing failed");
+            return Err(anyhow::Error::new(e).context(format!("Failed to send request to {}", url)));
+        }
+    };
+    let status = response.status();
+    if !status.is_success() {
+        pb.abandon_with_message("❌ Publishing failed");
+        if status == reqwest::StatusCode::CONFLICT {
+            anyhow::bail!("{}@{} is already published, bump --version", name, version);
+        }
+        anyhow::bail!(
+            "Registry returned status {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+    }
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    pb.finish_and_clear();
+
+    report_field("manifest", &manifest);
+    report_field("url", &body["url"]);
+    println!("📚 Published {}@{} ({}) to {}", name, version, manifest.proving_system, registry);
+    println!("   Proof SHA-256: {}", manifest.proof_sha256);
+    println!("   ELF SHA-256:   {}", manifest.elf_sha256);
+    if let Some(link) = body["url"].as_str() {
+        println!("🔗 {}", link);
+    }
+    Ok(())
+}
+
+// GET from the registry, turning error statuses into errors
+async fn registry_get(client: &reqwest::Client, url: &str) -> Result<reqwest::Response> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to send request to {}", url))?;
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        reqwest::StatusCode::NOT_FOUND => anyhow::bail!("Not found in the registry: {}", url),
+        status => anyhow::bail!(
+            "Registry returned status {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        ),
+    }
+}
+
+// The registry serves the manifest exactly as it was signed at push time, so the files
+// are only written once the signature and both hashes check out
+async fn registry_pull(client: &reqwest::Client, pull: &RegistryPull) -> Result<()> {
+    check_registry_name(&pull.name)?;
+    check_registry_version(&pull.version)?;
+    let release = format!("{}@{}", pull.name, pull.version);
+    let base = format!(
+        "{}/api/v1/proofs/{}/{}",
+        pull.registry.trim_end_matches('/'),
+        pull.name,
+        pull.version
+    );
+
+    let pb = create_progress_bar(&format!("📥 Fetching {}...", release));
+    let fetched = async {
+        let entry: serde_json::Value = registry_get(client, &base).await?.json().await?;
+        let proof = registry_get(client, &format!("{}/proof", base)).await?.bytes().await?;
+        let elf = registry_get(client, &format!("{}/elf", base)).await?.bytes().await?;
+        anyhow::Ok((entry, proof, elf))
+    }
+    .await;
+    let (entry, proof, elf) = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            pb.abandon_with_message("❌ Download failed");
+            return Err(e);
+        }
+    };
+    pb.finish_and_clear();
+
+    let (Some(manifest_json), Some(signature)) = (entry["manifest"].as_str(), entry["signature"].as_str()) else {
+        anyhow::bail!("Registry entry for {} has no manifest and signature", release);
+    };
+    let manifest: RegistryManifest =
+        serde_json::from_str(manifest_json).context("Registry returned an invalid manifest")?;
+    if manifest.name != pull.name || manifest.version != pull.version {
+        anyhow::bail!(
+            "Registry returned the manifest of {}@{} instead of {}",
+            manifest.name,
+            manifest.version,
+            release
+        );
+    }
+    let public_key = BASE64
+        .decode(&manifest.public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid public key in manifest: {}", e))?;
+    if let Some(expected) = &pull.publisher_key {
+        let expected = BASE64
+            .decode(expected.trim())
+            .map_err(|e| anyhow::anyhow!("Invalid --publisher-key: {}", e))?;
+        if expected != public_key {
+            anyhow::bail!(
+                "{} was published by {}, not by --publisher-key",
+                release,
+                public_key_fingerprint(&public_key)
+            );
+        }
+    }
+    let signature = BASE64
+        .decode(signature)
+        .map_err(|e| anyhow::anyhow!("Invalid manifest signature encoding: {}", e))?;
+    if !soundness_layer::verify_signature(&public_key, &signature, manifest_json.as_bytes())? {
+        anyhow::bail!("The manifest signature of {} does not verify, refusing to write the files", release);
+    }
+    for (what, bytes, sha256, size) in [
+        ("proof", &proof, &manifest.proof_sha256, manifest.proof_size),
+        ("ELF", &elf, &manifest.elf_sha256, manifest.elf_size),
+    ] {
+        if bytes.len() as u64 != size || hex::encode(Sha256::digest(bytes)) != *sha256 {
+            anyhow::bail!("The downloaded {} of {} does not match its signed manifest", what, release);
+        }
+    }
+
+    fs::create_dir_all(&pull.output_dir)
+        .with_context(|| format!("Failed to create directory: {}", pull.output_dir.display()))?;
+    let proof_path = pull.output_dir.join(format!("{}-{}.proof", pull.name, pull.version));
+    let elf_path = pull.output_dir.join(format!("{}-{}.elf", pull.name, pull.version));
+    fs::write(&proof_path, &proof).with_context(|| format!("Failed to write to file: {}", proof_path.display()))?;
+    fs::write(&elf_path, &elf).with_context(|| format!("Failed to write to file: {}", elf_path.display()))?;
+
+    println!(
+        "📥 Pulled {} ({}), signed by {}",
+        release,
+        manifest.proving_system,
+        public_key_fingerprint(&public_key)
+    );
+    println!("   Proof: {}", proof_path.display());
+    println!("   ELF:   {}", elf_path.display());
+    report_field("manifest", &manifest);
+    report_field("proof_file", &proof_path);
+    report_field("elf_file", &elf_path);
+
+    if pull.verify_local {
+        match <ProvingSystem as clap::ValueEnum>::from_str(&manifest.proving_system, true) {
+            Ok(ProvingSystem::Risc0) => {
+                verify_risc0_locally(&proof_path, &elf_path, "❌ Local verification failed")?;
+            }
+            _ => eprintln!(
+                "⚠️  No local verifier for {} proofs, only the manifest was checked",
+                manifest.proving_system
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Entries are listed as the registry returns them; one whose manifest signature doesn't
+// verify is still shown, but flagged, since spotting those is part of auditing a registry
+async fn search_registry(client: &reqwest::Client, registry: &str, circuit_hash: &str) -> Result<()> {
+    let circuit_hash = circuit_hash.trim().to_lowercase();
+    if circuit_hash.len() != 64 || !circuit_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("--circuit-hash must be a hex SHA-256 (64 characters)");
+    }
+    let url = format!(
+        "{}/api/v1/proofs?elf_sha256={}",
+        registry.trim_end_matches('/'),
+        circuit_hash
+    );
+    let pb = create_progress_bar("🔎 Searching the registry...");
+    let entries: Vec<serde_json::Value> = match registry_get(client, &url).await {
+        Ok(response) => response.json().await.context("Registry returned an invalid search result")?,
+        Err(e) => {
+            pb.abandon_with_message("❌ Search failed");
+            return Err(e);
+        }
+    };
+    pb.finish_and_clear();
+
+    let mut results = Vec::new();
+    for entry in &entries {
+        let (Some(manifest_json), Some(signature)) = (entry["manifest"].as_str(), entry["signature"].as_str()) else {
+            anyhow::bail!("Registry returned an entry without manifest and signature");
+        };
+        let manifest: RegistryManifest =
+            serde_json::from_str(manifest_json).context("Registry returned an invalid manifest")?;
+        let signed = match (BASE64.decode(&manifest.public_key), BASE64.decode(signature)) {
+            (Ok(public_key), Ok(signature)) => {
+                soundness_layer::verify_signature(&public_key, &signature, manifest_json.as_bytes()).unwrap_or(false)
+            }
+            _ => false,
+        };
+        results.push((manifest, signed));
+    }
+
+    if json_output() {
+        let listings: Vec<_> = results
+            .iter()
+            .map(|(manifest, signed)| serde_json::json!({ "manifest": manifest, "signature_valid": signed }))
+            .collect();
+        report_field("proofs", listings);
+        return Ok(());
+    }
+    if results.is_empty() {
+        println!("No proofs in {} were made by {}", registry, circuit_hash);
+        return Ok(());
+    }
+    println!("🔎 {} proof(s) made by {}:", results.len(), circuit_hash);
+    for (manifest, signed) in &results {
+        outputln!(
+            "   {}@{} ({}), published {} by {}{}",
+            manifest.name,
+            manifest.version,
+            manifest.proving_system,
+            &rfc3339(manifest.published_at)[..10],
+            manifest.public_key,
+            if *signed { "" } else { " ❌ signature does not verify" }
+        );
+    }
+    Ok(())
+}
+
+async fn post_proof(ctx: &CliContext, client: &reqwest::Client, signed: &SignedRequest) -> Result<reqwest::Response> {
+    let mut builder = client
+        .post(format!("{}/api/proof", ctx.endpoint))
+        .header("X-Signature", BASE64.encode(&signed.signature))
+        .header("X-Public-Key", BASE64.encode(&signed.public_key));
+    if let Some(approvals) = &signed.approvals {
+        builder = builder.header("X-Approvals", approvals);
+    }
+    let builder = match &signed.request {
+        ProofRequest::Json(body) => builder.header("Content-Type", "application/json").json(body),
+        ProofRequest::Multipart(multipart) => builder.multipart(multipart.form().await?),
+    };
+    builder.send().await.map_err(|e| {
+        let message = if e.is_timeout() {
+            format!(
+                "Request to {} timed out after {} s (raise it with --timeout)",
+                ctx.endpoint,
+                ctx.timeout.as_secs()
+            )
         } else {
-            // If no password is stored, prompt for it
-            let new_password = prompt_password("Enter password to decrypt the secret key: ")
-                .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
+            format!("Failed to send request to {}", ctx.endpoint)
+        };
+        anyhow::Error::new(e).context(message)
+    })
+}
 
-            // Try to decrypt with the password to verify it's correct
-            if let Err(e) = decrypt_secret_key(encrypted_secret, &new_password) {
-                anyhow::bail!("Invalid password: {}", e);
+/// How `send` retries submissions that failed for transient reasons
+#[derive(clap::Args, Debug, Clone)]
+struct RetryPolicy {
+    /// Extra attempts after a connection error, timeout, or 429/502/503/504 response
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubled after every attempt up to 30 s
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    retry_delay: u64,
+}
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Only failures to reach the server may clear up, anything else would fail the same way again
+fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+// Rate limiting and an unavailable or overloaded gateway; other statuses are answers about the request
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 502 | 503 | 504)
+}
+
+async fn post_proof_with_retry(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    signed: &SignedRequest,
+    retry: &RetryPolicy,
+    pb: &ProgressBar,
+) -> Result<reqwest::Response> {
+    let attempts = retry.retries.saturating_add(1);
+    let mut delay = Duration::from_millis(retry.retry_delay).min(MAX_RETRY_DELAY);
+    let mut attempt = 1;
+    loop {
+        pb.set_message(format!("🚀 Sending to server (attempt {}/{})...", attempt, attempts));
+        let error = match post_proof(ctx, client, signed).await {
+            Ok(response) if !is_retryable_status(response.status()) => return Ok(response),
+            Ok(response) => {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                anyhow::anyhow!("Server returned status {}: {}", status, error_text)
             }
+            Err(e) if is_transient(&e) => e,
+            Err(e) => return Err(e),
+        };
+
+        if attempt >= attempts {
+            return Err(error.context(format!("Submission failed after {} attempt(s)", attempts)));
+        }
+        pb.set_message(format!(
+            "⏳ Attempt {}/{} failed, retrying in {} ms...",
+            attempt,
+            attempts,
+            delay.as_millis()
+        ));
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2).min(MAX_RETRY_DELAY);
+        attempt += 1;
+    }
+}
+
+async fn send_proof(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    key_name: &str,
+    retry: &RetryPolicy,
+    wait: &WaitPolicy,
+    dry_run: bool,
+) -> Result<bool> {
+    let request = build_proof_request(ctx, files, dry_run)?;
+
+    // Sign the canonical string
+    let canonical_string = request.canonical_string();
+    let signature = sign_payload(ctx, canonical_string.as_bytes(), key_name)?;
+    let public_key = get_public_key(ctx, key_name)?;
+
+    if dry_run {
+        print_dry_run(ctx, &request, &signature, &public_key)?;
+        report_field("dry_run", true);
+        report_field("signature", BASE64.encode(&signature));
+        report_field("canonical_string", canonical_string);
+        return Ok(false);
+    }
+
+    let signed = SignedRequest {
+        request,
+        signature,
+        public_key,
+        approvals: None,
+    };
+    post_signed_proof(ctx, client, files, &[key_name], &signed, retry, wait).await
+}
+
+// Sends a signed request and reports the server's answer. `signers` are the key pairs the
+// submission log records, the one that signed X-Signature first. Returns whether the server
+// accepted the proof
+async fn post_signed_proof(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    signers: &[&str],
+    signed: &SignedRequest,
+    retry: &RetryPolicy,
+    wait: &WaitPolicy,
+) -> Result<bool> {
+    let sending_pb = create_progress_bar("🚀 Sending to server...");
+    let response = match post_proof_with_retry(ctx, client, signed, retry, &sending_pb).await {
+        Ok(response) => response,
+        Err(e) => {
+            sending_pb.abandon_with_message(format!("❌ Sending to {} failed", ctx.endpoint));
+            record_submission(ctx, files, signers, false, &format!("{:#}", e));
+            return Err(e);
+        }
+    };
+
+    sending_pb.finish_with_message("🚀 Request sent successfully");
+
+    // Check if the request was successful
+    if response.status().is_success() {
+        println!("\n✅ Successfully sent files to {}", ctx.endpoint);
+        let response_text = response.text().await?;
+        outputln!("Server response: {}", response_text);
+        record_submission(ctx, files, signers, true, &response_text);
+        report_field("accepted", true);
+        report_field("response", &response_text);
+        if wait.wait {
+            wait_for_verification(ctx, client, &response_text, wait).await?;
+        }
+        Ok(true)
+    } else {
+        let status = response.status();
+        println!("\n❌ Error: Server returned status {}", status);
+        let error_text = response.text().await?;
+        outputln!("Error details: {}", error_text);
+        record_submission(ctx, files, signers, false, &format!("status {}: {}", status, error_text));
+        report_field("accepted", false);
+        report_field("status", status.as_u16());
+        report_field("response", &error_text);
+        Ok(false)
+    }
+}
+
+// Every approver signs the same canonical string as X-Signature does, so the server checks each
+// approval the same way. Approvers are asked in order until enough have signed; one whose key
+// can't sign (wrong password, public key only) is skipped
+async fn threshold_send(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    approvers: &[String],
+    threshold: usize,
+    retry: &RetryPolicy,
+    wait: &WaitPolicy,
+) -> Result<bool> {
+    if threshold == 0 || threshold > approvers.len() {
+        anyhow::bail!("--threshold must be between 1 and the number of --approvers ({})", approvers.len());
+    }
+    let key_store = ctx.load_key_store()?;
+    let mut public_keys = BTreeSet::new();
+    for approver in approvers {
+        let key_pair = key_store
+            .keys
+            .get(approver)
+            .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", approver))?;
+        if !public_keys.insert(&key_pair.public_key) {
+            anyhow::bail!("--approvers lists the key of '{}' more than once", approver);
+        }
+    }
+
+    let request = build_proof_request(ctx, files, false)?;
+    let canonical_string = request.canonical_string();
+    let mut approvals = Vec::new();
+    for (index, approver) in approvers.iter().enumerate() {
+        if approvals.len() == threshold || approvals.len() + (approvers.len() - index) < threshold {
+            break;
+        }
+        eprintln!("✍️  Approval {} of {}: '{}'", approvals.len() + 1, threshold, approver);
+        // The password cache is per run, and every approver has their own password
+        *PASSWORD_CACHE.lock().unwrap() = None;
+        match sign_payload(ctx, canonical_string.as_bytes(), approver) {
+            Ok(signature) => approvals.push((approver.as_str(), get_public_key(ctx, approver)?, signature)),
+            Err(e) => eprintln!("⚠️  No approval from '{}': {:#}", approver, e),
+        }
+    }
+    *PASSWORD_CACHE.lock().unwrap() = None;
+    if approvals.len() < threshold {
+        anyhow::bail!(
+            "Only {} of {} required approvals were collected, nothing was sent",
+            approvals.len(),
+            threshold
+        );
+    }
+
+    let names: Vec<&str> = approvals.iter().map(|(name, _, _)| *name).collect();
+    println!("🗳️  {} of {} approvals collected from {}", threshold, approvers.len(), names.join(", "));
+    report_field("approvers", &names);
+    let header: Vec<_> = approvals
+        .iter()
+        .map(|(_, public_key, signature)| {
+            serde_json::json!({ "public_key": BASE64.encode(public_key), "signature": BASE64.encode(signature) })
+        })
+        .collect();
+    let (_, public_key, signature) = approvals.swap_remove(0);
+    let signed = SignedRequest {
+        request,
+        signature,
+        public_key,
+        approvals: Some(serde_json::to_string(&header)?),
+    };
+    post_signed_proof(ctx, client, files, &names, &signed, retry, wait).await
+}
+
+const SUBMISSIONS_LOG: &str = "submissions.log";
+
+/// One line of submissions.log: a proof sent with `send`, and what the server made of it
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct SubmissionRecord {
+    /// RFC 3339, in UTC
+    timestamp: String,
+    key: String,
+    proof_hash: String,
+    elf_hash: String,
+    proving_system: String,
+    endpoint: String,
+    /// `ok` or `error`
+    status: String,
+    /// The server's response body, or the error if there was none
+    response: String,
+    /// Every key pair that approved a `threshold-send`, `key` among them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    approvers: Vec<String>,
+    /// Where the files were and the names signed for them, so `rotate-key --resubmit` can
+    /// send them again. Lines written by older versions don't have them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elf_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elf_name: Option<String>,
+}
+
+// The log is only ever appended to, and a failure to write it must not hide the outcome of the submission
+fn record_submission(ctx: &CliContext, files: &ProofFiles, signers: &[&str], ok: bool, response: &str) {
+    let append = || -> Result<()> {
+        let (proof_name, elf_name) = files.resolve_names().ok().unzip();
+        let record = SubmissionRecord {
+            timestamp: rfc3339(soundness_layer::unix_timestamp()),
+            key: signers.first().copied().unwrap_or_default().to_string(),
+            proof_hash: sha256_file(files.proof_file())?,
+            elf_hash: sha256_file(files.elf_file())?,
+            proving_system: files.proving_system().name(),
+            endpoint: ctx.endpoint.clone(),
+            status: if ok { "ok" } else { "error" }.to_string(),
+            response: response.to_string(),
+            approvers: match signers {
+                [_, _, ..] => signers.iter().map(|name| name.to_string()).collect(),
+                _ => Vec::new(),
+            },
+            proof_file: fs::canonicalize(files.proof_file()).ok(),
+            elf_file: fs::canonicalize(files.elf_file()).ok(),
+            proof_name,
+            elf_name,
+        };
+        let log_path = ctx.key_store_path.with_file_name(SUBMISSIONS_LOG);
+        let mut log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("Failed to open submission log: {}", log_path.display()))?;
+        writeln!(log, "{}", serde_json::to_string(&record)?)
+            .with_context(|| format!("Failed to write to file: {}", log_path.display()))
+    };
+    if let Err(e) = append() {
+        eprintln!("⚠️  The submission was not recorded: {:#}", e);
+    }
+}
+
+// Submissions signed by `key_name` whose latest attempt the server didn't accept. Proofs whose
+// files are gone or changed since, or that were logged without their paths, can't be sent again
+fn pending_submissions(ctx: &CliContext, key_name: &str) -> Result<Vec<ProofFiles>> {
+    let log_path = ctx.key_store_path.with_file_name(SUBMISSIONS_LOG);
+    if !log_path.exists() {
+        return Ok(Vec::new());
+    }
+    let log = fs::read_to_string(&log_path)
+        .with_context(|| format!("Failed to read submission log: {}", log_path.display()))?;
+
+    // Later lines for the same files replace earlier ones
+    let mut latest = BTreeMap::new();
+    for record in log.lines().filter_map(|line| serde_json::from_str::<SubmissionRecord>(line).ok()) {
+        if record.key != key_name {
+            continue;
+        }
+        if let (Some(proof_file), Some(elf_file)) = (record.proof_file.clone(), record.elf_file.clone()) {
+            latest.insert((proof_file, elf_file), record);
+        }
+    }
+
+    let mut pending = Vec::new();
+    for ((proof_file, elf_file), record) in latest {
+        if record.status == "ok" {
+            continue;
+        }
+        let unchanged = |path: &Path, hash: &str| sha256_file(path).is_ok_and(|current| current == hash);
+        if !unchanged(&proof_file, &record.proof_hash) || !unchanged(&elf_file, &record.elf_hash) {
+            eprintln!(
+                "⚠️  Not re-submitting {}: it or its ELF file is gone or changed since it was sent",
+                proof_file.display()
+            );
+            continue;
+        }
+        pending.push(ProofFiles {
+            proof_file,
+            elf_file,
+            options: SubmissionOptions {
+                proving_system: <ProvingSystem as clap::ValueEnum>::from_str(&record.proving_system, true).ok(),
+                proof_name: record.proof_name,
+                elf_name: record.elf_name,
+                upload: UploadFormat::Json,
+                compress: false,
+            },
+        });
+    }
+    Ok(pending)
+}
+
+// Send the pending submissions of a rotated key again, signed by its successor
+async fn resubmit_pending(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    old_name: &str,
+    new_name: &str,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    let pending = pending_submissions(ctx, old_name)?;
+    report_field("resubmitted", pending.len());
+    if pending.is_empty() {
+        println!("📭 No pending submissions of '{}' to re-submit", old_name);
+        return Ok(());
+    }
+
+    println!("\n📤 Re-submitting {} proof(s) the server didn't accept from '{}'", pending.len(), old_name);
+    let wait = WaitPolicy {
+        wait: false,
+        poll_interval: 5,
+        wait_timeout: 600,
+    };
+    let mut accepted = 0;
+    for files in &pending {
+        println!("\n📄 {}", files.proof_file().display());
+        match send_proof(ctx, client, files, new_name, retry, &wait, false).await {
+            Ok(true) => accepted += 1,
+            Ok(false) => {}
+            Err(e) => eprintln!("❌ {}: {:#}", files.proof_file().display(), e),
+        }
+    }
+    report_field("resubmitted_accepted", accepted);
+    if accepted < pending.len() {
+        anyhow::bail!(
+            "{} of {} re-submitted proof(s) were not accepted, see list-submissions --filter-key {}",
+            pending.len() - accepted,
+            pending.len(),
+            new_name
+        );
+    }
+    println!("\n✅ All {} re-submitted proof(s) were accepted", accepted);
+    Ok(())
+}
+
+fn list_submissions(ctx: &CliContext, filter_key: Option<&str>, since: Option<&str>) -> Result<()> {
+    // Timestamps are fixed-width RFC 3339, so comparing the strings compares the times
+    let since = match since.map(str::trim) {
+        Some(since) if !since.is_empty() && since.chars().all(|c| c.is_ascii_digit()) => {
+            Some(rfc3339(since.parse().context("--since is out of range")?))
+        }
+        Some(since) if since.len() >= 10 && since.as_bytes()[4] == b'-' && since.as_bytes()[7] == b'-' => {
+            Some(since.to_string())
+        }
+        Some(since) => anyhow::bail!("Invalid --since \"{}\": use a Unix timestamp or a date like 2025-10-18", since),
+        None => None,
+    };
+
+    let log_path = ctx.key_store_path.with_file_name(SUBMISSIONS_LOG);
+    if !log_path.exists() {
+        println!("No submissions recorded yet. Proofs sent with 'send' are logged to {}", log_path.display());
+        return Ok(());
+    }
+    let log = fs::read_to_string(&log_path)
+        .with_context(|| format!("Failed to read submission log: {}", log_path.display()))?;
+    let mut unreadable = 0;
+    let records: Vec<SubmissionRecord> = log
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let record = serde_json::from_str(line).ok();
+            unreadable += usize::from(record.is_none());
+            record
+        })
+        .filter(|record: &SubmissionRecord| {
+            filter_key.is_none_or(|key| record.key == key || record.approvers.iter().any(|approver| approver == key))
+        })
+        .filter(|record| since.as_ref().is_none_or(|since| record.timestamp >= *since))
+        .collect();
+
+    report_field("submissions", &records);
+    if records.is_empty() {
+        println!("No matching submissions in {}", log_path.display());
+    } else {
+        println!("📜 {} submission(s) in {}:", records.len(), log_path.display());
+    }
+    for record in &records {
+        let marker = if record.status == "ok" { "✅" } else { "❌" };
+        outputln!(
+            "\n{} {}  key '{}'  {}  {}",
+            marker, record.timestamp, record.key, record.proving_system, record.endpoint
+        );
+        if !record.approvers.is_empty() {
+            outputln!("   Approvers:     {}", record.approvers.join(", "));
+        }
+        outputln!("   Proof SHA-256: {}", record.proof_hash);
+        outputln!("   ELF SHA-256:   {}", record.elf_hash);
+        let response = record.response.lines().next().unwrap_or_default();
+        if response.chars().count() > 120 {
+            outputln!("   Response:      {}...", response.chars().take(120).collect::<String>());
+        } else {
+            outputln!("   Response:      {}", response);
+        }
+    }
+    if unreadable > 0 {
+        println!("\n⚠️  Skipped {} unreadable line(s)", unreadable);
+    }
+    Ok(())
+}
+
+/// Proof that a proof/ELF pair was signed and published to a transparency log before submission,
+/// written by `notarize` and sent with `send --notarization-ticket`
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct NotarizationTicket {
+    proof_sha256: String,
+    elf_sha256: String,
+    /// Base64 ed25519 public key that signed the hashes
+    public_key: String,
+    /// Base64 signature over the raw proof digest followed by the raw ELF digest
+    signature: String,
+    log_url: String,
+    log_entry_id: String,
+    log_index: u64,
+    /// When the log integrated the entry (or the ticket was made, if the log didn't say), in Unix seconds
+    timestamp: u64,
+}
+
+async fn notarize(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    proof_file: &Path,
+    elf_file: &Path,
+    key_name: &str,
+    rekor_url: &str,
+    output: Option<&Path>,
+) -> Result<()> {
+    let proof_sha256 = sha256_file(proof_file)?;
+    let elf_sha256 = sha256_file(elf_file)?;
+    let digests = [hex::decode(&proof_sha256)?, hex::decode(&elf_sha256)?].concat();
+    let signature = sign_payload(ctx, &digests, key_name)?;
+    let public_key = get_public_key(ctx, key_name)?;
+
+    let pb = create_progress_bar("📜 Publishing to the transparency log...");
+    let entry = match add_rekor_entry(client, rekor_url, &digests, &signature, &public_key).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            pb.abandon_with_message("❌ Notarization failed");
+            return Err(e);
+        }
+    };
+    pb.finish_with_message(format!("📜 Logged at index {}", entry.log_index));
 
-            // Store the password and key store hash
-            *password_guard = Some((new_password.clone(), key_store_hash));
-            new_password
+    let ticket = NotarizationTicket {
+        proof_sha256,
+        elf_sha256,
+        public_key: BASE64.encode(&public_key),
+        signature: BASE64.encode(&signature),
+        log_url: rekor_url.to_string(),
+        log_entry_id: entry.uuid,
+        log_index: entry.log_index,
+        timestamp: entry.integrated_time.unwrap_or_else(soundness_layer::unix_timestamp),
+    };
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let mut path = proof_file.as_os_str().to_owned();
+            path.push(".ticket.json");
+            PathBuf::from(path)
         }
-    }; // password_guard is dropped here
+    };
+    fs::write(&output, format!("{}\n", serde_json::to_string_pretty(&ticket)?))
+        .with_context(|| format!("Failed to write ticket file: {}", output.display()))?;
+    report_field("ticket", &output);
+    println!("🎫 Notarization ticket written to {}", output.display());
+    println!("   Attach it with: send --notarization-ticket {}", output.display());
+    Ok(())
+}
 
-    // Only show the progress bar after we have the password
-    let pb = create_progress_bar("✍️  Signing payload...");
+/// A TEE quote over a proof's SHA-256, written by `attest` and sent with `send --tee-attestation`
+#[derive(Debug, Serialize, serde::Deserialize)]
+struct TeeAttestation {
+    tee: TeePlatform,
+    proof_sha256: String,
+    /// Hex of the 64 bytes of report data in the quote: the raw proof digest, zero-padded
+    report_data: String,
+    /// Base64 quote (SGX, TDX) or signed attestation report (SEV-SNP), as the platform returned it
+    quote: String,
+    /// When the quote was made, in Unix seconds
+    timestamp: u64,
+}
 
-    let secret_key_bytes = decrypt_secret_key(encrypted_secret, &password)?;
-    let secret_key_array: [u8; 32] = secret_key_bytes.clone()
+fn attest(proof_file: &Path, platform: TeePlatform, output: Option<&Path>) -> Result<()> {
+    let proof_sha256 = sha256_file(proof_file)?;
+    let digest: [u8; 32] = hex::decode(&proof_sha256)?
         .try_into()
-        .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
+        .map_err(|_| anyhow::anyhow!("SHA-256 digest is not 32 bytes"))?;
+    let report_data = tee::report_data(&digest);
 
-    let signing_key = SigningKey::from_bytes(&secret_key_array);
-    let signature = signing_key.sign(payload);
-    pb.finish_with_message("✍️  Payload signed successfully");
+    let pb = create_progress_bar(&format!("🛡️  Requesting a {} quote...", platform.name()));
+    let quote = match tee::generate_quote(platform, &report_data) {
+        Ok(quote) => quote,
+        Err(e) => {
+            pb.abandon_with_message("❌ Attestation failed");
+            return Err(e);
+        }
+    };
+    pb.finish_with_message(format!("🛡️  Got a {}-byte {} quote", quote.len(), platform.name()));
 
-    Ok(signature.to_bytes().to_vec())
+    let attestation = TeeAttestation {
+        tee: platform,
+        proof_sha256,
+        report_data: hex::encode(report_data),
+        quote: BASE64.encode(&quote),
+        timestamp: soundness_layer::unix_timestamp(),
+    };
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let mut path = proof_file.as_os_str().to_owned();
+            path.push(".attestation.json");
+            PathBuf::from(path)
+        }
+    };
+    fs::write(&output, format!("{}\n", serde_json::to_string_pretty(&attestation)?))
+        .with_context(|| format!("Failed to write attestation file: {}", output.display()))?;
+    report_field("attestation", &output);
+    println!("🛡️  TEE attestation written to {}", output.display());
+    println!("   Attach it with: send --tee-attestation {}", output.display());
+    Ok(())
 }
 
-fn get_public_key(key_name: &str) -> Result<Vec<u8>> {
-    let key_store = load_key_store()?;
-    let key_pair = key_store
-        .keys
-        .get(key_name)
-        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", key_name))?;
-    Ok(key_pair.public_key.clone())
+// Sent as base64 JSON in X-TEE-Attestation. Like a notarization ticket, it must be for the proof being sent
+fn tee_attestation_header(attestation_file: &Path, proof_file: &Path) -> Result<reqwest::header::HeaderValue> {
+    let contents = fs::read_to_string(attestation_file)
+        .with_context(|| format!("Failed to read TEE attestation: {}", attestation_file.display()))?;
+    let attestation: TeeAttestation = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid TEE attestation: {}", attestation_file.display()))?;
+    let proof_sha256 = sha256_file(proof_file)?;
+    if !attestation.proof_sha256.eq_ignore_ascii_case(&proof_sha256)
+        || !attestation.report_data.to_lowercase().starts_with(&proof_sha256)
+    {
+        anyhow::bail!(
+            "TEE attestation {} is for a different proof than {}",
+            attestation_file.display(),
+            proof_file.display()
+        );
+    }
+    let encoded = BASE64.encode(serde_json::to_vec(&attestation)?);
+    Ok(reqwest::header::HeaderValue::from_str(&encoded)?)
 }
 
-fn export_key(name: &str) -> Result<()> {
-    let key_store = load_key_store()?;
-    let key_pair = key_store
-        .keys
-        .get(name)
-        .ok_or_else(|| anyhow::anyhow!("Key pair '{}' not found", name))?;
+// The ticket travels as base64 JSON in X-Notarization-Ticket. It must be for the proof being sent
+fn notarization_ticket_header(ticket_file: &Path, proof_file: &Path) -> Result<reqwest::header::HeaderValue> {
+    let contents = fs::read_to_string(ticket_file)
+        .with_context(|| format!("Failed to read notarization ticket: {}", ticket_file.display()))?;
+    let ticket: NotarizationTicket = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid notarization ticket: {}", ticket_file.display()))?;
+    if !ticket.proof_sha256.eq_ignore_ascii_case(&sha256_file(proof_file)?) {
+        anyhow::bail!(
+            "Notarization ticket {} is for a different proof than {}",
+            ticket_file.display(),
+            proof_file.display()
+        );
+    }
+    let encoded = BASE64.encode(serde_json::to_vec(&ticket)?);
+    Ok(reqwest::header::HeaderValue::from_str(&encoded)?)
+}
 
-    let encrypted_secret = key_pair
-        .encrypted_secret_key
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("Secret key not found or not encrypted for '{}'. Cannot export mnemonic.", name))?;
+fn verify_proof_locally(files: &ProofFiles) -> Result<()> {
+    if files.proving_system() != ProvingSystem::Risc0 {
+        eprintln!(
+            "⚠️  Only risc0 proofs can be verified locally, sending the {} proof unchecked",
+            files.proving_system().name()
+        );
+        return Ok(());
+    }
+    verify_risc0_locally(files.proof_file(), files.elf_file(), "❌ Local verification failed, nothing was sent")
+}
 
-    // Prompt for password
-    let password = prompt_password("Enter password to decrypt the secret key: ")
-        .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
-
-    // Decrypt the secret key with better error handling
-    let secret_key_bytes = match decrypt_secret_key(encrypted_secret, &password) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            println!("\n❌ Error: Invalid password. Please try again with the correct password.");
-            return Ok(());
+// Shows the verifier's progress, ending on `failure_message` if the receipt doesn't verify
+fn verify_risc0_locally(proof_file: &Path, elf_file: &Path, failure_message: &'static str) -> Result<()> {
+    let pb = create_progress_bar("🔎 Verifying the risc0 receipt locally...");
+    match verify_risc0_receipt(proof_file, elf_file) {
+        Ok(image_id) => {
+            pb.finish_with_message(format!("🔎 Receipt verified locally against image ID {}", image_id));
+            Ok(())
         }
-    };
+        Err(e) => {
+            pb.abandon_with_message(failure_message);
+            Err(e)
+        }
+    }
+}
 
-    // Generate mnemonic from secret key
-    let mnemonic = bip39::Mnemonic::from_entropy(&secret_key_bytes)
-        .map_err(|e| anyhow::anyhow!("Failed to generate mnemonic: {}", e))?;
-    let mnemonic_string = mnemonic.to_string();
+// Receipts are bincode, as risc0's own tools write them, with nothing after the receipt
+#[cfg(feature = "risc0")]
+fn decode_risc0_receipt(proof: &[u8]) -> Result<risc0_zkvm::Receipt, bincode::Error> {
+    use bincode::Options;
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(proof)
+}
 
-    println!("\n🔑 Mnemonic for key pair '{}':\n", name);
-    println!("{}", mnemonic_string);
-    println!("\n⚠️  WARNING: Keep this mnemonic secure and never share it with anyone!");
+#[cfg(feature = "risc0")]
+fn is_risc0_receipt(proof: &[u8]) -> bool {
+    decode_risc0_receipt(proof).is_ok()
+}
+
+// Without the decoder nothing can be told about a receipt
+#[cfg(not(feature = "risc0"))]
+fn is_risc0_receipt(_proof: &[u8]) -> bool {
+    false
+}
+
+// The ELF is the risc0 program binary whose image ID the receipt commits to. Returns the image ID as hex
+#[cfg(feature = "risc0")]
+fn verify_risc0_receipt(proof_file: &Path, elf_file: &Path) -> Result<String> {
+    let proof =
+        fs::read(proof_file).with_context(|| format!("Failed to read proof file: {}", proof_file.display()))?;
+    let elf = fs::read(elf_file).with_context(|| format!("Failed to read ELF file: {}", elf_file.display()))?;
+    let receipt = decode_risc0_receipt(&proof)
+        .map_err(|e| anyhow::anyhow!("{} is not a risc0 receipt: {}", proof_file.display(), e))?;
+    let image_id = risc0_zkvm::compute_image_id(&elf)
+        .map_err(|e| anyhow::anyhow!("Failed to compute the image ID of {}: {}", elf_file.display(), e))?;
+    receipt
+        .verify(image_id)
+        .map_err(|e| anyhow::anyhow!("risc0 receipt verification failed: {}", e))?;
+    Ok(hex::encode(image_id.as_bytes()))
+}
+
+#[cfg(not(feature = "risc0"))]
+fn verify_risc0_receipt(_proof_file: &Path, _elf_file: &Path) -> Result<String> {
+    anyhow::bail!("This build can't verify risc0 receipts, rebuild with `--features risc0` to check them locally")
+}
+
+/// How each proof is submitted, shared by every line of a --batch-file
+struct SendOptions<'a> {
+    key_name: &'a str,
+    retry: &'a RetryPolicy,
+    wait: &'a WaitPolicy,
+    sigstore: &'a SigstoreOptions,
+    dry_run: bool,
+    verify_locally: bool,
+}
+
+// GET <endpoint>/api/keys/<base64 public key>, so an unregistered key fails before time goes
+// into reading and signing a large proof. Only a 404 means the server doesn't know the key
+async fn check_key_in_server(ctx: &CliContext, client: &reqwest::Client, key_name: &str) -> Result<()> {
+    let public_key = BASE64.encode(get_public_key(ctx, key_name)?);
+    let invalid_endpoint = || anyhow::anyhow!("Invalid endpoint {}", ctx.endpoint);
+    let mut url = reqwest::Url::parse(&ctx.endpoint).map_err(|_| invalid_endpoint())?;
+    // Pushed as a path segment, so the `/` and `+` of base64 are escaped
+    url.path_segments_mut()
+        .map_err(|_| invalid_endpoint())?
+        .pop_if_empty()
+        .extend(["api", "keys", &public_key]);
+
+    let pb = create_progress_bar("🔎 Checking the server knows the key...");
+    let response = client.get(url.clone()).send().await;
+    pb.finish_and_clear();
+    let status = response
+        .with_context(|| format!("Failed to check the key with {}", ctx.endpoint))?
+        .status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        anyhow::bail!(
+            "{} does not know the public key of '{}'. Run `soundness-cli sync-public-keys` first",
+            ctx.endpoint,
+            key_name
+        );
+    }
+    if !status.is_success() {
+        anyhow::bail!("Checking the key with {} failed: server returned status {}", url, status);
+    }
+    println!("🔎 {} knows the public key of '{}'", ctx.endpoint, key_name);
     Ok(())
 }
 
-fn import_key(name: &str) -> Result<()> {
-    let mut key_store = load_key_store()?;
+// Returns whether the server accepted the proof
+async fn submit_proof(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    options: &SendOptions<'_>,
+) -> Result<bool> {
+    if options.verify_locally {
+        verify_proof_locally(files)?;
+    }
+    let accepted = send_proof(
+        ctx,
+        client,
+        files,
+        options.key_name,
+        options.retry,
+        options.wait,
+        options.dry_run,
+    )
+    .await?;
+    if accepted && options.sigstore.sigstore_upload {
+        upload_to_rekor(ctx, client, files, options.key_name, options.sigstore).await?;
+    }
+    Ok(accepted)
+}
 
-    if key_store.keys.contains_key(name) {
-        anyhow::bail!("Key pair with name '{}' already exists", name);
+// Submit every pair in --batch-file in order. The key password is cached after the first
+// signature, so the whole batch prompts for it at most once
+async fn send_batch(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    batch: &[ProofFiles],
+    options: &SendOptions<'_>,
+    fail_fast: bool,
+) -> Result<()> {
+    let pb = new_progress_bar(batch.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} proofs, {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+    );
+
+    *BATCH_FILE_CACHE.lock().unwrap() = Some(FileCache::default());
+    let mut succeeded = 0;
+    let mut failures = Vec::new();
+    for files in batch {
+        let label = format!("{} + {}", files.proof_file().display(), files.elf_file().display());
+        pb.set_message(label.clone());
+        match submit_proof(ctx, client, files, options).await {
+            Ok(true) => succeeded += 1,
+            // A dry run never reaches the server, so there is nothing to fail
+            Ok(false) if options.dry_run => succeeded += 1,
+            Ok(false) => failures.push(format!("{}: rejected by the server", label)),
+            Err(e) => failures.push(format!("{}: {:#}", label, e)),
+        }
+        pb.inc(1);
+        if fail_fast && !failures.is_empty() {
+            break;
+        }
     }
+    *BATCH_FILE_CACHE.lock().unwrap() = None;
+    pb.finish_and_clear();
 
-    // Prompt for mnemonic
-    println!("\nEnter your mnemonic phrase (12 or 24 words):");
-    let mut mnemonic_input = String::new();
-    std::io::stdin().read_line(&mut mnemonic_input)?;
-    let mnemonic_input = mnemonic_input.trim();
+    let skipped = batch.len() - succeeded - failures.len();
+    report_field("succeeded", succeeded);
+    report_field("failed", &failures);
+    report_field("skipped", skipped);
+    println!(
+        "\n📊 Batch finished: {} succeeded, {} failed, {} skipped",
+        succeeded,
+        failures.len(),
+        skipped
+    );
+    if !failures.is_empty() {
+        for failure in &failures {
+            println!("❌ {}", failure);
+        }
+        anyhow::bail!("{} of {} submission(s) failed", failures.len(), batch.len());
+    }
+    Ok(())
+}
 
-    // Parse mnemonic
-    let mnemonic = bip39::Mnemonic::from_str(mnemonic_input)
-        .map_err(|e| anyhow::anyhow!("Invalid mnemonic phrase: {}", e))?;
+/// Where `send --sigstore-upload` records submissions
+#[derive(clap::Args, Debug, Clone)]
+struct SigstoreOptions {
+    /// After the server accepts the proof, sign an attestation of it (file hashes, key and time)
+    /// and add it to the Rekor transparency log. The log index goes to receipts.jsonl next to the key store
+    #[arg(long)]
+    sigstore_upload: bool,
 
-    // Convert mnemonic to secret key
-    let secret_key_bytes = mnemonic.to_entropy();
-    let secret_key_array: [u8; 32] = secret_key_bytes.clone()
-        .try_into()
-        .map_err(|_| anyhow::anyhow!("Invalid secret key length"))?;
+    /// Rekor server for --sigstore-upload
+    #[arg(long, value_name = "URL", default_value = "https://rekor.sigstore.dev", requires = "sigstore_upload")]
+    rekor_url: String,
+}
 
-    // Create signing key and get public key
-    let signing_key = SigningKey::from_bytes(&secret_key_array);
-    let verifying_key = signing_key.verifying_key();
-    let public_key_bytes = verifying_key.to_bytes();
-    let public_key_string = BASE64.encode(&public_key_bytes);
+// DER prefix of an ed25519 SubjectPublicKeyInfo, followed by the 32 key bytes
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
 
-    // Get password for secret key encryption
-    let password = prompt_password("\nEnter password to encrypt the secret key: ")
-        .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
-    let confirm_password = prompt_password("Confirm password: ")
-        .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
+fn ed25519_public_key_pem(public_key: &[u8]) -> String {
+    let der = [&ED25519_SPKI_PREFIX[..], public_key].concat();
+    format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", BASE64.encode(der))
+}
 
-    if password != confirm_password {
-        anyhow::bail!("Passwords do not match");
+/// A new entry in a Rekor transparency log
+struct RekorEntry {
+    uuid: String,
+    log_index: u64,
+    /// When the log integrated the entry, in Unix seconds
+    integrated_time: Option<u64>,
+}
+
+// Rekor's `rekord` entries carry the signed data itself and accept ed25519 keys as PEM
+// ("x509" format). Only small documents are logged, so they are uploaded whole and Rekor hashes them
+async fn add_rekor_entry(
+    client: &reqwest::Client,
+    rekor_url: &str,
+    data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<RekorEntry> {
+    let entry = serde_json::json!({
+        "apiVersion": "0.0.1",
+        "kind": "rekord",
+        "spec": {
+            "signature": {
+                "format": "x509",
+                "content": BASE64.encode(signature),
+                "publicKey": { "content": BASE64.encode(ed25519_public_key_pem(public_key)) },
+            },
+            "data": { "content": BASE64.encode(data) },
+        },
+    });
+
+    let url = format!("{}/api/v1/log/entries", rekor_url.trim_end_matches('/'));
+    let response = client
+        .post(&url)
+        .json(&entry)
+        .send()
+        .await
+        .with_context(|| format!("Failed to send request to {}", url))?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("Rekor returned status {}: {}", status, text);
     }
+    // The response maps the new entry's UUID to the entry itself
+    let entries: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&text).with_context(|| format!("Unexpected Rekor response: {}", text))?;
+    entries
+        .iter()
+        .find_map(|(uuid, entry)| {
+            Some(RekorEntry {
+                uuid: uuid.clone(),
+                log_index: entry["logIndex"].as_u64()?,
+                integrated_time: entry["integratedTime"].as_u64(),
+            })
+        })
+        .ok_or_else(|| anyhow::anyhow!("Rekor response has no log index: {}", text))
+}
 
-    // Encrypt the secret key
-    let encrypted_secret = encrypt_secret_key(&secret_key_bytes, &password)?;
+// The attestation is signed with the submitting key, so the log entry ties the key to the files
+async fn upload_to_rekor(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    key_name: &str,
+    sigstore: &SigstoreOptions,
+) -> Result<()> {
+    let public_key = get_public_key(ctx, key_name)?;
+    let submitted_at = soundness_layer::unix_timestamp();
+    let proof_sha256 = sha256_file(files.proof_file())?;
+    let elf_sha256 = sha256_file(files.elf_file())?;
+    let attestation = serde_json::to_vec(&serde_json::json!({
+        "endpoint": ctx.endpoint,
+        "proof_sha256": proof_sha256,
+        "elf_sha256": elf_sha256,
+        "proving_system": files.proving_system().name(),
+        "public_key": BASE64.encode(&public_key),
+        "submitted_at": submitted_at,
+    }))?;
+    let signature = sign_payload(ctx, &attestation, key_name)?;
 
-    // Save the key pair
-    key_store.keys.insert(
-        name.to_string(),
-        KeyPair {
-            public_key: public_key_bytes.to_vec(),
-            public_key_string: public_key_string.clone(),
-            encrypted_secret_key: Some(encrypted_secret),
-        },
-    );
+    let pb = create_progress_bar("📜 Adding the submission to the Rekor transparency log...");
+    let entry = match add_rekor_entry(client, &sigstore.rekor_url, &attestation, &signature, &public_key).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            pb.abandon_with_message("❌ Rekor upload failed");
+            return Err(e.context("The proof was accepted, but it could not be added to the Rekor log"));
+        }
+    };
+    pb.finish_with_message(format!("📜 Added to the Rekor log at index {}", entry.log_index));
 
-    save_key_store(&key_store)?;
-    println!("\n✅ Successfully imported key pair '{}'", name);
-    println!("🔑 Public key: {}", public_key_string);
+    let receipt = serde_json::json!({
+        "submitted_at": submitted_at,
+        "endpoint": ctx.endpoint,
+        "key_name": key_name,
+        "proof_sha256": proof_sha256,
+        "elf_sha256": elf_sha256,
+        "rekor_url": sigstore.rekor_url,
+        "rekor_uuid": entry.uuid,
+        "rekor_log_index": entry.log_index,
+    });
+    let receipts_path = ctx.key_store_path.with_file_name("receipts.jsonl");
+    let mut receipts = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&receipts_path)
+        .with_context(|| format!("Failed to open receipts file: {}", receipts_path.display()))?;
+    writeln!(receipts, "{}", receipt)
+        .with_context(|| format!("Failed to write to file: {}", receipts_path.display()))?;
+    println!("🧾 Receipt appended to {}", receipts_path.display());
+    Ok(())
+}
+
+/// How `send --wait` follows an accepted submission until the server has verified it
+#[derive(clap::Args, Debug, Clone)]
+struct WaitPolicy {
+    /// After the server accepts the proof, poll its status until it is verified or failed
+    #[arg(long)]
+    wait: bool,
+
+    /// Seconds between status checks
+    #[arg(long, value_name = "SECONDS", default_value_t = 5, requires = "wait")]
+    poll_interval: u64,
+
+    /// Give up waiting after this many seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 600, requires = "wait")]
+    wait_timeout: u64,
+}
+
+// The job id from the submission response; servers that verify synchronously don't send one
+fn proof_job_id(response_text: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(response_text).ok()?;
+    match &body["id"] {
+        serde_json::Value::String(id) => Some(id.clone()),
+        serde_json::Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+async fn fetch_proof_status(client: &reqwest::Client, url: &str) -> Result<String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch proof status from {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("Server returned status {}: {}", status, error_text);
+    }
+    let body: serde_json::Value = response.json().await?;
+    body["status"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Proof status response has no status field: {}", body))
+}
+
+async fn wait_for_verification(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    response_text: &str,
+    wait: &WaitPolicy,
+) -> Result<()> {
+    let Some(id) = proof_job_id(response_text) else {
+        println!("⚠️  The server response has no job id, nothing to wait for");
+        return Ok(());
+    };
+
+    let url = format!("{}/api/proof/{}", ctx.endpoint, id);
+    let pb = create_progress_bar(&format!("⏳ Waiting for proof {} to be verified...", id));
+    let started = Instant::now();
+    loop {
+        // A failed check may be a hiccup on the server, so keep polling until the deadline
+        let last_status = match fetch_proof_status(client, &url).await {
+            Ok(status) if status == "verified" => {
+                pb.finish_with_message(format!("✅ Proof {} verified", id));
+                return Ok(());
+            }
+            Ok(status) if status == "failed" => {
+                pb.abandon_with_message(format!("❌ Proof {} failed verification", id));
+                anyhow::bail!("Proof {} failed verification", id);
+            }
+            Ok(status) => status,
+            Err(e) => format!("unavailable ({})", e),
+        };
+
+        let elapsed = started.elapsed().as_secs();
+        if elapsed >= wait.wait_timeout {
+            pb.abandon_with_message(format!("⌛ Gave up waiting for proof {}", id));
+            anyhow::bail!(
+                "Timed out after {} s waiting for proof {} (last status: {})",
+                wait.wait_timeout,
+                id,
+                last_status
+            );
+        }
+        pb.set_message(format!("⏳ Proof {} is {} ({} s elapsed)...", id, last_status, elapsed));
+        tokio::time::sleep(Duration::from_secs(wait.poll_interval)).await;
+    }
+}
+
+// Everything `send` would do short of the network: check the signature the way the server
+// would, then show the request
+fn print_dry_run(ctx: &CliContext, request: &ProofRequest, signature: &[u8], public_key: &[u8]) -> Result<()> {
+    let url = format!("{}/api/proof", ctx.endpoint);
+    reqwest::Url::parse(&url).map_err(|e| anyhow::anyhow!("Invalid endpoint {}: {}", ctx.endpoint, e))?;
+    if !soundness_layer::verify_signature(public_key, signature, request.canonical_string().as_bytes())? {
+        anyhow::bail!("Signature does not verify against the stored public key, the key store may be corrupted");
+    }
+
+    println!("\n🔍 Dry run: nothing was sent");
+    outputln!("\nPOST {}", url);
+    match request {
+        ProofRequest::Json(_) => outputln!("Content-Type: application/json"),
+        ProofRequest::Multipart(_) => outputln!("Content-Type: multipart/form-data"),
+    }
+    outputln!("X-Signature: {}", BASE64.encode(signature));
+    outputln!("X-Public-Key: {}", BASE64.encode(public_key));
+
+    match request {
+        ProofRequest::Json(body) => {
+            // The file contents can be huge, so only show how long they are
+            let mut body = body.clone();
+            for field in [PROOF_FIELD, ELF_FIELD] {
+                let length = body[field].as_str().map(str::len).unwrap_or_default();
+                body[field] = serde_json::json!(format!("<{} base64 characters>", length));
+            }
+            outputln!("\n{}", serde_json::to_string_pretty(&body)?);
+        }
+        ProofRequest::Multipart(multipart) => {
+            outputln!("\nproof_filename: {}", multipart.proof_filename);
+            outputln!("elf_filename: {}", multipart.elf_filename);
+            outputln!("proving_system: {}", multipart.proving_system.name());
+            outputln!("canonical_string: {:?}", multipart.canonical_string);
+            for (field, path, file_name) in [
+                (PROOF_FIELD, &multipart.proof_file, &multipart.proof_filename),
+                (ELF_FIELD, &multipart.elf_file, &multipart.elf_filename),
+            ] {
+                let length = fs::metadata(path)
+                    .with_context(|| format!("Failed to read file: {}", path.display()))?
+                    .len();
+                outputln!("{}: <{} bytes streamed from {} as {}>", field, length, path.display(), file_name);
+            }
+        }
+    }
+
+    println!("\n✅ Signature verified locally with the public key");
     Ok(())
 }
 
+// Submit the same signed request twice and expect the server to reject the replay
+async fn replay_attack_demo(
+    ctx: &CliContext,
+    client: &reqwest::Client,
+    files: &ProofFiles,
+    key_name: &str,
+) -> Result<()> {
+    // Both submissions are real, and the endpoint may come from a config file or profile
+    ctx.confirm(&format!("This sends two real signed submissions to {}. Continue?", ctx.endpoint))?;
+    let request = build_proof_request(ctx, files, false)?;
+
+    let canonical_string = request.canonical_string();
+    let signed = SignedRequest {
+        signature: sign_payload(ctx, canonical_string.as_bytes(), key_name)?,
+        public_key: get_public_key(ctx, key_name)?,
+        request,
+        approvals: None,
+    };
+
+    let first_pb = create_progress_bar("🚀 Sending original submission...");
+    let first = post_proof(ctx, client, &signed).await?;
+    first_pb.finish_with_message(format!("🚀 Original submission returned {}", first.status()));
+
+    // A rejected original would make a rejected replay meaningless
+    if !first.status().is_success() {
+        let error_text = first.text().await?;
+        anyhow::bail!("Original submission was not accepted, cannot test replay protection: {}", error_text);
+    }
+
+    let replay_pb = create_progress_bar("🔁 Replaying the same signed submission...");
+    let replay = post_proof(ctx, client, &signed).await?;
+    let replay_status = replay.status();
+    replay_pb.finish_with_message(format!("🔁 Replayed submission returned {}", replay_status));
+
+    if replay_status.is_client_error() {
+        println!("\n✅ Replay rejected by {} with status {}", ctx.endpoint, replay_status);
+        Ok(())
+    } else {
+        let response_text = replay.text().await?;
+        anyhow::bail!(
+            "Replay was not rejected (status {}), server response: {}",
+            replay_status,
+            response_text
+        )
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
-    let client = reqwest::Client::new();
+    let matches = Config::load()?.apply(Args::command()).get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let quiet = args.quiet.unwrap_or_else(|| !std::io::stdout().is_terminal());
+    QUIET_OUTPUT.store(quiet, Ordering::Relaxed);
+    if !args.json {
+        return run(args).await;
+    }
+
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+    let mut command = Vec::new();
+    let mut subcommand = matches.subcommand();
+    while let Some((name, matches)) = subcommand {
+        command.push(name);
+        subcommand = matches.subcommand();
+    }
+    let result = run(args).await;
+    let mut output = std::mem::take(&mut *JSON_RESULT.lock().unwrap());
+    output.insert("ok".to_string(), result.is_ok().into());
+    output.insert("command".to_string(), command.join(" ").into());
+    if let Err(e) = &result {
+        output.insert("error".to_string(), format!("{:#}", e).into());
+    }
+    std::println!("{}", serde_json::Value::Object(output));
+    if result.is_err() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> Result<()> {
+    #[cfg(not(feature = "trace-crypto"))]
+    if args.trace_crypto {
+        anyhow::bail!("--trace-crypto is not available in this build. Rebuild with `--features trace-crypto`.");
+    }
+    #[cfg(feature = "trace-crypto")]
+    if args.trace_crypto {
+        soundness_layer::crypto::set_trace_crypto(true);
+        eprintln!("[CRYPTO TRACE] ⚠️  WARNING: crypto tracing is enabled! Secret keys, derived keys and plaintexts will be printed.");
+        eprintln!("[CRYPTO TRACE] ⚠️  Never use this mode with keys that protect anything of value.");
+    }
+    let mut client_builder = reqwest::ClientBuilder::new()
+        .timeout(Duration::from_secs(args.timeout))
+        .connect_timeout(Duration::from_secs(args.connect_timeout));
+    if let Commands::Send {
+        files,
+        notarization_ticket,
+        tee_attestation,
+        client_cert,
+        client_key,
+        ..
+    } = &args.command
+    {
+        if let Some(identity) = load_client_identity(client_cert.as_deref(), client_key.as_deref())? {
+            client_builder = client_builder.identity(identity);
+        }
+        let mut headers = reqwest::header::HeaderMap::new();
+        // Both conflict with --batch-file, so there is a single proof file to bind them to
+        if let (Some(ticket), Some(proof_file)) = (notarization_ticket, &files.proof_file) {
+            headers.insert("X-Notarization-Ticket", notarization_ticket_header(ticket, proof_file)?);
+        }
+        if let (Some(attestation), Some(proof_file)) = (tee_attestation, &files.proof_file) {
+            headers.insert("X-TEE-Attestation", tee_attestation_header(attestation, proof_file)?);
+        }
+        client_builder = client_builder.default_headers(headers);
+    }
+    let client = client_builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to build HTTP client: {}", e))?;
+    let ephemeral_store = if args.ephemeral {
+        eprintln!("⚠️  Ephemeral mode: the key store lives in memory and nothing will be persisted");
+        let key_store = match &args.key_store_env {
+            Some(var) => {
+                let contents = std::env::var(var)
+                    .with_context(|| format!("Failed to read key store from environment variable {}", var))?;
+                serde_json::from_str(&contents)
+                    .with_context(|| format!("Invalid key store JSON in environment variable {}", var))?
+            }
+            None => KeyStore::default(),
+        };
+        Some(Mutex::new(key_store))
+    } else {
+        None
+    };
+    // Fall back to an annotated `key_store.json5` or a converted `key_store.ndjson` when the
+    // default JSON store doesn't exist
+    let mut key_store_path = match &args.profile {
+        Some(profile) => profile_key_store_path(profile)?,
+        None => args.key_store,
+    };
+    if !args.ephemeral && key_store_path == Path::new("key_store.json") && !key_store_path.exists() {
+        if let Some(fallback) = ["key_store.json5", "key_store.ndjson"]
+            .into_iter()
+            .map(Path::new)
+            .find(|path| path.exists())
+        {
+            key_store_path = fallback.to_path_buf();
+        }
+    }
+    let key_store_format = args
+        .key_store_format
+        .unwrap_or_else(|| KeyStoreFormat::from_path(&key_store_path));
+
+    let ctx = CliContext {
+        endpoint: args.endpoint,
+        canonical_template: args.canonical_template,
+        encoding: args.encoding,
+        password_source: match (args.password_env, args.password_file, args.password_source) {
+            (Some(var), _, _) => SecretSource::Env(var),
+            (_, Some(path), _) => SecretSource::File(path),
+            (_, _, Some(source)) => source,
+            // Scripts can export the password instead of passing an option to every command
+            _ if std::env::var_os(PASSWORD_ENV_VAR).is_some() => SecretSource::Env(PASSWORD_ENV_VAR.to_string()),
+            _ => SecretSource::Prompt,
+        },
+        mnemonic_source: args.mnemonic_source,
+        strict_base64: args.strict_base64,
+        fail_on_empty_password: args.fail_on_empty_password,
+        assume_yes: args.yes,
+        strict_password: args.strict_password,
+        min_password_bits: args.min_password_bits,
+        timeout: Duration::from_secs(args.timeout),
+        key_store_path,
+        key_store_format,
+        max_key_store_size: (!args.ignore_size_limit).then_some(args.max_key_store_size),
+        encrypt_store: args.encrypt_store,
+        store_password_source: args.store_password_source,
+        store_password: Mutex::new(None),
+        ephemeral_store,
+    };
 
     match args.command {
-        Commands::GenerateKey { name } => {
-            generate_key_pair(&name)?;
+        Commands::GenerateKey {
+            name,
+            kdf,
+            hd_standard,
+            derivation_path,
+            mnemonic_passphrase,
+            show_mnemonic,
+        } => {
+            let hd = hd_standard
+                .map(|standard| HdDerivation::new(standard, derivation_path, mnemonic_passphrase))
+                .transpose()?;
+            generate_key_pair(&ctx, &name, kdf.map(Kdf::params).unwrap_or_default(), hd, show_mnemonic)?;
         }
         Commands::ListKeys => {
-            list_keys()?;
+            list_keys(&ctx)?;
+        }
+        Commands::ListProfiles => {
+            list_profiles(args.profile.as_deref())?;
+        }
+        Commands::KeyInfo { name } => {
+            key_info(&ctx, &name)?;
+        }
+        Commands::ExportPublicKey { name, format, output } => {
+            export_public_key(&ctx, &name, format, output.as_deref())?;
+        }
+        Commands::HealKeyStore { remove_corrupt, dry_run } => {
+            heal_key_store(&ctx, remove_corrupt, dry_run)?;
+        }
+        Commands::CompactKeyStore => {
+            compact_key_store(&ctx)?;
+        }
+        Commands::ConvertKeyStore { to, output } => {
+            convert_key_store(&ctx, to, output.as_deref())?;
+        }
+        Commands::Backup {
+            since,
+            output,
+            backup_password_source,
+            ..
+        } => {
+            backup_key_store(&ctx, since, output.as_deref(), &backup_password_source)?;
+        }
+        Commands::Restore {
+            input,
+            backup_password_source,
+            force,
+            merge,
+            overwrite,
+        } => {
+            restore_key_store(&ctx, &input, &backup_password_source, force, merge, overwrite)?;
         }
-        Commands::ExportKey { name } => {
-            export_key(&name)?;
+        Commands::SplitKeyStore { count, output_dir } => {
+            split_key_store(&ctx, count, &output_dir)?;
         }
-        Commands::ImportKey { name } => {
-            import_key(&name)?;
+        Commands::MergeKeyStores { input_dir, overwrite } => {
+            merge_key_stores(&ctx, &input_dir, overwrite)?;
         }
-        Commands::BatchGen { count } => {
-            batch_gen_keys(count)?;
+        Commands::ValidateKeyStore => {
+            exit_unless(validate_key_store(&ctx)?, "The key store has problems")?;
+        }
+        Commands::Check { verbose, decrypt } => {
+            exit_unless(check_key_store(&ctx, verbose, decrypt)?, "The key store has problems")?;
+        }
+        Commands::ExportPublicKeys { output } => {
+            export_public_keys(&ctx, &output)?;
+        }
+        Commands::ExportKey { name, qr_file } => {
+            export_key(&ctx, &name, qr_file.as_deref())?;
+        }
+        Commands::ExportPem { name, output } => {
+            export_pem(&ctx, &name, output.as_deref())?;
+        }
+        Commands::BatchExport { output_file, filter } => {
+            batch_export(&ctx, &output_file, filter.as_deref())?;
+        }
+        Commands::ImportKey {
+            name,
+            kdf,
+            hd_standard,
+            derivation_path,
+            mnemonic_passphrase,
+            format,
+        } => {
+            let hd = hd_standard
+                .map(|standard| HdDerivation::new(standard, derivation_path, mnemonic_passphrase))
+                .transpose()?;
+            import_key(&ctx, &name, kdf.map(Kdf::params).unwrap_or_default(), hd, format)?;
+        }
+        Commands::ImportRaw {
+            name,
+            key_file,
+            hex,
+            kdf,
+        } => {
+            import_raw(&ctx, &name, &key_file, hex, kdf.map(Kdf::params).unwrap_or_default())?;
+        }
+        Commands::ChangePassword {
+            name,
+            new_password_source,
+        } => {
+            change_password(&ctx, &name, &new_password_source)?;
+        }
+        Commands::RenameKey { old_name, new_name } => {
+            rename_key(&ctx, &old_name, &new_name)?;
+        }
+        Commands::SetAcl { name, allow, clear } => {
+            set_acl(&ctx, &name, (!clear).then_some(allow))?;
+        }
+        Commands::BatchGen {
+            count,
+            prefix,
+            start,
+            output,
+            append,
+            no_append: _,
+            report_file,
+            parallel,
+            ndjson,
+            skip_file_output,
+            output_dir,
+        } => {
+            batch_gen_keys(
+                &ctx,
+                &BatchGenOptions {
+                    count,
+                    prefix,
+                    start,
+                    output,
+                    append,
+                    report_file,
+                    stream: false,
+                    parallel,
+                    ndjson,
+                    skip_file_output,
+                    output_dir,
+                },
+            )?;
+        }
+        Commands::KeygenBatchStream {
+            count,
+            prefix,
+            start,
+            parallel,
+        } => {
+            batch_gen_keys(
+                &ctx,
+                &BatchGenOptions {
+                    count,
+                    prefix,
+                    start,
+                    output: PathBuf::from("-"),
+                    append: false,
+                    report_file: None,
+                    stream: true,
+                    parallel,
+                    ndjson: false,
+                    skip_file_output: false,
+                    output_dir: None,
+                },
+            )?;
+        }
+        Commands::CanonicalString { files } => {
+            print_canonical_string(&ctx, &files.with_detected_proving_system()?)?;
+        }
+        Commands::Sign {
+            key_name,
+            message_file,
+            output,
+        } => {
+            sign_message(&ctx, &key_name, &message_file, output.as_deref())?;
+        }
+        Commands::SignFile {
+            input,
+            output,
+            key_name,
+        } => {
+            write_file_signature(&ctx, &key_name, &input, output.as_deref())?;
+        }
+        Commands::VerifyFile { input, sig, public_key } => {
+            exit_unless(
+                verify_file_signature(&ctx, &input, sig.as_deref(), &public_key)?,
+                "Signature invalid",
+            )?;
+        }
+        Commands::SignCommit {
+            key_name,
+            operation,
+            namespace,
+            key_file,
+            file,
+            ..
+        } => {
+            if let Some(operation) = operation.filter(|operation| operation != "sign") {
+                anyhow::bail!("Only `-Y sign` is supported, use ssh-keygen for `-Y {}`", operation);
+            }
+            sign_commit(&ctx, key_name.as_deref(), key_file.as_deref(), &namespace, file.as_deref())?;
+        }
+        Commands::VerifyCommit {
+            commit_hash,
+            public_key,
+        } => {
+            exit_unless(verify_commit(&ctx, &commit_hash, public_key.as_deref())?, "Bad commit signature")?;
+        }
+        Commands::Verify {
+            public_key,
+            signature,
+            message_file,
+        } => {
+            exit_unless(verify_signature(&ctx, &public_key, &signature, &message_file)?, "Signature invalid")?;
         }
         Commands::Send {
+            files,
+            key_name,
+            retry,
+            wait,
+            sigstore,
+            dry_run,
+            fail_fast,
+            verify_locally,
+            require_key_in_server,
+            skip_key_check,
+            ..
+        } => {
+            // A dry run never reaches the server, so there is nothing to check against
+            if require_key_in_server && !skip_key_check && !dry_run {
+                check_key_in_server(&ctx, &client, &key_name).await?;
+            }
+            let options = SendOptions {
+                key_name: &key_name,
+                retry: &retry,
+                wait: &wait,
+                sigstore: &sigstore,
+                dry_run,
+                verify_locally,
+            };
+            match files.single() {
+                Some(files) => {
+                    let files = files.with_detected_proving_system()?;
+                    submit_proof(&ctx, &client, &files, &options).await?;
+                }
+                None => {
+                    let batch = files.read_batch()?;
+                    send_batch(&ctx, &client, &batch, &options, fail_fast).await?;
+                }
+            }
+        }
+        Commands::ThresholdSend {
+            files,
+            approvers,
+            threshold,
+            retry,
+            wait,
+        } => {
+            let files = files.with_detected_proving_system()?;
+            threshold_send(&ctx, &client, &files, &approvers, threshold, &retry, &wait).await?;
+        }
+        Commands::Notarize {
             proof_file,
             elf_file,
             key_name,
-            proving_system,
+            rekor_url,
+            output,
         } => {
-            // Create progress bars
-            let reading_pb = create_progress_bar("📂 Reading files...");
-
-            // Read the files as binary data
-            let proof_content = fs::read(&proof_file)
-                .with_context(|| format!("Failed to read proof file: {}", proof_file.display()))?;
-
-            let elf_content = fs::read(&elf_file)
-                .with_context(|| format!("Failed to read ELF file: {}", elf_file.display()))?;
-
-            reading_pb.finish_with_message("📂 Files read successfully");
-
-            // Create the request body with canonical string
-            let request_body = serde_json::json!({
-                "proof": BASE64.encode(&proof_content),
-                "elf": BASE64.encode(&elf_content),
-                "proof_filename": proof_file.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown"),
-                "elf_filename": elf_file.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown"),
-                "proving_system": format!("{:?}", proving_system).to_lowercase(),
-                "canonical_string": format!(
-                    "proof:{}\nelf:{}\nproof_filename:{}\nelf_filename:{}\nproving_system:{}",
-                    BASE64.encode(&proof_content),
-                    BASE64.encode(&elf_content),
-                    proof_file.file_name().and_then(|n| n.to_str()).unwrap_or("unknown"),
-                    elf_file.file_name().and_then(|n| n.to_str()).unwrap_or("unknown"),
-                    format!("{:?}", proving_system).to_lowercase()
-                )
-            });
-
-            // Sign the canonical string
-            let canonical_string = request_body["canonical_string"].as_str().unwrap();
-            let signature = sign_payload(canonical_string.as_bytes(), &key_name)?;
-            let public_key = get_public_key(&key_name)?;
-
-            // Send the request
-            let sending_pb = create_progress_bar("🚀 Sending to server...");
-            let response = client
-                .post(format!("{}/api/proof", args.endpoint))
-                .header("Content-Type", "application/json")
-                .header("X-Signature", BASE64.encode(&signature))
-                .header("X-Public-Key", BASE64.encode(&public_key))
-                .json(&request_body)
-                .send()
-                .await
-                .with_context(|| format!("Failed to send request to {}", args.endpoint))?;
-
-            sending_pb.finish_with_message("🚀 Request sent successfully");
-
-            // Check if the request was successful
-            if response.status().is_success() {
-                println!("\n✅ Successfully sent files to {}", args.endpoint);
-                let response_text = response.text().await?;
-                println!("Server response: {}", response_text);
-            } else {
-                println!("\n❌ Error: Server returned status {}", response.status());
-                let error_text = response.text().await?;
-                println!("Error details: {}", error_text);
+            notarize(&ctx, &client, &proof_file, &elf_file, &key_name, &rekor_url, output.as_deref()).await?;
+        }
+        Commands::Attest {
+            proof_file,
+            tee,
+            output,
+        } => {
+            attest(&proof_file, tee, output.as_deref())?;
+        }
+        Commands::RotateKey {
+            old_name,
+            new_name,
+            kdf,
+            announce,
+            resubmit,
+            retry,
+        } => {
+            let kdf = kdf.map(Kdf::params).unwrap_or_default();
+            rotate_key(&ctx, &client, &old_name, &new_name, kdf, announce).await?;
+            if resubmit {
+                resubmit_pending(&ctx, &client, &old_name, &new_name, &retry).await?;
+            }
+        }
+        Commands::Pin {
+            ipfs_cid,
+            ipns_key,
+            ipfs_api,
+            lifetime,
+        } => {
+            pin_to_ipns(&ctx, &client, &ipfs_cid, &ipns_key, &ipfs_api, lifetime).await?;
+        }
+        Commands::Search { circuit_hash, registry } => {
+            search_registry(&client, &registry, &circuit_hash).await?;
+        }
+        Commands::Registry {
+            command: RegistryCommands::Push(push),
+        } => {
+            registry_push(&ctx, &client, &push).await?;
+        }
+        Commands::Registry {
+            command: RegistryCommands::Pull(pull),
+        } => {
+            registry_pull(&client, &pull).await?;
+        }
+        Commands::ListSubmissions { filter_key, since } => {
+            list_submissions(&ctx, filter_key.as_deref(), since.as_deref())?;
+        }
+        Commands::SyncPublicKeys { registry, path } => {
+            sync_public_keys(&ctx, &client, registry.as_deref(), &path).await?;
+        }
+        Commands::Config {
+            command: ConfigCommands::Init { local, force },
+        } => {
+            init_config(local, force)?;
+        }
+        Commands::Completions { shell } => {
+            if json_output() {
+                anyhow::bail!("Completion scripts are written to stdout, drop --json");
             }
+            let mut command = Args::command();
+            let bin_name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, bin_name, &mut std::io::stdout());
+        }
+        Commands::ReplayAttackDemo { files, key_name } => {
+            replay_attack_demo(&ctx, &client, &files, &key_name).await?;
         }
     }
 
@@ -0,0 +1,100 @@
+//! Where passwords, mnemonics and other secrets are read from.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A place to read a secret from, parsed from `prompt`, `stdin`, `env:VAR`, `file:PATH` or `fd:N`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SecretSource {
+    /// Ask on the terminal without echoing the input
+    #[default]
+    Prompt,
+    /// Read one line from standard input
+    Stdin,
+    /// Read an environment variable
+    Env(String),
+    /// Read a whole file
+    File(PathBuf),
+    /// Read an inherited file descriptor to EOF (Unix only)
+    Fd(i32),
+}
+
+impl FromStr for SecretSource {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        match source {
+            "prompt" => return Ok(SecretSource::Prompt),
+            "stdin" => return Ok(SecretSource::Stdin),
+            _ => {}
+        }
+        match source.split_once(':') {
+            Some(("env", var)) if !var.is_empty() => Ok(SecretSource::Env(var.to_string())),
+            Some(("file", path)) if !path.is_empty() => Ok(SecretSource::File(PathBuf::from(path))),
+            Some(("fd", fd)) => fd
+                .parse()
+                .map(SecretSource::Fd)
+                .map_err(|_| anyhow::anyhow!("Invalid file descriptor '{}'", fd)),
+            _ => anyhow::bail!(
+                "Unknown secret source '{}': expected prompt, stdin, env:VAR, file:PATH or fd:N",
+                source
+            ),
+        }
+    }
+}
+
+impl SecretSource {
+    /// Read the secret. `prompt` is only shown for [`SecretSource::Prompt`].
+    ///
+    /// One trailing newline is stripped, so files written with `echo` work as expected.
+    pub fn read(&self, prompt: &str) -> Result<String> {
+        let secret = match self {
+            SecretSource::Prompt => return Ok(rpassword::prompt_password(prompt)?),
+            SecretSource::Stdin => {
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line)?;
+                line
+            }
+            SecretSource::Env(var) => std::env::var(var)
+                .with_context(|| format!("Environment variable {} is not set", var))?,
+            SecretSource::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read secret file: {}", path.display()))?,
+            SecretSource::Fd(fd) => read_fd(*fd)?,
+        };
+
+        let secret = secret.strip_suffix('\n').unwrap_or(&secret);
+        Ok(secret.strip_suffix('\r').unwrap_or(secret).to_string())
+    }
+
+    pub fn is_prompt(&self) -> bool {
+        *self == SecretSource::Prompt
+    }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String> {
+    use std::io::Read;
+    use std::os::fd::BorrowedFd;
+
+    if fd <= 2 {
+        anyhow::bail!("File descriptor {} is a standard stream, use `stdin` instead", fd);
+    }
+    // SAFETY: the descriptor is only borrowed long enough to duplicate it; dup fails
+    // cleanly if it isn't open, and the caller keeps ownership of the original
+    let owned = unsafe { BorrowedFd::borrow_raw(fd) }
+        .try_clone_to_owned()
+        .with_context(|| format!("File descriptor {} is not open", fd))?;
+
+    let mut secret = String::new();
+    fs::File::from(owned)
+        .read_to_string(&mut secret)
+        .with_context(|| format!("Failed to read file descriptor {}", fd))?;
+    Ok(secret)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<String> {
+    anyhow::bail!("Reading secrets from a file descriptor is only supported on Unix")
+}
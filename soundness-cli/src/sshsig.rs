@@ -0,0 +1,184 @@
+//! OpenSSH signatures (the SSHSIG format of `ssh-keygen -Y sign`), which Git uses for
+//! commits when `gpg.format` is `ssh`.
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256, Sha512};
+
+const MAGIC_PREAMBLE: &[u8] = b"SSHSIG";
+const SIG_VERSION: u32 = 1;
+const KEY_TYPE: &str = "ssh-ed25519";
+const HASH_ALGORITHM: &str = "sha512";
+const BEGIN_ARMOR: &str = "-----BEGIN SSH SIGNATURE-----";
+const END_ARMOR: &str = "-----END SSH SIGNATURE-----";
+
+fn put_string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+// The public key as it appears on the wire: key type, then the raw key
+fn public_key_blob(public_key: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    put_string(&mut blob, KEY_TYPE.as_bytes());
+    put_string(&mut blob, public_key);
+    blob
+}
+
+/// The bytes to sign with ed25519 for `message` in `namespace` (`git` for commits)
+pub fn signed_data(namespace: &str, message: &[u8]) -> Vec<u8> {
+    signed_data_with(namespace, HASH_ALGORITHM, &Sha512::digest(message))
+}
+
+fn signed_data_with(namespace: &str, hash_algorithm: &str, digest: &[u8]) -> Vec<u8> {
+    let mut data = MAGIC_PREAMBLE.to_vec();
+    put_string(&mut data, namespace.as_bytes());
+    put_string(&mut data, b"");
+    put_string(&mut data, hash_algorithm.as_bytes());
+    put_string(&mut data, digest);
+    data
+}
+
+/// Wrap an ed25519 `signature` over [`signed_data`] in an armored SSH signature
+pub fn armor(public_key: &[u8], namespace: &str, signature: &[u8]) -> String {
+    let mut blob = MAGIC_PREAMBLE.to_vec();
+    blob.extend_from_slice(&SIG_VERSION.to_be_bytes());
+    put_string(&mut blob, &public_key_blob(public_key));
+    put_string(&mut blob, namespace.as_bytes());
+    put_string(&mut blob, b"");
+    put_string(&mut blob, HASH_ALGORITHM.as_bytes());
+    let mut signature_blob = Vec::new();
+    put_string(&mut signature_blob, KEY_TYPE.as_bytes());
+    put_string(&mut signature_blob, signature);
+    put_string(&mut blob, &signature_blob);
+
+    // ssh-keygen wraps at 70 columns
+    let encoded = BASE64.encode(&blob);
+    let mut armored = format!("{}\n", BEGIN_ARMOR);
+    for line in encoded.as_bytes().chunks(70) {
+        armored.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        armored.push('\n');
+    }
+    armored.push_str(END_ARMOR);
+    armored.push('\n');
+    armored
+}
+
+/// A parsed armored SSH signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshSignature {
+    /// The raw ed25519 key the signature claims to be made with
+    pub public_key: Vec<u8>,
+    pub namespace: String,
+    pub hash_algorithm: String,
+    pub signature: Vec<u8>,
+}
+
+// Reads SSH wire format strings off the front of a buffer
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            anyhow::bail!("Invalid SSH signature: truncated");
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn uint32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let len = self.uint32()? as usize;
+        self.take(len)
+    }
+
+    // A (key type, data) pair, as used for both the public key and the signature
+    fn typed(&mut self, what: &str) -> Result<&'a [u8]> {
+        let mut inner = Reader(self.string()?);
+        let key_type = inner.string()?;
+        if key_type != KEY_TYPE.as_bytes() {
+            anyhow::bail!(
+                "Unsupported {} type {}, only {} is supported",
+                what,
+                String::from_utf8_lossy(key_type),
+                KEY_TYPE
+            );
+        }
+        inner.string()
+    }
+}
+
+impl SshSignature {
+    /// Parse a `-----BEGIN SSH SIGNATURE-----` block
+    pub fn parse(armored: &str) -> Result<Self> {
+        let armored = armored.trim();
+        let body = armored
+            .strip_prefix(BEGIN_ARMOR)
+            .and_then(|rest| rest.strip_suffix(END_ARMOR))
+            .ok_or_else(|| anyhow::anyhow!("Not an armored SSH signature"))?;
+        let encoded: String = body.split_whitespace().collect();
+        let blob = BASE64
+            .decode(encoded)
+            .map_err(|e| anyhow::anyhow!("Invalid SSH signature: {}", e))?;
+
+        let mut reader = Reader(&blob);
+        if reader.take(MAGIC_PREAMBLE.len())? != MAGIC_PREAMBLE {
+            anyhow::bail!("Invalid SSH signature: missing SSHSIG preamble");
+        }
+        let version = reader.uint32()?;
+        if version != SIG_VERSION {
+            anyhow::bail!("Unsupported SSH signature version {}", version);
+        }
+        let public_key = reader.typed("key")?.to_vec();
+        let namespace = String::from_utf8_lossy(reader.string()?).into_owned();
+        reader.string()?;
+        let hash_algorithm = String::from_utf8_lossy(reader.string()?).into_owned();
+        let signature = reader.typed("signature")?.to_vec();
+        Ok(SshSignature {
+            public_key,
+            namespace,
+            hash_algorithm,
+            signature,
+        })
+    }
+
+    /// Check the signature over `message` against the public key it carries. Callers
+    /// still have to decide whether they trust that key and namespace.
+    pub fn verify(&self, message: &[u8]) -> Result<bool> {
+        let digest = match self.hash_algorithm.as_str() {
+            "sha512" => Sha512::digest(message).to_vec(),
+            "sha256" => Sha256::digest(message).to_vec(),
+            other => anyhow::bail!("Unsupported SSH signature hash algorithm {}", other),
+        };
+        let data = signed_data_with(&self.namespace, &self.hash_algorithm, &digest);
+        crate::crypto::verify_signature(&self.public_key, &self.signature, &data)
+    }
+}
+
+/// Format a public key as an OpenSSH `ssh-ed25519 AAAA...` line
+pub fn openssh_public_key(public_key: &[u8]) -> String {
+    format!("{} {}", KEY_TYPE, BASE64.encode(public_key_blob(public_key)))
+}
+
+/// Parse an OpenSSH public key line (`ssh-ed25519 AAAA... comment`) into the raw ed25519 key
+pub fn parse_openssh_public_key(line: &str) -> Result<Vec<u8>> {
+    let mut fields = line.split_whitespace();
+    let (Some(key_type), Some(encoded)) = (fields.next(), fields.next()) else {
+        anyhow::bail!("Expected an OpenSSH public key like `ssh-ed25519 AAAA...`");
+    };
+    if key_type != KEY_TYPE {
+        anyhow::bail!("Unsupported SSH key type {}, only {} keys can be used", key_type, KEY_TYPE);
+    }
+    let blob = BASE64
+        .decode(encoded)
+        .map_err(|e| anyhow::anyhow!("Invalid OpenSSH public key: {}", e))?;
+    let expected_type = public_key_blob(&[]);
+    match blob.strip_prefix(&expected_type[..expected_type.len() - 4]) {
+        Some([0, 0, 0, 32, key @ ..]) if key.len() == 32 => Ok(key.to_vec()),
+        _ => anyhow::bail!("Invalid OpenSSH public key: malformed {} key blob", KEY_TYPE),
+    }
+}
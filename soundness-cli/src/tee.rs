@@ -0,0 +1,117 @@
+//! TEE attestation quotes bound to a proof. The proof's SHA-256 goes into the 64 bytes of
+//! report data that the TEE signs, so a verifier of the quote knows the proof was produced
+//! inside that enclave or confidential VM.
+//!
+//! Quotes come from the interfaces the platform exposes as files, so no vendor SDK is needed:
+//! the kernel's configfs-tsm reports for TDX and SEV-SNP guests, and Gramine's
+//! `/dev/attestation` for SGX enclaves.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const REPORT_DATA_LEN: usize = 64;
+
+const TSM_REPORT_DIR: &str = "sys/kernel/config/tsm/report";
+const GRAMINE_ATTESTATION_DIR: &str = "dev/attestation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TeePlatform {
+    /// Intel SGX enclave running under Gramine (DCAP quote)
+    Sgx,
+    /// Intel TDX guest
+    Tdx,
+    /// AMD SEV-SNP guest (the signed attestation report)
+    Sev,
+}
+
+impl TeePlatform {
+    pub fn name(self) -> &'static str {
+        match self {
+            TeePlatform::Sgx => "sgx",
+            TeePlatform::Tdx => "tdx",
+            TeePlatform::Sev => "sev",
+        }
+    }
+
+    // What configfs-tsm reports in `provider` for the guest driver of this platform
+    fn tsm_provider(self) -> Option<&'static str> {
+        match self {
+            TeePlatform::Sgx => None,
+            TeePlatform::Tdx => Some("tdx_guest"),
+            TeePlatform::Sev => Some("sev_guest"),
+        }
+    }
+}
+
+/// Report data binding a proof: its SHA-256 followed by 32 zero bytes
+pub fn report_data(proof_sha256: &[u8; 32]) -> [u8; REPORT_DATA_LEN] {
+    let mut report_data = [0u8; REPORT_DATA_LEN];
+    report_data[..32].copy_from_slice(proof_sha256);
+    report_data
+}
+
+/// Ask the TEE this process runs in for a quote over `report_data`
+pub fn generate_quote(platform: TeePlatform, report_data: &[u8; REPORT_DATA_LEN]) -> Result<Vec<u8>> {
+    generate_quote_under(Path::new("/"), platform, report_data)
+}
+
+/// [`generate_quote`] with the platform files looked up under `root` instead of `/`
+pub fn generate_quote_under(root: &Path, platform: TeePlatform, report_data: &[u8; REPORT_DATA_LEN]) -> Result<Vec<u8>> {
+    match platform.tsm_provider() {
+        Some(provider) => tsm_quote(&root.join(TSM_REPORT_DIR), provider, report_data),
+        None => gramine_quote(&root.join(GRAMINE_ATTESTATION_DIR), report_data),
+    }
+}
+
+// configfs-tsm: each directory made under report/ is one request. The kernel fills outblob when
+// it is read after inblob was written, and bumps `generation` on every write, so a generation
+// other than 1 means another process wrote to the same request
+fn tsm_quote(reports: &Path, provider: &str, report_data: &[u8; REPORT_DATA_LEN]) -> Result<Vec<u8>> {
+    if !reports.is_dir() {
+        anyhow::bail!(
+            "{} not found: this is not a TDX or SEV-SNP guest, or the kernel lacks configfs-tsm",
+            reports.display()
+        );
+    }
+    let request = reports.join(format!("soundness-{}", std::process::id()));
+    fs::create_dir(&request).with_context(|| format!("Failed to create TSM report: {}", request.display()))?;
+    let quote = (|| {
+        let actual = fs::read_to_string(request.join("provider"))
+            .with_context(|| format!("Failed to read TSM provider: {}", request.display()))?;
+        if actual.trim() != provider {
+            anyhow::bail!("This machine's TEE is {}, not {}", actual.trim(), provider);
+        }
+        fs::write(request.join("inblob"), report_data).context("Failed to write TSM report data")?;
+        let quote = fs::read(request.join("outblob")).context("The TEE did not produce a quote")?;
+        let generation = fs::read_to_string(request.join("generation")).context("Failed to read TSM generation")?;
+        if generation.trim() != "1" {
+            anyhow::bail!("The TSM report was written to concurrently, try again");
+        }
+        Ok(quote)
+    })();
+    // configfs removes the request's files along with the directory
+    let _ = fs::remove_dir(&request);
+    quote
+}
+
+// Gramine: writing user_report_data and then reading quote returns a quote over that data
+fn gramine_quote(attestation: &Path, report_data: &[u8; REPORT_DATA_LEN]) -> Result<Vec<u8>> {
+    let attestation_type = fs::read_to_string(attestation.join("attestation_type")).with_context(|| {
+        format!(
+            "{} not found: SGX quotes need the CLI to run in a Gramine enclave",
+            attestation.display()
+        )
+    })?;
+    if attestation_type.trim() == "none" {
+        anyhow::bail!("The Gramine manifest has remote attestation disabled (sgx.remote_attestation = \"none\")");
+    }
+    fs::write(attestation.join("user_report_data"), report_data).context("Failed to write SGX report data")?;
+    let quote = fs::read(attestation.join("quote")).context("The enclave did not produce a quote")?;
+    if quote.is_empty() {
+        anyhow::bail!("The enclave returned an empty quote");
+    }
+    Ok(quote)
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+// The canonical string is what gets signed, so each template layout is pinned byte-for-byte.
+fn canonical_string(dir: &Path, extra_args: &[&str]) -> Result<String> {
+    fs::write(dir.join("fib.proof"), "test proof content")?;
+    fs::write(dir.join("fib.elf"), "test elf content")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_soundness-cli"))
+        .current_dir(dir)
+        .args(extra_args)
+        .args(["canonical-string", "--proof-file", "fib.proof", "--elf-file", "fib.elf"])
+        .args(["--proving-system", "risc0"])
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!("Command failed: {}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let (_, utf8) = stdout
+        .split_once("\nUTF-8:\n")
+        .ok_or_else(|| anyhow::anyhow!("Missing UTF-8 section: {}", stdout))?;
+    Ok(utf8.trim_end_matches('\n').to_string())
+}
+
+#[test]
+fn test_canonical_template_v1_is_default() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let expected = "proof:dGVzdCBwcm9vZiBjb250ZW50\n\
+                    elf:dGVzdCBlbGYgY29udGVudA==\n\
+                    proof_filename:fib.proof\n\
+                    elf_filename:fib.elf\n\
+                    proving_system:risc0";
+
+    assert_eq!(canonical_string(temp_dir.path(), &[])?, expected);
+    assert_eq!(
+        canonical_string(temp_dir.path(), &["--canonical-template", "v1"])?,
+        expected
+    );
+    Ok(())
+}
+
+#[test]
+fn test_canonical_template_v2() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let expected = "version:2\n\
+                    proof_sha256:e0938c7350236c47e65a2dbfacc8e2869e69c0241881de5657953fac4bf35f9d\n\
+                    elf_sha256:423b78473ad9e63f49945607cf27aa66ad5f81076a7ae62727d8f5191e3397b4\n\
+                    proof_filename:fib.proof\n\
+                    elf_filename:fib.elf\n\
+                    proving_system:risc0";
+
+    assert_eq!(
+        canonical_string(temp_dir.path(), &["--canonical-template", "v2"])?,
+        expected
+    );
+    Ok(())
+}
+
+#[test]
+fn test_file_name_overrides() -> Result<()> {
+    let temp_dir = tempdir()?;
+    fs::write(temp_dir.path().join("fib.proof"), "test proof content")?;
+    fs::write(temp_dir.path().join("fib.elf"), "test elf content")?;
+
+    let run = |names: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_soundness-cli"))
+            .current_dir(temp_dir.path())
+            .args(["canonical-string", "--proof-file", "fib.proof", "--elf-file", "fib.elf"])
+            .args(names)
+            .output()
+    };
+
+    let output = run(&["--proof-name", "fibonacci-v2.proof", "--elf-name", "fibonacci-v2.elf"])?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("\nproof_filename:fibonacci-v2.proof\nelf_filename:fibonacci-v2.elf\n"));
+
+    // A newline would let a name forge extra canonical string fields
+    let output = run(&["--proof-name", "a\nproving_system:sp1"])?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("\nproof_filename:unknown\n"));
+    assert!(String::from_utf8(output.stderr)?.contains("--proof-name must be a non-empty, single-line name"));
+
+    // `send --dry-run` previews the same way, while a real send refuses the name
+    let send = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_soundness-cli"))
+            .current_dir(temp_dir.path())
+            .env("SOUNDNESS_QUIET", "false")
+            .env("XDG_CONFIG_HOME", temp_dir.path())
+            .args(["send", "--proof-file", "fib.proof", "--elf-file", "fib.elf", "--key-name", "batch_key_0"])
+            .args(["--proving-system", "risc0", "--proof-name", ""])
+            .args(extra)
+            .output()
+    };
+    let generated = Command::new(env!("CARGO_BIN_EXE_soundness-cli"))
+        .current_dir(temp_dir.path())
+        .env("XDG_CONFIG_HOME", temp_dir.path())
+        .args(["batch-gen", "--count", "1", "--skip-file-output"])
+        .output()?;
+    assert!(generated.status.success());
+    let output = send(&["--dry-run"])?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout)?.contains("proof_filename:unknown"));
+    assert!(String::from_utf8(output.stderr)?.contains("Using \"unknown\" in this preview only"));
+    let output = send(&[])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("--proof-name must be a non-empty, single-line name"));
+    Ok(())
+}
+
+#[test]
+fn test_canonical_string_multipart_upload() -> Result<()> {
+    let temp_dir = tempdir()?;
+    fs::write(temp_dir.path().join("fib.proof"), "test proof content")?;
+    fs::write(temp_dir.path().join("fib.elf"), "test elf content")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_soundness-cli"))
+        .current_dir(temp_dir.path())
+        .args(["canonical-string", "--proof-file", "fib.proof", "--elf-file", "fib.elf"])
+        .args(["--proving-system", "risc0", "--upload", "multipart"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let expected = "version:multipart-1\n\
+                    proof_field:proof\n\
+                    proof_sha256:e0938c7350236c47e65a2dbfacc8e2869e69c0241881de5657953fac4bf35f9d\n\
+                    proof_filename:fib.proof\n\
+                    elf_field:elf\n\
+                    elf_sha256:423b78473ad9e63f49945607cf27aa66ad5f81076a7ae62727d8f5191e3397b4\n\
+                    elf_filename:fib.elf\n\
+                    proving_system:risc0";
+    assert!(stdout.contains(&format!("\nUTF-8:\n{}\n", expected)), "{}", stdout);
+    Ok(())
+}
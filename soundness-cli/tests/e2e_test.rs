@@ -19,6 +19,7 @@ fn run_cli_command(args: &[&str]) -> Result<String> {
 }
 
 #[test]
+#[ignore = "requires a running soundness-server, see tests/run_e2e_tests.sh"]
 fn test_signature_verification() -> Result<()> {
     // Create a temporary directory for test files
     let temp_dir = tempdir()?;
@@ -0,0 +1,418 @@
+use anyhow::Result;
+use ed25519_dalek::{Verifier, VerifyingKey};
+use soundness_layer::{
+    corrupt_backup_path, decrypt_secret_key, load_ndjson_key_pair, password_strength, encrypt_secret_key, load_key_store, load_key_store_as, save_key_store,
+    save_key_store_as, sign_payload, KdfParams, KeyEncoding, KeyPair, KeyStore, KeyStoreFormat,
+};
+use tempfile::tempdir;
+
+// Embedders should be able to build a signing pipeline from the library alone
+#[test]
+fn test_library_encrypt_store_and_sign() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("keys.json");
+
+    let secret_key = [7u8; 32];
+    let verifying_key = ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key();
+
+    let mut key_store = KeyStore::default();
+    key_store.keys.insert(
+        "embedded".to_string(),
+        KeyPair {
+            public_key: verifying_key.to_bytes().to_vec(),
+            public_key_string: String::new(),
+            encrypted_secret_key: Some(encrypt_secret_key(&secret_key, "pw", KdfParams::pbkdf2())?),
+            encrypted_mnemonic: None,
+            metadata: Default::default(),
+            has_bip39_passphrase: false,
+            registered_with: Vec::new(),
+        },
+    );
+    save_key_store(&key_store, &key_store_path)?;
+
+    let loaded = load_key_store(&key_store_path)?;
+    let encrypted = loaded.keys["embedded"].encrypted_secret_key.as_ref().unwrap();
+    assert!(decrypt_secret_key(encrypted, "wrong").is_err());
+
+    let signature = sign_payload(&decrypt_secret_key(encrypted, "pw")?, b"payload")?;
+    let public_key: [u8; 32] = loaded.keys["embedded"].public_key.clone().try_into().unwrap();
+    VerifyingKey::from_bytes(&public_key)?.verify(b"payload", &signature)?;
+    Ok(())
+}
+
+// A half-written store must survive the failed load, even if a later save replaces it
+#[test]
+fn test_corrupt_key_store_is_backed_up_on_load() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("key_store.json");
+    let truncated = r#"{"keys":{"half":{"public_key":[1,2,"#;
+    std::fs::write(&key_store_path, truncated)?;
+
+    let backup_path = corrupt_backup_path(&key_store_path);
+    assert_eq!(backup_path, temp_dir.path().join("key_store.json.corrupt"));
+    let error = load_key_store(&key_store_path).unwrap_err();
+    assert!(format!("{:#}", error).contains("key_store.json.corrupt"));
+    assert_eq!(std::fs::read_to_string(&backup_path)?, truncated);
+
+    save_key_store(&KeyStore::default(), &key_store_path)?;
+    assert_eq!(std::fs::read_to_string(&backup_path)?, truncated);
+    assert!(!temp_dir.path().join("key_store.json.tmp").exists());
+
+    // A second bad load keeps the first backup and writes its own next to it
+    std::fs::write(&key_store_path, "{not json")?;
+    let error = format!("{:#}", load_key_store(&key_store_path).unwrap_err());
+    assert_eq!(std::fs::read_to_string(&backup_path)?, truncated);
+    let second_backup = std::fs::read_dir(temp_dir.path())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.to_string_lossy().contains(".corrupt."))
+        .expect("second backup");
+    assert!(error.contains(&*second_backup.to_string_lossy()));
+    assert_eq!(std::fs::read_to_string(&second_backup)?, "{not json");
+    Ok(())
+}
+
+// A healthy store opened with the wrong format is reported as such, not backed up as corrupt
+#[test]
+fn test_key_store_in_another_format_is_not_backed_up() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("key_store.json");
+    save_key_store(&KeyStore::default(), &key_store_path)?;
+
+    let error = format!("{:#}", load_key_store_as(&key_store_path, KeyStoreFormat::Ndjson).unwrap_err());
+    assert!(error.contains("is not valid ndjson, but it is valid json. Pass --key-store-format json"));
+    assert!(!corrupt_backup_path(&key_store_path).exists());
+    Ok(())
+}
+
+#[test]
+fn test_password_strength_scores() {
+    assert_eq!(password_strength("").score, 0);
+    assert_eq!(password_strength("a").score, 0);
+    assert_eq!(password_strength("abc123").label(), "weak");
+    // Repeating a character doesn't make a password long
+    assert_eq!(password_strength("aaaaaaaaaaaaaaaaaaaa").score, 0);
+    assert!(password_strength("c0rrect-H0rse-battery-Staple").score >= 3);
+    assert!(password_strength("Tr0ub4dor&3").bits > password_strength("troubador").bits);
+}
+
+#[test]
+fn test_check_secret_key_detects_mismatch() -> Result<()> {
+    let secret_key = [7u8; 32];
+    let mut key_pair = KeyPair {
+        public_key: ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key().to_bytes().to_vec(),
+        public_key_string: String::new(),
+        encrypted_secret_key: None,
+        encrypted_mnemonic: None,
+        metadata: Default::default(),
+        has_bip39_passphrase: false,
+        registered_with: Vec::new(),
+    };
+    key_pair.check_secret_key(&secret_key)?;
+
+    // Simulate a hand-edited store whose public key belongs to someone else
+    key_pair.public_key[0] ^= 0xff;
+    let err = key_pair.check_secret_key(&secret_key).unwrap_err();
+    assert!(err.to_string().contains("does not match"));
+    Ok(())
+}
+
+#[test]
+fn test_check_encrypted_secret_round_trips() -> Result<()> {
+    let secret_key = [7u8; 32];
+    let mut key_pair = KeyPair {
+        public_key: ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key().to_bytes().to_vec(),
+        public_key_string: String::new(),
+        encrypted_secret_key: None,
+        encrypted_mnemonic: None,
+        metadata: Default::default(),
+        has_bip39_passphrase: false,
+        registered_with: Vec::new(),
+    };
+    assert!(key_pair.check_encrypted_secret("pw").is_err());
+
+    key_pair.encrypted_secret_key = Some(encrypt_secret_key(&secret_key, "pw", KdfParams::pbkdf2())?);
+    key_pair.check_encrypted_secret("pw")?;
+    assert!(key_pair.check_encrypted_secret("other").is_err());
+
+    // A secret that decrypts fine but belongs to another public key is caught too
+    key_pair.encrypted_secret_key = Some(encrypt_secret_key(&[8u8; 32], "pw", KdfParams::pbkdf2())?);
+    let err = key_pair.check_encrypted_secret("pw").unwrap_err();
+    assert!(err.to_string().contains("does not match"));
+    Ok(())
+}
+
+#[test]
+fn test_kdf_defaults_and_legacy_entries() -> Result<()> {
+    // New secrets use Argon2id, and record it
+    let encrypted = encrypt_secret_key(&[1u8; 32], "pw", KdfParams::default())?;
+    assert!(matches!(encrypted.kdf, KdfParams::Argon2id { .. }));
+    assert_eq!(*decrypt_secret_key(&encrypted, "pw")?, vec![1u8; 32]);
+
+    // Entries written before the kdf field existed must still be read as PBKDF2
+    let legacy = encrypt_secret_key(&[2u8; 32], "pw", KdfParams::pbkdf2())?;
+    let mut json = serde_json::to_value(&legacy)?;
+    json.as_object_mut().unwrap().remove("kdf");
+    let legacy: soundness_layer::EncryptedSecretKey = serde_json::from_value(json)?;
+    assert_eq!(legacy.kdf, KdfParams::Pbkdf2 { iterations: 100_000 });
+    assert_eq!(*decrypt_secret_key(&legacy, "pw")?, vec![2u8; 32]);
+    Ok(())
+}
+
+#[test]
+fn test_secret_sources() -> Result<()> {
+    use soundness_layer::SecretSource;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    assert_eq!(SecretSource::from_str("prompt")?, SecretSource::Prompt);
+    assert_eq!(SecretSource::from_str("stdin")?, SecretSource::Stdin);
+    assert_eq!(SecretSource::from_str("env:PW")?, SecretSource::Env("PW".to_string()));
+    assert_eq!(SecretSource::from_str("file:/tmp/pw")?, SecretSource::File(PathBuf::from("/tmp/pw")));
+    assert_eq!(SecretSource::from_str("fd:3")?, SecretSource::Fd(3));
+    assert!(SecretSource::from_str("fd:three").is_err());
+    assert!(SecretSource::from_str("env:").is_err());
+    assert!(SecretSource::from_str("keychain").is_err());
+
+    std::env::set_var("SOUNDNESS_TEST_SECRET", "from env");
+    assert_eq!(SecretSource::Env("SOUNDNESS_TEST_SECRET".to_string()).read("")?, "from env");
+    assert!(SecretSource::Env("SOUNDNESS_TEST_UNSET".to_string()).read("").is_err());
+
+    // A single trailing newline is stripped, inner whitespace is kept
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().join("secret");
+    std::fs::write(&path, "from file \n")?;
+    assert_eq!(SecretSource::File(path.clone()).read("")?, "from file ");
+
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        std::fs::write(&path, "from fd\r\n")?;
+        let file = std::fs::File::open(&path)?;
+        assert_eq!(SecretSource::Fd(file.as_raw_fd()).read("")?, "from fd");
+        assert!(SecretSource::Fd(1).read("").is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_openssh_public_key_round_trip() -> Result<()> {
+    use soundness_layer::sshsig::{openssh_public_key, parse_openssh_public_key};
+
+    let public_key = ed25519_dalek::SigningKey::from_bytes(&[5u8; 32]).verifying_key();
+    let line = openssh_public_key(public_key.as_bytes());
+    assert!(line.starts_with("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI"));
+    assert_eq!(parse_openssh_public_key(&format!("{} me@example.com", line))?, public_key.as_bytes());
+
+    assert!(parse_openssh_public_key("ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQ").is_err());
+    assert!(parse_openssh_public_key("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA==").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_ssh_signature_round_trip() -> Result<()> {
+    use soundness_layer::sshsig::{armor, signed_data, SshSignature};
+
+    let secret_key = [6u8; 32];
+    let public_key = ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key();
+    let signature = sign_payload(&secret_key, &signed_data("git", b"commit object"))?;
+    let parsed = SshSignature::parse(&armor(public_key.as_bytes(), "git", &signature.to_bytes()))?;
+
+    assert_eq!(parsed.public_key, public_key.as_bytes());
+    assert_eq!(parsed.namespace, "git");
+    assert!(parsed.verify(b"commit object")?);
+    assert!(!parsed.verify(b"another object")?);
+    assert!(SshSignature::parse("-----BEGIN PGP SIGNATURE-----").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_ipns_record() -> Result<()> {
+    use soundness_layer::ipns::{ipns_name, IpnsRecord};
+    use std::time::Duration;
+
+    // The ed25519 example from the libp2p peer ID spec (12D3KooWBtg3aaRMjxwedh83aGiUkwSxDwUZkzuJcfaqUmo7R3pq)
+    let public_key = hex::decode("1ed1e8fae2c4a144b8be8fd4b47bf3d3b34b871c3cacf6010f0e42d474fce27e")?;
+    assert_eq!(ipns_name(&public_key), "k51qzi5uqu5dgy8qsq67hbz73jqkw87l3fgf4a91qb0d9b5173tir7n4vxk1oe");
+
+    // 2000-02-29, to get the leap day right
+    let record = IpnsRecord::new("/ipfs/bafyproof", 3, 951_782_400, Duration::from_secs(60));
+    assert_eq!(record.validity, "2000-02-29T00:00:00.000000000Z");
+    assert_eq!(record.ttl_ns, 60_000_000_000);
+    assert!(record.signed_data_v2().starts_with(b"ipns-signature:\xa5cTTL\x1b"));
+
+    let secret_key = [8u8; 32];
+    let verifying_key = ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key();
+    let signature_v1 = sign_payload(&secret_key, &record.signed_data_v1())?.to_bytes();
+    let signature_v2 = sign_payload(&secret_key, &record.signed_data_v2())?.to_bytes();
+    let entry = record.encode(&signature_v1, &signature_v2);
+    assert!(entry.starts_with(b"\x0a\x0f/ipfs/bafyproof\x12\x40"));
+    assert!(entry.ends_with(&record.data()));
+    let signature = ed25519_dalek::Signature::from_bytes(&signature_v2);
+    assert!(verifying_key.verify(&record.signed_data_v2(), &signature).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_key_store_envelope() -> Result<()> {
+    use soundness_layer::{is_encrypted_key_store, load_encrypted_key_store, save_encrypted_key_store};
+
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("keys.json");
+    let mut key_store = KeyStore::default();
+    key_store.keys.insert(
+        "hidden".to_string(),
+        serde_json::from_str(r#"{"public_key":[1,2,3],"public_key_string":"AQID"}"#)?,
+    );
+
+    save_encrypted_key_store(&key_store, &key_store_path, "store password")?;
+    assert!(is_encrypted_key_store(&key_store_path)?);
+    assert!(!std::fs::read_to_string(&key_store_path)?.contains("hidden"));
+    assert!(load_key_store(&key_store_path).is_err());
+    assert!(load_encrypted_key_store(&key_store_path, "wrong").is_err());
+
+    let loaded = load_encrypted_key_store(&key_store_path, "store password")?;
+    assert_eq!(loaded.keys["hidden"].public_key_string, "AQID");
+    Ok(())
+}
+
+#[test]
+fn test_key_store_backup() -> Result<()> {
+    use soundness_layer::{decrypt_key_store_backup, encrypt_key_store_backup};
+
+    let mut key_store = KeyStore::default();
+    key_store.keys.insert(
+        "hidden".to_string(),
+        serde_json::from_str(r#"{"public_key":[1,2,3],"public_key_string":"AQID"}"#)?,
+    );
+
+    let backup = encrypt_key_store_backup(&key_store, "backup password")?;
+    assert!(backup.starts_with(b"SNDBKUP1"));
+    assert!(!String::from_utf8_lossy(&backup).contains("hidden"));
+    assert!(decrypt_key_store_backup(&backup, "wrong").is_err());
+    assert!(decrypt_key_store_backup(&backup[..40], "backup password").is_err());
+    assert!(decrypt_key_store_backup(b"{\"keys\":{}}", "backup password").is_err());
+
+    let restored = decrypt_key_store_backup(&backup, "backup password")?;
+    assert_eq!(restored.keys["hidden"].public_key_string, "AQID");
+    Ok(())
+}
+
+#[test]
+fn strict_base64_rejects_non_canonical_input() {
+    let encoding = KeyEncoding::Base64;
+    assert_eq!(encoding.decode_strict("AAEC").unwrap(), vec![0, 1, 2]);
+    assert_eq!(encoding.decode(" AAEC\n").unwrap(), vec![0, 1, 2]);
+
+    let err = encoding.decode_strict(" AAEC").unwrap_err().to_string();
+    assert!(err.contains("bad character ' ' at position 0"), "{}", err);
+    let err = encoding.decode_strict("AA-C").unwrap_err().to_string();
+    assert!(err.contains("at position 2"), "{}", err);
+    let err = encoding.decode_strict("AAE").unwrap_err().to_string();
+    assert!(err.contains("length 3"), "{}", err);
+    let err = encoding.decode_strict("A=AA").unwrap_err().to_string();
+    assert!(err.contains("at position 1"), "{}", err);
+    // Non-zero trailing bits decode leniently elsewhere but are not canonical
+    assert!(encoding.decode_strict("AAF=").is_err());
+}
+
+#[test]
+fn test_tee_quote_from_gramine_files() -> Result<()> {
+    use soundness_layer::tee::{generate_quote_under, report_data, TeePlatform};
+
+    let report_data = report_data(&[9u8; 32]);
+    assert_eq!(report_data[..32], [9u8; 32]);
+    assert_eq!(report_data[32..], [0u8; 32]);
+
+    // Gramine's pseudo-files, as plain files
+    let temp_dir = tempdir()?;
+    let attestation = temp_dir.path().join("dev/attestation");
+    std::fs::create_dir_all(&attestation)?;
+    std::fs::write(attestation.join("attestation_type"), "dcap")?;
+    std::fs::write(attestation.join("quote"), b"sgx quote")?;
+    assert_eq!(generate_quote_under(temp_dir.path(), TeePlatform::Sgx, &report_data)?, b"sgx quote");
+    assert_eq!(std::fs::read(attestation.join("user_report_data"))?, report_data);
+
+    std::fs::write(attestation.join("attestation_type"), "none")?;
+    assert!(generate_quote_under(temp_dir.path(), TeePlatform::Sgx, &report_data).is_err());
+    let err = generate_quote_under(temp_dir.path(), TeePlatform::Tdx, &report_data).unwrap_err();
+    assert!(err.to_string().contains("configfs-tsm"));
+    Ok(())
+}
+
+#[test]
+fn test_ndjson_key_store_loads_single_keys() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("keys.ndjson");
+    assert_eq!(KeyStoreFormat::from_path(&key_store_path), KeyStoreFormat::Ndjson);
+
+    let mut key_store = KeyStore::default();
+    for (index, name) in ["alice", "bob", "carol"].into_iter().enumerate() {
+        let public_key = ed25519_dalek::SigningKey::from_bytes(&[index as u8; 32]).verifying_key().to_bytes();
+        let public_key_string = key_store.encode_public_key(&public_key, KeyEncoding::Hex);
+        key_store.keys.insert(
+            name.to_string(),
+            KeyPair {
+                public_key: public_key.to_vec(),
+                public_key_string,
+                encrypted_secret_key: None,
+                encrypted_mnemonic: None,
+                metadata: Default::default(),
+                has_bip39_passphrase: false,
+                registered_with: Vec::new(),
+            },
+        );
+    }
+    save_key_store_as(&key_store, &key_store_path, KeyStoreFormat::Ndjson)?;
+
+    let contents = std::fs::read_to_string(&key_store_path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], r#"{"display_encoding":"hex"}"#);
+    assert!(lines[1].starts_with(r#"{"name":"alice","#));
+    let loaded = load_key_store_as(&key_store_path, KeyStoreFormat::Ndjson)?;
+    assert_eq!(loaded.keys.len(), 3);
+    assert_eq!(loaded.display_encoding, Some(KeyEncoding::Hex));
+    assert_eq!(loaded.keys["bob"].public_key, key_store.keys["bob"].public_key);
+
+    // Only the requested key's line is parsed, so a broken line elsewhere doesn't get in the way
+    std::fs::write(&key_store_path, contents.replace(lines[3], r#"{"name":"carol","public_key":"#))?;
+    let bob = load_ndjson_key_pair(&key_store_path, "bob")?.unwrap();
+    assert_eq!(bob.public_key_string, key_store.keys["bob"].public_key_string);
+    assert!(load_ndjson_key_pair(&key_store_path, "dave")?.is_none());
+    assert!(load_ndjson_key_pair(&key_store_path, "carol").is_err());
+
+    save_key_store(&key_store, &key_store_path)?;
+    assert!(load_ndjson_key_pair(&key_store_path, "bob").is_err());
+    Ok(())
+}
+
+#[cfg(feature = "json5")]
+#[test]
+fn test_json5_key_store_keeps_comments() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_store_path = temp_dir.path().join("keys.json5");
+    assert_eq!(KeyStoreFormat::from_path(&key_store_path), KeyStoreFormat::Json5);
+
+    let public_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]).verifying_key().to_bytes();
+    std::fs::write(
+        &key_store_path,
+        format!(
+            "// team keys\n{{\n  keys: {{\n    // production signing key - expires 2025-01-01\n    \
+             'prod': {{ public_key: {:?}, public_key_string: '', }}, // rotated yearly\n  }},\n}}\n",
+            public_key
+        ),
+    )?;
+
+    let mut key_store = load_key_store_as(&key_store_path, KeyStoreFormat::Json5)?;
+    assert_eq!(key_store.keys["prod"].public_key, public_key);
+    key_store.keys.insert("staging".to_string(), key_store.keys["prod"].clone());
+    save_key_store_as(&key_store, &key_store_path, KeyStoreFormat::Json5)?;
+
+    let saved = std::fs::read_to_string(&key_store_path)?;
+    assert!(saved.starts_with("// team keys\n{"));
+    assert!(saved.contains("    // production signing key - expires 2025-01-01\n    \"prod\": {"));
+    assert!(saved.contains("}, // rotated yearly\n"));
+    assert_eq!(load_key_store_as(&key_store_path, KeyStoreFormat::Json5)?.keys.len(), 2);
+    Ok(())
+}
@@ -10,7 +10,7 @@ sleep 2
 
 # Run the tests
 cd ../soundness-cli
-cargo test --test e2e_test -- --nocapture
+cargo test --test e2e_test -- --ignored --nocapture
 
 # Kill the server
 kill $SERVER_PID 
\ No newline at end of file
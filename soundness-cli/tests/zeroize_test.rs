@@ -0,0 +1,61 @@
+// Reading a buffer after it is freed is undefined behaviour, so this test binary installs an
+// allocator that looks at a watched buffer in `dealloc`, just before handing it back
+use anyhow::Result;
+use soundness_layer::{decrypt_secret_key, encrypt_secret_key, KdfParams, Zeroizing};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+static WATCHED: AtomicUsize = AtomicUsize::new(0);
+// 0: the watched buffer wasn't freed, 1: it was all zeros when freed, 2: it still held data
+static FREED: AtomicU8 = AtomicU8::new(0);
+
+struct WatchingAllocator;
+
+unsafe impl GlobalAlloc for WatchingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Only the first free of the watched address counts, later ones are other allocations
+        if WATCHED.compare_exchange(ptr as usize, 0, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            // SAFETY: `ptr` is a live allocation of `layout.size()` bytes until System.dealloc below
+            let bytes = std::slice::from_raw_parts(ptr, layout.size());
+            FREED.store(if bytes.iter().all(|b| *b == 0) { 1 } else { 2 }, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: WatchingAllocator = WatchingAllocator;
+
+// Drop `value`, whose heap buffer starts at `ptr`, and report whether that buffer was zeroed first
+fn zeroed_when_dropped<T>(value: T, ptr: *const u8) -> bool {
+    FREED.store(0, Ordering::SeqCst);
+    WATCHED.store(ptr as usize, Ordering::SeqCst);
+    drop(value);
+    match FREED.load(Ordering::SeqCst) {
+        0 => panic!("the buffer was not freed"),
+        state => state == 1,
+    }
+}
+
+#[test]
+fn test_secrets_are_zeroed_on_drop() -> Result<()> {
+    // Without Zeroizing the bytes are still there when the memory goes back to the allocator
+    let plain = vec![0xABu8; 32];
+    let ptr = plain.as_ptr();
+    assert!(!zeroed_when_dropped(plain, ptr));
+
+    let encrypted = encrypt_secret_key(&[0xAB; 32], "pw", KdfParams::pbkdf2())?;
+    let secret_key = decrypt_secret_key(&encrypted, "pw")?;
+    assert_eq!(*secret_key, vec![0xAB; 32]);
+    let ptr = secret_key.as_ptr();
+    assert!(zeroed_when_dropped(secret_key, ptr));
+
+    let password = Zeroizing::new(String::from("hunter2"));
+    let ptr = password.as_ptr();
+    assert!(zeroed_when_dropped(password, ptr));
+    Ok(())
+}